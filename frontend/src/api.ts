@@ -1,4 +1,4 @@
-import type { MotorControllerConfig, PausedControlPayload, MotorState } from './types'
+import type { MotorControllerConfig, PausedControlPayload, MotorState, ReadyStatus } from './types'
 
 const api_base = window.location.hostname === 'localhost' ? 'http://ossm.lan' : document.location.href;
 
@@ -29,13 +29,19 @@ export async function getConfig(): Promise<MotorControllerConfig> {
 }
 
 export async function setConfig(config: MotorControllerConfig): Promise<MotorControllerConfig> {
-  return postJson<MotorControllerConfig>('/config', config)
+  // 4 decimals is far finer than the motor can resolve and keeps big splines small on the wire
+  const spline_points = config.spline_points.map(p => Math.round(p * 10000) / 10000)
+  return postJson<MotorControllerConfig>('/config', { ...config, spline_points })
 }
 
 export async function setPaused(payload: PausedControlPayload): Promise<MotorControllerConfig> {
   return postJson<MotorControllerConfig>('/paused', payload)
 }
 
+export async function getReady(): Promise<ReadyStatus> {
+  return getJson<ReadyStatus>('/ready')
+}
+
 export async function getState(): Promise<MotorState> {
   return getJson<MotorState>('/state')
 }
@@ -7,9 +7,35 @@ export interface MotorControllerConfig {
   reversed: boolean
   wave_func: WaveFunc
   sharpness: number
+  sharpness_speed: number
   spline_points: number[]
   paused: boolean
   paused_position: number
+  dwell_top: number
+  dwell_bottom: number
+  up_speed_ratio: number
+  down_speed_ratio: number
+  tease_probability: number
+  tease_intensity: number
+  escalation_step: number
+  escalation_max: number
+  escalation_reset: boolean
+  output_smoothing: number
+  reversal_smoothing: number
+  end_deadzone: number
+  crossfade_time: number
+  idle_park_timeout: number
+  idle_park_position: number
+  power_save_on_pause: boolean
+  demo_mode: boolean
+  resync_on_unpause: boolean
+  warmup_on_unpause: boolean
+  acceleration: number
+}
+
+export interface ReadyStatus {
+  ready: boolean
+  phase: 'booting' | 'no_motor' | 'homing' | 'ready' | 'failed' | 'safe_mode' | 'stopped'
 }
 
 export interface PausedControlPayload {
@@ -26,4 +52,6 @@ export interface MotorState {
   shaped_y: number
   position: number
   speed: number
+  position_mm: number | null
+  stroke_mm: number | null
 }
@@ -0,0 +1,58 @@
+// The firmware modules that don't touch the hardware, built for the computer running the tests.
+// The firmware itself runs no tests under `cargo test` (harness = false, and the ESP target has
+// no test crate), so their unit tests run from here:
+//
+//     cd host-tests && cargo +stable test --target x86_64-unknown-linux-gnu
+//
+// --target overrides the ESP target from ../.cargo/config.toml, and stable Cargo ignores its
+// build-std setting.
+#![allow(dead_code)]
+
+#[path = "../../src/debounce.rs"]
+mod debounce;
+#[path = "../../src/error.rs"]
+mod error;
+#[path = "../../src/motion.rs"]
+mod motion;
+#[path = "../../src/motor.rs"]
+mod motor;
+#[path = "../../src/pattern.rs"]
+mod pattern;
+#[path = "../../src/units.rs"]
+mod units;
+
+// Stand-ins for what the modules above use from ESP-IDF and from the modules that need it
+extern crate self as esp_idf_svc;
+
+pub mod sys {
+    #[derive(Debug)]
+    pub struct EspError(i32);
+
+    impl EspError {
+        pub fn code(&self) -> i32 {
+            self.0
+        }
+    }
+
+    impl std::fmt::Display for EspError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "ESP error {}", self.0)
+        }
+    }
+
+    pub const ESP_ERR_TIMEOUT: i32 = 0x107;
+}
+
+mod clock {
+    pub fn timestamp() -> (f64, bool) {
+        (0.0, false)
+    }
+}
+
+mod limit_switch {
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum LimitEnd {
+        Min,
+        Max,
+    }
+}
@@ -0,0 +1,161 @@
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use esp_idf_svc::hal::delay::FreeRtos;
+use esp_idf_svc::hal::gpio::{AnyIOPin, PinDriver, Pull};
+use esp_idf_svc::sys::{self, esp};
+use serde::Serialize;
+
+use crate::context::AppContext;
+
+// Shorting this pin to GND at power-on starts in safe mode
+pub const SAFE_MODE_JUMPER_PIN: usize = 3;
+// Reboots in a row that count as a crash loop
+pub const CRASH_LOOP_THRESHOLD: u8 = 3;
+// Uptime after which the firmware is considered to run stable and the crash counter is cleared
+pub const STABLE_UPTIME_SECS: u64 = 120;
+
+// Why the chip last reset, from esp_reset_reason()
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResetReason {
+    PowerOn,
+    External,           // reset pin
+    Software,           // esp_restart(), e.g. after a config change
+    Panic,
+    InterruptWatchdog,
+    TaskWatchdog,
+    Watchdog,           // other watchdogs
+    DeepSleep,
+    Brownout,           // supply voltage dropped too low
+    Sdio,
+    Usb,                // USB peripheral, e.g. when flashing
+    Jtag,
+    Efuse,
+    PowerGlitch,
+    CpuLockup,
+    Unknown,
+}
+
+impl ResetReason {
+    pub fn read() -> Self {
+        match unsafe { sys::esp_reset_reason() } {
+            sys::esp_reset_reason_t_ESP_RST_POWERON => ResetReason::PowerOn,
+            sys::esp_reset_reason_t_ESP_RST_EXT => ResetReason::External,
+            sys::esp_reset_reason_t_ESP_RST_SW => ResetReason::Software,
+            sys::esp_reset_reason_t_ESP_RST_PANIC => ResetReason::Panic,
+            sys::esp_reset_reason_t_ESP_RST_INT_WDT => ResetReason::InterruptWatchdog,
+            sys::esp_reset_reason_t_ESP_RST_TASK_WDT => ResetReason::TaskWatchdog,
+            sys::esp_reset_reason_t_ESP_RST_WDT => ResetReason::Watchdog,
+            sys::esp_reset_reason_t_ESP_RST_DEEPSLEEP => ResetReason::DeepSleep,
+            sys::esp_reset_reason_t_ESP_RST_BROWNOUT => ResetReason::Brownout,
+            sys::esp_reset_reason_t_ESP_RST_SDIO => ResetReason::Sdio,
+            sys::esp_reset_reason_t_ESP_RST_USB => ResetReason::Usb,
+            sys::esp_reset_reason_t_ESP_RST_JTAG => ResetReason::Jtag,
+            sys::esp_reset_reason_t_ESP_RST_EFUSE => ResetReason::Efuse,
+            sys::esp_reset_reason_t_ESP_RST_PWR_GLITCH => ResetReason::PowerGlitch,
+            sys::esp_reset_reason_t_ESP_RST_CPU_LOCKUP => ResetReason::CpuLockup,
+            _ => ResetReason::Unknown,
+        }
+    }
+
+    // A panic or a watchdog, as opposed to power-on or a requested restart
+    pub fn is_crash(&self) -> bool {
+        matches!(
+            self,
+            ResetReason::Panic
+                | ResetReason::InterruptWatchdog
+                | ResetReason::TaskWatchdog
+                | ResetReason::Watchdog
+                | ResetReason::CpuLockup
+        )
+    }
+}
+
+// Writes what the motor loop hasn't saved yet: config changes reach NVS up to 200 ms late, and
+// paused position moves only once they settle. An open transaction isn't committed, and a jog
+// alone stays unsaved as always.
+pub fn flush_pending(app_context: &AppContext) {
+    flush(app_context, true);
+}
+
+// Without `wait` it gives up instead of waiting for a lock. On the way down the thread holding
+// one may be the one that panicked, and it is never going to let go.
+fn flush(app_context: &AppContext, wait: bool) {
+    let Some(mut mc_opt) = acquire(&app_context.motor_controller, wait) else {
+        log::error!("Motor controller busy, the pending config change is lost");
+        return;
+    };
+    let Some((version, config)) = mc_opt.as_mut().and_then(|mc| mc.unsaved_config(true)) else {
+        return;
+    };
+    let Some(mut storage_manager) = acquire(&app_context.storage_manager, wait) else {
+        log::error!("Storage busy, the pending config change is lost");
+        return;
+    };
+    match storage_manager.set_motor_config(&config) {
+        Ok(()) => {
+            log::info!("Saved the pending config change");
+            if let Some(mc) = mc_opt.as_mut() {
+                mc.mark_saved(version);
+            }
+        }
+        Err(e) => log::error!("Failed to save the pending config change: {}", e),
+    }
+}
+
+fn acquire<T>(mutex: &Mutex<T>, wait: bool) -> Option<MutexGuard<'_, T>> {
+    if wait {
+        Some(mutex.lock().unwrap())
+    } else {
+        mutex.try_lock().ok()
+    }
+}
+
+static FLUSH_CONTEXT: OnceLock<AppContext> = OnceLock::new();
+
+extern "C" fn flush_on_shutdown() {
+    if let Some(app_context) = FLUSH_CONTEXT.get() {
+        flush(app_context, false);
+    }
+}
+
+// Flushes on the other ways down as well: a panic, and any esp_restart() that doesn't come
+// through restart(). Watchdog resets, brownouts and power loss give no warning, a change from
+// the last 200 ms is lost then. Call once, early.
+pub fn flush_on_exit(app_context: &AppContext) {
+    if FLUSH_CONTEXT.set(app_context.clone()).is_err() {
+        return;
+    }
+    let report = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        report(info);
+        flush_on_shutdown();
+    }));
+    if let Err(e) = esp!(unsafe { sys::esp_register_shutdown_handler(Some(flush_on_shutdown)) }) {
+        log::error!("Failed to register the shutdown flush: {}", e);
+    }
+}
+
+// Every requested restart goes through here, so an acknowledged change isn't lost. Don't hold
+// the storage or motor controller lock when calling it.
+pub fn restart(app_context: &AppContext) -> ! {
+    flush_pending(app_context);
+    FreeRtos::delay_ms(100);    // Lets the log get out
+    esp_idf_svc::hal::reset::restart()
+}
+
+// Reads the safe mode jumper. The pin is only borrowed, it stays available for other uses.
+pub fn safe_mode_jumper_set(all_pins: &mut [Option<AnyIOPin>]) -> bool {
+    let Some(pin) = all_pins.get_mut(SAFE_MODE_JUMPER_PIN).and_then(|p| p.as_mut()) else {
+        return false;
+    };
+    let Ok(mut driver) = PinDriver::input(pin) else {
+        return false;
+    };
+    if driver.set_pull(Pull::Up).is_err() {
+        return false;
+    }
+    // Let the pull-up charge the pin before reading it
+    FreeRtos::delay_ms(1);
+    driver.is_low()
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use esp_idf_svc::hal::ledc::config::TimerConfig;
+use esp_idf_svc::hal::ledc::{LedcDriver, LedcTimerDriver, CHANNEL0, TIMER0};
+use esp_idf_svc::hal::prelude::*;
+
+use crate::context::AppContext;
+use crate::pins::PinLease;
+use crate::status::InitState;
+use crate::storage::BuzzerConfiguration;
+
+const POLL_INTERVAL_MS: u64 = 50;
+const BUZZER_TASK_STACK_SIZE: usize = 4096;
+const TONE_FREQUENCY_HZ: u32 = 2700;    // Around the resonance of common piezo discs
+
+// Events worth a beep, picked up from the shared state
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BuzzerEvent {
+    HomingComplete,
+    Fault,
+    PatternFinished,
+}
+
+impl BuzzerEvent {
+    // Beeps as (on, off) times in ms
+    fn beeps(&self) -> &'static [(u64, u64)] {
+        match self {
+            BuzzerEvent::HomingComplete => &[(80, 80), (80, 0)],
+            BuzzerEvent::Fault => &[(400, 200), (400, 200), (400, 0)],
+            BuzzerEvent::PatternFinished => &[(600, 0)],
+        }
+    }
+}
+
+// What the events are detected from
+#[derive(Clone, Copy, PartialEq)]
+struct Snapshot {
+    init_state: InitState,
+    fault: bool,
+    patterns_finished: u32,
+}
+
+impl Snapshot {
+    fn take(app_context: &AppContext) -> Self {
+        let (init_state, fault) = {
+            let status = app_context.status.lock().unwrap();
+            (status.init_state, status.motor_fault.is_some())
+        };
+        let patterns_finished = app_context.motor_controller.lock().unwrap().as_ref().map_or(0, |mc| mc.patterns_finished());
+        Self { init_state, fault, patterns_finished }
+    }
+
+    fn event_since(&self, previous: &Snapshot) -> Option<BuzzerEvent> {
+        if (self.fault && !previous.fault) || (self.init_state == InitState::Failed && previous.init_state != InitState::Failed) {
+            Some(BuzzerEvent::Fault)
+        } else if self.init_state == InitState::Ready && previous.init_state == InitState::Homing {
+            Some(BuzzerEvent::HomingComplete)
+        } else if self.patterns_finished > previous.patterns_finished {    // A new controller counts from zero
+            Some(BuzzerEvent::PatternFinished)
+        } else {
+            None
+        }
+    }
+}
+
+// Starts a task that beeps on events. Beeping blocks only this task, never the motor loop.
+pub fn start(app_context: AppContext, timer: TIMER0, channel: CHANNEL0, config: &BuzzerConfiguration) -> anyhow::Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    let Some((pin_lease, [pin])) = PinLease::take(&app_context.all_pins, [config.pin as usize]) else {
+        anyhow::bail!("GPIO{} is not available for the buzzer", config.pin);
+    };
+    let timer = LedcTimerDriver::new(timer, &TimerConfig::new().frequency(TONE_FREQUENCY_HZ.Hz()))?;
+    let mut driver = LedcDriver::new(channel, timer, pin)?;
+    driver.set_duty(0)?;
+    let on_duty = driver.get_max_duty() / 2;
+    log::info!("Buzzer on GPIO{}", config.pin);
+
+    std::thread::Builder::new()
+        .stack_size(BUZZER_TASK_STACK_SIZE)
+        .spawn(move || {
+            let _pin_lease = pin_lease;
+            let mut previous = Snapshot::take(&app_context);
+            loop {
+                std::thread::sleep(Duration::from_millis(POLL_INTERVAL_MS));
+                let current = Snapshot::take(&app_context);
+                if current == previous {
+                    continue;
+                }
+                let event = current.event_since(&previous);
+                previous = current;
+                let Some(event) = event else {
+                    continue;
+                };
+                log::info!("Beep: {:?}", event);
+                for &(on, off) in event.beeps() {
+                    if let Err(e) = driver.set_duty(on_duty) {
+                        log::error!("Failed to drive the buzzer: {}", e);
+                        break;
+                    }
+                    std::thread::sleep(Duration::from_millis(on));
+                    let _ = driver.set_duty(0);
+                    std::thread::sleep(Duration::from_millis(off));
+                }
+            }
+        })?;
+    Ok(())
+}
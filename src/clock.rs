@@ -0,0 +1,46 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use esp_idf_svc::sntp::{EspSntp, SntpConf};
+use esp_idf_svc::sys;
+
+use crate::storage::TimeConfiguration;
+
+// Before SNTP sets it the clock counts from 1970 at boot, anything later than this was synced
+const SYNCED_AFTER_UNIX_SECS: u64 = 1_700_000_000;
+
+pub fn uptime_secs() -> f64 {
+    (unsafe { sys::esp_timer_get_time() }) as f64 / 1_000_000.0
+}
+
+// Unix time in seconds once SNTP has set the clock
+pub fn wall_clock() -> Option<f64> {
+    let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    (since_epoch.as_secs() > SYNCED_AFTER_UNIX_SECS).then(|| since_epoch.as_secs_f64())
+}
+
+// Unix time when known, otherwise seconds since boot. The flag tells which one it is.
+pub fn timestamp() -> (f64, bool) {
+    match wall_clock() {
+        Some(t) => (t, true),
+        None => (uptime_secs(), false),
+    }
+}
+
+// Sets the timezone for local time in the logs and starts syncing the clock in the background.
+// Keep the returned handle alive, dropping it stops SNTP.
+pub fn start_sntp(config: &TimeConfiguration) -> anyhow::Result<Option<EspSntp<'static>>> {
+    std::env::set_var("TZ", &config.timezone);
+    unsafe { sys::tzset() };
+    if config.ntp_server.is_empty() {
+        return Ok(None);
+    }
+    let mut sntp_conf = SntpConf::default();
+    sntp_conf.servers[0] = &config.ntp_server;
+    let sntp = EspSntp::new(&sntp_conf)?;
+    log::info!(
+        "SNTP started with {}, timezone {}",
+        config.ntp_server,
+        config.timezone
+    );
+    Ok(Some(sntp))
+}
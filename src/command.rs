@@ -1,9 +1,22 @@
 use std::io::{self, BufRead};
+use std::sync::atomic::Ordering;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use esp_idf_svc::hal::delay::FreeRtos;
-use crate::storage::StorageManager;
-use crate::motion::MotorControllerConfig;
+use crate::storage::{self, StorageManager};
+use crate::motion::{self, MotorControllerConfig};
+use crate::pattern;
+use crate::pins;
+use crate::schedule::Schedule;
+use crate::boot;
 use crate::context::AppContext;
+use crate::status::InitState;
+use crate::units::Units;
+
+// Homing has to finish before the loop sees a stop request
+const LOOP_STOP_TIMEOUT: Duration = Duration::from_secs(60);
+// Their arguments are credentials, kept out of the log
+const SECRET_COMMANDS: &[&str] = &["set_wifi_password", "set_mqtt_credentials"];
 
 pub fn handle_stdin_command(app_context: AppContext) {
     let stdin = io::stdin();
@@ -30,295 +43,1790 @@ pub fn handle_stdin_command(app_context: AppContext) {
             }
         }
 
-        let cmdline = cmdline.trim();
+        run_command(&app_context, cmdline.trim());
+    }
+}
 
-        log::info!("Command: {}", cmdline);
+// Runs one command line, from the serial console or a remote interface
+pub fn run_command(app_context: &AppContext, cmdline: &str) {
+    // parse and execute command
+    let parts = cmdline.splitn(2, ' ').collect::<Vec<&str>>();
+    let command = parts[0];
+    let args = if parts.len() > 1 { parts[1] } else { "" };
 
-        // parse and execute command
-        let parts = cmdline.splitn(2, ' ').collect::<Vec<&str>>();
-        let command = parts[0];
-        let args = if parts.len() > 1 { parts[1] } else { "" };
+    if SECRET_COMMANDS.contains(&command) && !args.is_empty() {
+        log::info!("Command: {} ***", command);
+    } else {
+        log::info!("Command: {}", cmdline);
+    }
 
-        match command {
-            "set_wifi_ssid" => {
-                app_context.storage_manager.lock().unwrap().set_ssid(args).unwrap();
-                log::info!("SSID saved: {}, restart to apply", args);
-            } ,
-            "set_wifi_password" => {
-                app_context.storage_manager.lock().unwrap().set_password(args).unwrap();
-                log::info!("Password saved: {}, restart to apply", args);
-            } ,
-            "set_pin_modbus_tx" => {
-                match args.parse::<u32>() {
-                    Ok(pin) => {
+    match command {
+        "set_wifi_ssid" => {
+            app_context.storage_manager.lock().unwrap().set_ssid(args).unwrap();
+            log::info!("SSID saved: {}, restart to apply", args);
+        } ,
+        "set_wifi_password" => {
+            app_context.storage_manager.lock().unwrap().set_password(args).unwrap();
+            log::info!("Password saved, restart to apply");
+        } ,
+        "set_pin_modbus_tx" => {
+            let (pin, live) = parse_pin_args(args);
+            match pin {
+                Some(pin) => {
+                    let result = {
                         let mut sm = app_context.storage_manager.lock().unwrap();
                         let mut config = sm.get_pin_configuration().unwrap_or_default();
                         config.modbus_tx = pin;
-                        sm.set_pin_configuration(&config).unwrap();
-                        log::info!("Modbus TX pin set to {}, restart to apply", pin);
+                        sm.set_pin_configuration(&config)
+                    };
+                    match result {
+                        Ok(()) => {
+                            if let Some(warning) = pins::strapping_warning(pin) {
+                                log::warn!("{}", warning);
+                            }
+                            if live {
+                                log::info!("Modbus TX pin set to {}, restarting the motor loop to apply", pin);
+                                restart_motor_loop(app_context);
+                            } else {
+                                log::info!("Modbus TX pin set to {}, restart to apply", pin);
+                            }
+                        }
+                        Err(e) => log::error!("Failed to set Modbus TX pin: {}", e),
                     }
-                    Err(_) => log::error!("Invalid pin value: {}", args),
                 }
-            },
-            "set_pin_modbus_rx" => {
-                match args.parse::<u32>() {
-                    Ok(pin) => {
+                None => log::error!("Invalid pin value: {}", args),
+            }
+        },
+        "set_pin_modbus_rx" => {
+            let (pin, live) = parse_pin_args(args);
+            match pin {
+                Some(pin) => {
+                    let result = {
                         let mut sm = app_context.storage_manager.lock().unwrap();
                         let mut config = sm.get_pin_configuration().unwrap_or_default();
                         config.modbus_rx = pin;
-                        sm.set_pin_configuration(&config).unwrap();
-                        log::info!("Modbus RX pin set to {}, restart to apply", pin);
+                        sm.set_pin_configuration(&config)
+                    };
+                    match result {
+                        Ok(()) => {
+                            if live {
+                                log::info!("Modbus RX pin set to {}, restarting the motor loop to apply", pin);
+                                restart_motor_loop(app_context);
+                            } else {
+                                log::info!("Modbus RX pin set to {}, restart to apply", pin);
+                            }
+                        }
+                        Err(e) => log::error!("Failed to set Modbus RX pin: {}", e),
                     }
-                    Err(_) => log::error!("Invalid pin value: {}", args),
                 }
-            },
-            "set_pin_modbus_de_re" => {
-                match args.parse::<u32>() {
-                    Ok(pin) => {
+                None => log::error!("Invalid pin value: {}", args),
+            }
+        },
+        "set_pin_modbus_de_re" => {
+            let (pin, live) = parse_pin_args(args);
+            match pin {
+                Some(pin) => {
+                    let result = {
                         let mut sm = app_context.storage_manager.lock().unwrap();
                         let mut config = sm.get_pin_configuration().unwrap_or_default();
                         config.modbus_de_re = pin;
-                        sm.set_pin_configuration(&config).unwrap();
-                        log::info!("Modbus DE/RE pin set to {}, restart to apply", pin);
-                    }
-                    Err(_) => log::error!("Invalid pin value: {}", args),
-                }
-            },
-            "get_pin_configuration" => {
-                match app_context.storage_manager.lock().unwrap().get_pin_configuration() {
-                    Ok(config) => {
-                        let json = serde_json::to_string_pretty(&config).unwrap();
-                        println!("{}", json);
-                    }
-                    Err(e) => {
-                        log::error!("Failed to get pin config: {}", e);
-                    }
-                }
-            },
-            "set_motor_config" => {
-                match serde_json::from_str::<MotorControllerConfig>(args) {
-                    Ok(config) => {
-                        let mut mc_opt = app_context.motor_controller.lock().unwrap();
-                        if let Some(mc) = mc_opt.as_mut() {
-                            mc.set_config(config).unwrap();
-                            log::info!("Motor config updated");
-                        } else {
-                            log::error!("Motor controller not initialized");
+                        sm.set_pin_configuration(&config)
+                    };
+                    match result {
+                        Ok(()) => {
+                            if let Some(warning) = pins::strapping_warning(pin) {
+                                log::warn!("{}", warning);
+                            }
+                            if live {
+                                log::info!("Modbus DE/RE pin set to {}, restarting the motor loop to apply", pin);
+                                restart_motor_loop(app_context);
+                            } else {
+                                log::info!("Modbus DE/RE pin set to {}, restart to apply", pin);
+                            }
                         }
+                        Err(e) => log::error!("Failed to set Modbus DE/RE pin: {}", e),
                     }
-                    Err(e) => {
-                        log::error!("Failed to parse motor config: {}", e);
+                }
+                None => log::error!("Invalid pin value: {}", args),
+            }
+        },
+        "set_status_led" => {
+            let led = |kind, pin: &str, active_low| {
+                pin.parse::<u32>().ok().map(|pin| storage::LedConfiguration { kind, pin, active_low })
+            };
+            let parts: Vec<&str> = args.split_whitespace().collect();
+            let config = match parts.as_slice() {
+                ["off"] => Some(storage::LedConfiguration { kind: storage::LedKind::None, ..Default::default() }),
+                ["gpio", pin] => led(storage::LedKind::Gpio, pin, false),
+                ["gpio", pin, "active_low"] => led(storage::LedKind::Gpio, pin, true),
+                ["ws2812", pin] => led(storage::LedKind::Ws2812, pin, false),
+                _ => None,
+            };
+            match config {
+                Some(config) => {
+                    if let Err(e) = app_context.storage_manager.lock().unwrap().set_led_configuration(&config) {
+                        log::error!("Failed to set status LED: {}", e);
+                    } else {
+                        log::info!("Status LED set to {}, restart to apply", args);
                     }
                 }
-            } ,
-            "get_motor_config" => {
-                let mut mc_opt = app_context.motor_controller.lock().unwrap();
-                if let Some(mc) = mc_opt.as_mut() {
-                    let config = mc.get_config();
+                None => log::error!("Invalid status LED: {}. Use off, gpio <pin> [active_low] or ws2812 <pin>", args),
+            }
+        },
+        "set_buzzer" => {
+            let config = match args {
+                "off" => Some(storage::BuzzerConfiguration { enabled: false, ..Default::default() }),
+                pin => pin.parse::<u32>().ok().map(|pin| storage::BuzzerConfiguration { enabled: true, pin }),
+            };
+            match config {
+                Some(config) => {
+                    if let Err(e) = app_context.storage_manager.lock().unwrap().set_buzzer_configuration(&config) {
+                        log::error!("Failed to set buzzer: {}", e);
+                    } else {
+                        log::info!("Buzzer set to {}, restart to apply", args);
+                    }
+                }
+                None => log::error!("Invalid buzzer pin: {}. Use a pin number or off", args),
+            }
+        },
+        "set_interlock" => {
+            let interlock = |pin: &str, active_low| {
+                pin.parse::<u32>().ok().map(|pin| storage::InterlockConfiguration { enabled: true, pin, active_low })
+            };
+            let parts: Vec<&str> = args.split_whitespace().collect();
+            let config = match parts.as_slice() {
+                ["off"] => Some(storage::InterlockConfiguration { enabled: false, ..Default::default() }),
+                [pin] => interlock(pin, true),
+                [pin, "active_high"] => interlock(pin, false),
+                _ => None,
+            };
+            match config {
+                Some(config) => {
+                    if let Err(e) = app_context.storage_manager.lock().unwrap().set_interlock_configuration(&config) {
+                        log::error!("Failed to set interlock: {}", e);
+                    } else {
+                        log::info!("Interlock set to {}, restart to apply", args);
+                    }
+                }
+                None => log::error!("Invalid interlock: {}. Use off or <pin> [active_high]", args),
+            }
+        },
+        "set_limit_switches" => {
+            let pin = |s: &str| match s {
+                "none" => Some(None),
+                pin => pin.parse::<u32>().ok().map(Some),
+            };
+            let parts: Vec<&str> = args.split_whitespace().collect();
+            let config = match parts.as_slice() {
+                ["off"] => Some(storage::LimitSwitchConfiguration::default()),
+                [min, max] => pin(min).zip(pin(max)).map(|(min_pin, max_pin)| storage::LimitSwitchConfiguration { min_pin, max_pin, active_low: true }),
+                [min, max, "active_high"] => pin(min).zip(pin(max)).map(|(min_pin, max_pin)| storage::LimitSwitchConfiguration { min_pin, max_pin, active_low: false }),
+                _ => None,
+            };
+            match config {
+                Some(config) => {
+                    if let Err(e) = app_context.storage_manager.lock().unwrap().set_limit_switch_configuration(&config) {
+                        log::error!("Failed to set limit switches: {}", e);
+                    } else {
+                        log::info!("Limit switches set to {}, restart to apply", args);
+                    }
+                }
+                None => log::error!("Invalid limit switches: {}. Use off or <min pin|none> <max pin|none> [active_high]", args),
+            }
+        },
+        "set_mqtt_url" => {
+            let mut sm = app_context.storage_manager.lock().unwrap();
+            let mut config = sm.get_mqtt_configuration().unwrap_or_default();
+            config.url = if args == "off" { String::new() } else { args.to_string() };
+            if let Err(e) = sm.set_mqtt_configuration(&config) {
+                log::error!("Failed to set MQTT configuration: {}", e);
+            } else {
+                log::info!("MQTT broker set to {}, restart to apply", args);
+            }
+        },
+        "set_mqtt_credentials" => {
+            let parts: Vec<&str> = args.split_whitespace().collect();
+            let credentials = match parts.as_slice() {
+                ["none"] => Some(("", "")),
+                [username, password] => Some((*username, *password)),
+                _ => None,
+            };
+            match credentials {
+                Some((username, password)) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_mqtt_configuration().unwrap_or_default();
+                    config.username = username.to_string();
+                    config.password = password.to_string();
+                    if let Err(e) = sm.set_mqtt_configuration(&config) {
+                        log::error!("Failed to set MQTT configuration: {}", e);
+                    } else {
+                        log::info!("MQTT credentials saved, restart to apply");
+                    }
+                }
+                None => log::error!("Usage: set_mqtt_credentials <username> <password>, or none"),
+            }
+        },
+        "set_mqtt_topic_prefix" => {
+            let mut sm = app_context.storage_manager.lock().unwrap();
+            let mut config = sm.get_mqtt_configuration().unwrap_or_default();
+            config.topic_prefix = args.trim_end_matches('/').to_string();
+            if let Err(e) = sm.set_mqtt_configuration(&config) {
+                log::error!("Failed to set MQTT configuration: {}", e);
+            } else {
+                log::info!("MQTT topic prefix set to {}, restart to apply", config.topic_prefix);
+            }
+        },
+        "set_mqtt_interval" => {
+            match args.parse::<u32>() {
+                Ok(interval_ms) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_mqtt_configuration().unwrap_or_default();
+                    config.interval_ms = interval_ms;
+                    if let Err(e) = sm.set_mqtt_configuration(&config) {
+                        log::error!("Failed to set MQTT configuration: {}", e);
+                    } else {
+                        log::info!("MQTT publish interval set to {} ms, restart to apply", interval_ms);
+                    }
+                }
+                Err(_) => log::error!("Invalid interval: {}", args),
+            }
+        },
+        "set_ntp_server" => {
+            let mut sm = app_context.storage_manager.lock().unwrap();
+            let mut config = sm.get_time_configuration().unwrap_or_default();
+            config.ntp_server = if args == "off" { String::new() } else { args.to_string() };
+            if let Err(e) = sm.set_time_configuration(&config) {
+                log::error!("Failed to set NTP server: {}", e);
+            } else {
+                log::info!("NTP server set to {}, restart to apply", args);
+            }
+        },
+        "set_timezone" => {
+            let mut sm = app_context.storage_manager.lock().unwrap();
+            let mut config = sm.get_time_configuration().unwrap_or_default();
+            config.timezone = args.to_string();
+            if let Err(e) = sm.set_time_configuration(&config) {
+                log::error!("Failed to set timezone: {}", e);
+            } else {
+                log::info!("Timezone set to {}, restart to apply", args);
+            }
+        },
+        "set_schedule" => {
+            match Schedule::parse(args) {
+                Ok(schedule) => {
+                    if let Err(e) = app_context.storage_manager.lock().unwrap().set_schedule(Some(&schedule)) {
+                        log::error!("Failed to set schedule: {}", e);
+                    } else {
+                        log::info!("Schedule set: {:?}", schedule);
+                        app_context.scheduler.lock().unwrap().set(Some(schedule));
+                    }
+                }
+                Err(e) => log::error!("Invalid schedule: {}", e),
+            }
+        },
+        "clear_schedule" => {
+            if let Err(e) = app_context.storage_manager.lock().unwrap().set_schedule(None) {
+                log::error!("Failed to clear schedule: {}", e);
+            } else {
+                app_context.scheduler.lock().unwrap().set(None);
+                log::info!("Schedule cleared");
+            }
+        },
+        "set_modbus_framing" => {
+            let parsed = match args.to_uppercase().as_str() {
+                "8N1" => Some((storage::Parity::None, 1)),
+                "8N2" => Some((storage::Parity::None, 2)),
+                "8E1" => Some((storage::Parity::Even, 1)),
+                "8O1" => Some((storage::Parity::Odd, 1)),
+                _ => None,
+            };
+            match parsed {
+                Some((parity, stop_bits)) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_modbus_configuration().unwrap_or_default();
+                    config.parity = parity;
+                    config.stop_bits = stop_bits;
+                    if let Err(e) = sm.set_modbus_configuration(&config) {
+                        log::error!("Failed to set Modbus framing: {}", e);
+                    } else {
+                        log::info!("Modbus framing set to {}, restart to apply", config.framing());
+                    }
+                }
+                None => log::error!("Invalid framing: {}. Use 8N1, 8N2, 8E1 or 8O1", args),
+            }
+        },
+        "set_position_format" => {
+            let mut parts = args.split_whitespace();
+            let word_order = match parts.next() {
+                Some("low_first") => Some(storage::WordOrder::LowFirst),
+                Some("high_first") => Some(storage::WordOrder::HighFirst),
+                _ => None,
+            };
+            let signed = match parts.next() {
+                Some("signed") => Some(true),
+                Some("unsigned") => Some(false),
+                _ => None,
+            };
+            match (word_order, signed, parts.next()) {
+                (Some(word_order), Some(signed), None) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_modbus_configuration().unwrap_or_default();
+                    config.position_word_order = word_order;
+                    config.position_signed = signed;
+                    if let Err(e) = sm.set_modbus_configuration(&config) {
+                        log::error!("Failed to set position format: {}", e);
+                    } else {
+                        log::info!("Position format set to {}, restart to apply", args);
+                    }
+                }
+                _ => log::error!("Invalid position format: {}. Use: set_position_format <low_first|high_first> <signed|unsigned>", args),
+            }
+        },
+        "set_de_re_polarity" => {
+            let active_low = match args {
+                "active_high" => Some(false),
+                "active_low" => Some(true),
+                _ => None,
+            };
+            match active_low {
+                Some(active_low) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_modbus_configuration().unwrap_or_default();
+                    config.de_re_active_low = active_low;
+                    if let Err(e) = sm.set_modbus_configuration(&config) {
+                        log::error!("Failed to set DE/RE polarity: {}", e);
+                    } else {
+                        log::info!("DE/RE set to {}, restart to apply", args);
+                    }
+                }
+                None => log::error!("Invalid polarity: {}. Use active_high or active_low", args),
+            }
+        },
+        "set_de_re_control" => {
+            let control = match args {
+                "rts" => Some(storage::DeReControl::Rts),
+                "gpio" => Some(storage::DeReControl::Gpio),
+                _ => None,
+            };
+            match control {
+                Some(control) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_modbus_configuration().unwrap_or_default();
+                    config.de_re_control = control;
+                    if let Err(e) = sm.set_modbus_configuration(&config) {
+                        log::error!("Failed to set DE/RE control: {}", e);
+                    } else {
+                        log::info!("DE/RE control set to {}, restart to apply", args);
+                    }
+                }
+                None => log::error!("Invalid DE/RE control: {}. Use rts or gpio", args),
+            }
+        },
+        "set_uart_buffers" => {
+            let values = args.split_whitespace().map(|s| s.parse::<usize>()).collect::<Result<Vec<_>, _>>();
+            match values.as_deref() {
+                Ok(&[rx, tx]) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_modbus_configuration().unwrap_or_default();
+                    config.rx_buffer_size = rx;
+                    config.tx_buffer_size = tx;
+                    if let Err(e) = sm.set_modbus_configuration(&config) {
+                        log::error!("Failed to set UART buffers: {}", e);
+                    } else {
+                        log::info!("UART buffers set to {} bytes RX and {} bytes TX, restart to apply", rx, tx);
+                    }
+                }
+                _ => log::error!("Invalid arguments: {}. Use: set_uart_buffers <rx bytes> <tx bytes>", args),
+            }
+        },
+        "modbus_set_device_id" => {
+            match args.parse::<u8>() {
+                Ok(device_id) => {
+                    let result = app_context.storage_manager.lock().unwrap().request_device_id_change(device_id);
+                    match result {
+                        Ok(()) => {
+                            log::info!("Restarting to set the motor device id to {}...", device_id);
+                            boot::restart(app_context);
+                        }
+                        Err(e) => log::error!("Failed to request device id change: {}", e),
+                    }
+                }
+                Err(_) => log::error!("Invalid device id: {}. Use a number between 1 and 247", args),
+            }
+        },
+        "set_control_rate" => {
+            match args.parse::<f32>() {
+                Ok(rate) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_motor_loop_configuration().unwrap_or_default();
+                    config.control_rate_hz = rate;
+                    if let Err(e) = sm.set_motor_loop_configuration(&config) {
+                        log::error!("Failed to set control rate: {}", e);
+                    } else {
+                        log::info!("Control rate set to {} Hz, restart to apply", rate);
+                    }
+                }
+                Err(_) => log::error!("Invalid control rate: {}", args),
+            }
+        },
+        "set_motor_task_priority" => {
+            match args.parse::<u8>() {
+                Ok(priority) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_motor_loop_configuration().unwrap_or_default();
+                    config.task_priority = priority;
+                    if let Err(e) = sm.set_motor_loop_configuration(&config) {
+                        log::error!("Failed to set motor task priority: {}", e);
+                    } else {
+                        log::info!("Motor task priority set to {}, restart to apply", priority);
+                    }
+                }
+                Err(_) => log::error!("Invalid priority: {}", args),
+            }
+        },
+        "set_motor_task_core" => {
+            match args.parse::<u8>() {
+                Ok(core) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_motor_loop_configuration().unwrap_or_default();
+                    config.task_core = core;
+                    if let Err(e) = sm.set_motor_loop_configuration(&config) {
+                        log::error!("Failed to set motor task core: {}", e);
+                    } else {
+                        log::info!("Motor task core set to {}, restart to apply", core);
+                    }
+                }
+                Err(_) => log::error!("Invalid core: {}. Use 0 or 1", args),
+            }
+        },
+        "set_pause_after_brownout" => {
+            match args.parse::<bool>() {
+                Ok(v) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_motor_loop_configuration().unwrap_or_default();
+                    config.pause_after_brownout = v;
+                    if let Err(e) = sm.set_motor_loop_configuration(&config) {
+                        log::error!("Failed to set pause after brownout: {}", e);
+                    } else {
+                        log::info!("Pause after brownout set to {}", v);
+                    }
+                }
+                Err(_) => log::error!("Invalid boolean value: {}. Use 'true' or 'false'", args),
+            }
+        },
+        "set_skip_homing" => {
+            match args.parse::<bool>() {
+                Ok(v) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_motor_loop_configuration().unwrap_or_default();
+                    config.skip_homing = v;
+                    if let Err(e) = sm.set_motor_loop_configuration(&config) {
+                        log::error!("Failed to set skip homing: {}", e);
+                    } else if v {
+                        log::warn!("Homing will be skipped from the next start, using the last homed range. \
+                            Only use this with an absolute encoder, and never move the carriage while the motor is off.");
+                    } else {
+                        log::info!("Skip homing set to false");
+                    }
+                }
+                Err(_) => log::error!("Invalid boolean value: {}. Use 'true' or 'false'", args),
+            }
+        },
+        "set_homing_stability" => {
+            let values = args.split_whitespace().map(|s| s.parse::<u32>()).collect::<Result<Vec<_>, _>>();
+            match values.as_deref() {
+                Ok(&[stable_threshold, stable_reads]) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let config = storage::HomingConfiguration {
+                        stable_threshold,
+                        stable_reads,
+                        ..sm.get_homing_configuration().unwrap_or_default()
+                    };
+                    if let Err(e) = sm.set_homing_configuration(&config) {
+                        log::error!("Failed to set homing stability: {}", e);
+                    } else {
+                        log::info!("Homing waits for {} reads in a row within {} counts, applies from the next homing", stable_reads, stable_threshold);
+                    }
+                }
+                _ => log::error!("Invalid arguments: {}. Use: set_homing_stability <threshold> <reads>", args),
+            }
+        },
+        "set_homing_margins" => {
+            let values = args.split_whitespace().map(|s| s.parse::<u32>()).collect::<Result<Vec<_>, _>>();
+            match values.as_deref() {
+                Ok(&[margin_min, margin_max]) => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let config = storage::HomingConfiguration {
+                        margin_min,
+                        margin_max,
+                        ..sm.get_homing_configuration().unwrap_or_default()
+                    };
+                    if let Err(e) = sm.set_homing_configuration(&config) {
+                        log::error!("Failed to set homing margins: {}", e);
+                    } else {
+                        log::info!("Homing keeps {} counts clear of the min end and {} of the max end, applies from the next homing", margin_min, margin_max);
+                    }
+                }
+                _ => log::error!("Invalid arguments: {}. Use: set_homing_margins <min> <max>", args),
+            }
+        },
+        "set_homing_verify" => {
+            let tolerance = match args {
+                "off" => None,
+                _ => match args.parse::<u32>() {
+                    Ok(tolerance) => Some(tolerance),
+                    Err(_) => {
+                        log::error!("Invalid arguments: {}. Use: set_homing_verify <tolerance> | off", args);
+                        return;
+                    }
+                },
+            };
+            let mut sm = app_context.storage_manager.lock().unwrap();
+            let mut config = sm.get_homing_configuration().unwrap_or_default();
+            config.verify = tolerance.is_some();
+            config.repeat_tolerance = tolerance.unwrap_or(config.repeat_tolerance);
+            match sm.set_homing_configuration(&config) {
+                Err(e) => log::error!("Failed to set homing verification: {}", e),
+                Ok(()) if config.verify => log::info!("Homing finds the ends twice and warns if they differ by more than {} counts, applies from the next homing",
+                    config.repeat_tolerance),
+                Ok(()) => log::info!("Homing finds the ends once, applies from the next homing"),
+            }
+        },
+        "set_homing_speed" => {
+            let values = args.split_whitespace().map(|s| s.parse::<u32>()).collect::<Result<Vec<_>, _>>();
+            let mut sm = app_context.storage_manager.lock().unwrap();
+            let mut config = sm.get_homing_configuration().unwrap_or_default();
+            match values.as_deref() {
+                Ok(&[0]) => config.approach_speed = 0,
+                Ok(&[approach_speed, touch_speed]) => {
+                    config.approach_speed = approach_speed;
+                    config.touch_speed = touch_speed;
+                }
+                Ok(&[approach_speed, touch_speed, backoff]) => {
+                    config.approach_speed = approach_speed;
+                    config.touch_speed = touch_speed;
+                    config.backoff = backoff;
+                }
+                _ => {
+                    log::error!("Invalid arguments: {}. Use: set_homing_speed <approach> <touch> [backoff] | 0", args);
+                    return;
+                }
+            }
+            match sm.set_homing_configuration(&config) {
+                Err(e) => log::error!("Failed to set homing speed: {}", e),
+                Ok(()) if config.approach_speed == 0 => log::info!("Homing pushes against the ends under the power limit, applies from the next homing"),
+                Ok(()) => log::info!("Homing approaches at {} counts/s, backs off {} counts and touches at {} counts/s, applies from the next homing",
+                    config.approach_speed, config.backoff, config.touch_speed),
+            }
+        },
+        "set_http_port" => {
+            match args.parse::<u16>() {
+                Ok(port) if port > 0 => {
+                    let mut sm = app_context.storage_manager.lock().unwrap();
+                    let mut config = sm.get_http_configuration().unwrap_or_default();
+                    config.port = port;
+                    if let Err(e) = sm.set_http_configuration(&config) {
+                        log::error!("Failed to set HTTP port: {}", e);
+                    } else {
+                        log::info!("HTTP port set to {}, restart to apply", port);
+                    }
+                }
+                _ => log::error!("Invalid port value: {}. Use a number between 1 and 65535", args),
+            }
+        },
+        "set_cors_origin" => {
+            if args.is_empty() || args.contains(char::is_whitespace) {
+                log::error!("Invalid origin: '{}'. Use '*' or an origin like http://example.com", args);
+            } else {
+                let mut sm = app_context.storage_manager.lock().unwrap();
+                let mut config = sm.get_http_configuration().unwrap_or_default();
+                config.allowed_origin = args.to_string();
+                if let Err(e) = sm.set_http_configuration(&config) {
+                    log::error!("Failed to set CORS origin: {}", e);
+                } else {
+                    log::info!("CORS allowed origin set to {}, restart to apply", args);
+                }
+            }
+        },
+        "get_available_pins" => {
+            let report = pins::pin_report(&app_context.all_pins, &app_context.storage_manager.lock().unwrap());
+            println!("{}", serde_json::to_string_pretty(&report).unwrap());
+        },
+        "get_pin_configuration" => {
+            match app_context.storage_manager.lock().unwrap().get_pin_configuration() {
+                Ok(config) => {
                     let json = serde_json::to_string_pretty(&config).unwrap();
                     println!("{}", json);
-                } else {
-                    log::error!("Motor controller not initialized");
                 }
-            },
-            "pause" => {
-                let mut mc_opt = app_context.motor_controller.lock().unwrap();
-                if let Some(mc) = mc_opt.as_mut() {
-                    if let Err(e) = mc.update_config(|config| {
-                        config.paused = true;
-                    }) {
-                        log::error!("Failed to set motor config: {}", e);
+                Err(e) => {
+                    log::error!("Failed to get pin config: {}", e);
+                }
+            }
+        },
+        "set_motor_config" => {
+            match MotorControllerConfig::from_json(args.as_bytes()) {
+                Ok(mut config) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        match mc.check_ranges(&mut config) {
+                            Ok(clamped) => {
+                                for clamped in clamped {
+                                    log::warn!("Clamped {}", clamped);
+                                }
+                                match mc.set_config(config) {
+                                    Ok(()) => log::info!("Motor config updated"),
+                                    Err(e) => log::error!("Failed to set motor config: {}", e),
+                                }
+                            }
+                            Err(e) => log::error!("{}", e),
+                        }
                     } else {
-                        log::info!("Motor paused");
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(e) => {
+                    for line in e.to_string().lines() {
+                        log::error!("{}", line);
+                    }
+                }
+            }
+        } ,
+        "diagnose_modbus" => {
+            let result = app_context.storage_manager.lock().unwrap().request_modbus_diagnosis();
+            match result {
+                Ok(()) => {
+                    log::info!("Restarting to run the Modbus diagnosis before the motor starts...");
+                    boot::restart(app_context);
+                }
+                Err(e) => log::error!("Failed to request Modbus diagnosis: {}", e),
+            }
+        },
+        "reboot_safe_mode" => {
+            let result = app_context.storage_manager.lock().unwrap().request_safe_mode();
+            match result {
+                Ok(()) => {
+                    log::info!("Restarting in safe mode, the motor will not be started...");
+                    boot::restart(app_context);
+                }
+                Err(e) => log::error!("Failed to request safe mode: {}", e),
+            }
+        },
+        "motor_stop_loop" => {
+            if app_context.motor_loop_enabled.swap(false, Ordering::Relaxed) {
+                log::info!("Stopping the motor loop...");
+            } else {
+                log::info!("Motor loop is already stopped");
+            }
+        },
+        "motor_start_loop" => {
+            if app_context.motor_loop_enabled.swap(true, Ordering::Relaxed) {
+                log::info!("Motor loop is already running");
+            } else {
+                log::info!("Starting the motor loop, the motor will be initialized and homed again...");
+            }
+        },
+        "get_motor_config" => {
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                let config = mc.get_config();
+                let json = serde_json::to_string_pretty(&config).unwrap();
+                println!("{}", json);
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        "get_telemetry" => {
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                match mc.read_telemetry() {
+                    Ok(telemetry) => println!("{}", serde_json::to_string_pretty(&telemetry).unwrap()),
+                    Err(e) => log::error!("Failed to read telemetry: {}", e),
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        #[cfg(feature = "modbus-debug")]
+        "modbus_dump" => {
+            let mut parts = args.split_whitespace().map(parse_register_number);
+            let (start, count) = match (parts.next(), parts.next()) {
+                (Some(Some(start)), Some(Some(count))) if count > 0 && start as u32 + count as u32 <= 0x10000 => (start, count),
+                _ => {
+                    log::error!("Invalid arguments: {}. Use: modbus_dump <start> <count>, e.g. modbus_dump 0x00 32", args);
+                    return;
+                }
+            };
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                let mut values = vec![0u16; count as usize];
+                match mc.read_registers(start, &mut values) {
+                    Ok(()) => {
+                        println!("  addr    hex  unsigned  signed");
+                        for (i, value) in values.iter().enumerate() {
+                            println!("{:#06x} {:#06x} {:>9} {:>7}", start as usize + i, value, value, *value as i16);
+                        }
                     }
+                    Err(e) => log::error!("Failed to read registers: {}", e),
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        #[cfg(not(feature = "modbus-debug"))]
+        "modbus_dump" => {
+            log::error!("modbus_dump needs a build with the modbus-debug feature, reading some registers can have side effects");
+        },
+        "pause" => {
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                if let Err(e) = mc.update_config(|config| {
+                    config.paused = true;
+                }) {
+                    log::error!("Failed to set motor config: {}", e);
                 } else {
-                    log::error!("Motor controller not initialized");
+                    log::info!("Motor paused");
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        "start" => {
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                if let Err(e) = mc.update_config(|config| {
+                    config.paused = false;
+                }) {
+                    log::error!("Failed to set motor config: {}", e);
+                } else {
+                    log::info!("Motor started");
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        "set_bpm" => {
+            match args.parse::<f32>() {
+                Ok(bpm) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.bpm = bpm;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("BPM set to {}", bpm);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
                 }
-            },
-            "start" => {
+                Err(_) => log::error!("Invalid BPM value: {}", args),
+            }
+        },
+        "list_waves" => {
+            let json = serde_json::to_string_pretty(&motion::waveform_infos()).unwrap();
+            println!("{}", json);
+        },
+        "set_wave" => {
+            if motion::is_known_waveform(args) {
+                let wave = args.to_string();
                 let mut mc_opt = app_context.motor_controller.lock().unwrap();
                 if let Some(mc) = mc_opt.as_mut() {
                     if let Err(e) = mc.update_config(|config| {
-                        config.paused = false;
+                        config.wave_func = wave;
                     }) {
                         log::error!("Failed to set motor config: {}", e);
                     } else {
-                        log::info!("Motor started");
+                        log::info!("Wave function set to {}", args);
                     }
                 } else {
                     log::error!("Motor controller not initialized");
                 }
-            },
-            "set_bpm" => {
-                match args.parse::<f32>() {
-                    Ok(bpm) => {
-                        let mut mc_opt = app_context.motor_controller.lock().unwrap();
-                        if let Some(mc) = mc_opt.as_mut() {
-                            if let Err(e) = mc.update_config(|config| {
-                                config.bpm = bpm;
-                            }) {
-                                log::error!("Failed to set motor config: {}", e);
-                            } else {
-                                log::info!("BPM set to {}", bpm);
+            } else {
+                log::error!("Invalid wave function: {}. Use one of: {}", args, motion::waveform_names().join(", "));
+            }
+        },
+        "set_paused_position" => {
+            let (value, units) = args.split_once(' ').unwrap_or((args, "fraction"));
+            match (value.parse::<f32>(), Units::parse(units.trim())) {
+                (Ok(value), Ok(units)) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        match mc.unit_converter().position_to_fraction(value, units) {
+                            Ok(pos) => {
+                                if let Err(e) = mc.update_config(|config| {
+                                    config.paused_position = pos;
+                                }) {
+                                    log::error!("Failed to set motor config: {}", e);
+                                } else {
+                                    log::info!("Paused position set to {}", pos);
+                                }
                             }
+                            Err(e) => log::error!("{}", e),
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                (_, Err(e)) => log::error!("{}", e),
+                (Err(_), _) => log::error!("Invalid paused position value: {}", args),
+            }
+        },
+        "set_depth" => {
+            match args.parse::<f32>() {
+                Ok(depth) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.depth = depth;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
                         } else {
-                            log::error!("Motor controller not initialized");
+                            log::info!("Depth set to {}", depth);
                         }
+                    } else {
+                        log::error!("Motor controller not initialized");
                     }
-                    Err(_) => log::error!("Invalid BPM value: {}", args),
                 }
-            },
-            "set_wave" => {
-                if args == "sine" || args == "thrust" || args == "spline" {
-                    let wave = args.to_string();
+                Err(_) => log::error!("Invalid depth value: {}", args),
+            }
+        },
+        "set_depth_top" => {
+            match args.parse::<bool>() {
+                Ok(v) => {
                     let mut mc_opt = app_context.motor_controller.lock().unwrap();
                     if let Some(mc) = mc_opt.as_mut() {
                         if let Err(e) = mc.update_config(|config| {
-                            config.wave_func = wave;
+                            config.depth_top = v;
                         }) {
                             log::error!("Failed to set motor config: {}", e);
                         } else {
-                            log::info!("Wave function set to {}", args);
+                            log::info!("Depth top set to {}", v);
                         }
                     } else {
                         log::error!("Motor controller not initialized");
                     }
-                } else {
-                    log::error!("Invalid wave function: {}. Use 'sine' or 'thrust' or 'spline'", args);
-                }
-            },
-            "set_paused_position" => {
-                match args.parse::<f32>() {
-                    Ok(pos) => {
-                        let mut mc_opt = app_context.motor_controller.lock().unwrap();
-                        if let Some(mc) = mc_opt.as_mut() {
-                            if let Err(e) = mc.update_config(|config| {
-                                config.paused_position = pos;
-                            }) {
-                                log::error!("Failed to set motor config: {}", e);
-                            } else {
-                                log::info!("Paused position set to {}", pos);
-                            }
+                }
+                Err(_) => log::error!("Invalid boolean value: {}. Use 'true' or 'false'", args),
+            }
+        },
+        "set_sharpness" => {
+            match args.parse::<f32>() {
+                Ok(sharpness) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.thrust.sharpness = sharpness;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
                         } else {
-                            log::error!("Motor controller not initialized");
+                            log::info!("Sharpness set to {}", sharpness);
                         }
+                    } else {
+                        log::error!("Motor controller not initialized");
                     }
-                    Err(_) => log::error!("Invalid paused position value: {}", args),
                 }
-            },
-            "set_depth" => {
-                match args.parse::<f32>() {
-                    Ok(depth) => {
-                        let mut mc_opt = app_context.motor_controller.lock().unwrap();
-                        if let Some(mc) = mc_opt.as_mut() {
-                            if let Err(e) = mc.update_config(|config| {
-                                config.depth = depth;
-                            }) {
-                                log::error!("Failed to set motor config: {}", e);
-                            } else {
-                                log::info!("Depth set to {}", depth);
+                Err(_) => log::error!("Invalid sharpness value: {}", args),
+            }
+        },
+        "set_acceleration" => {
+            match args.parse::<u16>() {
+                Ok(v) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.acceleration = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("Acceleration set to {}", mc.get_config().acceleration);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                _ => log::error!("Invalid acceleration value: {}. Use a number between 1000 and 60000", args),
+            }
+        },
+        "set_sharpness_speed" => {
+            match args.parse::<f32>() {
+                Ok(v) if v >= 0.0 => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.thrust.sharpness_speed = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("Sharpness speed set to {}", v);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                _ => log::error!("Invalid sharpness speed value: {}", args),
+            }
+        },
+        "set_dwell_top" => {
+            match args.parse::<f32>() {
+                Ok(v) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.dwell_top = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("Dwell top set to {}", v);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(_) => log::error!("Invalid dwell top value: {}", args),
+            }
+        },
+        "set_dwell_bottom" => {
+            match args.parse::<f32>() {
+                Ok(v) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.dwell_bottom = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("Dwell bottom set to {}", v);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(_) => log::error!("Invalid dwell bottom value: {}", args),
+            }
+        },
+        "set_output_smoothing" => {
+            match args.parse::<f32>() {
+                Ok(v) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.output_smoothing = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("Output smoothing set to {}", v);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(_) => log::error!("Invalid output smoothing value: {}", args),
+            }
+        },
+        "set_resync_on_unpause" => {
+            match args.parse::<bool>() {
+                Ok(v) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.resync_on_unpause = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("Resync on unpause set to {}", v);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(_) => log::error!("Invalid boolean value: {}. Use 'true' or 'false'", args),
+            }
+        },
+        "set_warmup_on_unpause" => {
+            match args.parse::<bool>() {
+                Ok(v) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.warmup_on_unpause = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("Warmup on unpause set to {}", v);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(_) => log::error!("Invalid boolean value: {}. Use 'true' or 'false'", args),
+            }
+        },
+        "set_power_save_on_pause" => {
+            match args.parse::<bool>() {
+                Ok(v) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.power_save_on_pause = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("Power save on pause set to {}", v);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(_) => log::error!("Invalid boolean value: {}. Use 'true' or 'false'", args),
+            }
+        },
+        "set_demo_mode" => {
+            match args.parse::<bool>() {
+                Ok(v) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.demo_mode = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("Demo mode set to {}", v);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(_) => log::error!("Invalid boolean value: {}. Use 'true' or 'false'", args),
+            }
+        },
+        "set_reversal_smoothing" => {
+            match args.parse::<f32>() {
+                Ok(v) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.reversal_smoothing = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("Reversal smoothing set to {}", v);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(_) => log::error!("Invalid reversal smoothing value: {}", args),
+            }
+        },
+        "set_end_deadzone" => {
+            match args.parse::<f32>() {
+                Ok(v) if (0.0..=motion::MAX_END_DEADZONE).contains(&v) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.end_deadzone = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("End deadzone set to {}", v);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                _ => log::error!("Invalid end deadzone value: {}. Use 0.0 to {}", args, motion::MAX_END_DEADZONE),
+            }
+        },
+        "set_speed_ratio" => {
+            let values = args.split_whitespace().map(|s| s.parse::<f32>()).collect::<Result<Vec<_>, _>>();
+            match values.as_deref() {
+                Ok(&[up, down]) if up > 0.0 && down > 0.0 => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.up_speed_ratio = up;
+                            config.down_speed_ratio = down;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            let config = mc.get_config();
+                            log::info!("Speed ratio set to {} up, {} down", config.up_speed_ratio, config.down_speed_ratio);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                _ => log::error!("Invalid speed ratio: {}. Use: set_speed_ratio <up> <down>, e.g. 1 2 for a twice as fast down stroke", args),
+            }
+        },
+        "set_tease" => {
+            let values = args.split_whitespace().map(|s| s.parse::<f32>()).collect::<Result<Vec<_>, _>>();
+            let (probability, intensity) = match values.as_deref() {
+                Ok([probability]) => (Some(*probability), None),
+                Ok([probability, intensity]) => (Some(*probability), Some(*intensity)),
+                _ => (None, None),
+            };
+            let in_range = |v: f32| (0.0..=1.0).contains(&v);
+            match probability {
+                Some(probability) if in_range(probability) && intensity.map_or(true, in_range) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.tease_probability = probability;
+                            if let Some(intensity) = intensity {
+                                config.tease_intensity = intensity;
                             }
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else if probability > 0.0 {
+                            log::info!("Teasing {:.0}% of strokes at intensity {}", probability * 100.0, mc.get_config().tease_intensity);
                         } else {
-                            log::error!("Motor controller not initialized");
+                            log::info!("Teasing off");
                         }
+                    } else {
+                        log::error!("Motor controller not initialized");
                     }
-                    Err(_) => log::error!("Invalid depth value: {}", args),
                 }
-            },
-            "set_depth_top" => {
-                match args.parse::<bool>() {
-                    Ok(v) => {
-                        let mut mc_opt = app_context.motor_controller.lock().unwrap();
-                        if let Some(mc) = mc_opt.as_mut() {
-                            if let Err(e) = mc.update_config(|config| {
-                                config.depth_top = v;
-                            }) {
-                                log::error!("Failed to set motor config: {}", e);
+                _ => log::error!("Invalid tease: {}. Use: set_tease <probability 0.0 to 1.0> [intensity 0.0 to 1.0]", args),
+            }
+        },
+        "set_escalation" => {
+            let parts: Vec<&str> = args.split_whitespace().collect();
+            let step = parts.first().and_then(|s| s.parse::<f32>().ok());
+            let max = parts.get(1).map(|s| s.parse::<f32>()).transpose();
+            let reset = match parts.get(2).copied() {
+                None => Ok(None),
+                Some("reset") => Ok(Some(true)),
+                Some("hold") => Ok(Some(false)),
+                Some(_) => Err(()),
+            };
+            match (step, max, reset) {
+                (Some(step), Ok(max), Ok(reset)) if parts.len() <= 3 => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.escalation_step = step;
+                            if let Some(max) = max {
+                                config.escalation_max = max;
+                            }
+                            if let Some(reset) = reset {
+                                config.escalation_reset = reset;
+                            }
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            let config = mc.get_config();
+                            if config.escalation_step > 0.0 {
+                                log::info!("Deepening by {} per stroke up to {}, then {}", config.escalation_step, config.escalation_max,
+                                    if config.escalation_reset { "starting over" } else { "holding" });
                             } else {
-                                log::info!("Depth top set to {}", v);
+                                log::info!("Escalation off");
                             }
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                _ => log::error!("Invalid escalation: {}. Use: set_escalation <step> [max depth] [reset|hold]", args),
+            }
+        },
+        "tease_seed" => {
+            match args.parse::<u32>() {
+                Ok(seed) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        mc.seed_tease(seed);
+                        log::info!("Tease seed set to {}", seed);
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(_) => log::error!("Invalid tease seed: {}", args),
+            }
+        },
+        "set_crossfade_time" => {
+            match args.parse::<f32>() {
+                Ok(v) if (0.0..=10.0).contains(&v) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.crossfade_time = v;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
                         } else {
-                            log::error!("Motor controller not initialized");
+                            log::info!("Crossfade time set to {}", v);
                         }
+                    } else {
+                        log::error!("Motor controller not initialized");
                     }
-                    Err(_) => log::error!("Invalid boolean value: {}. Use 'true' or 'false'", args),
                 }
-            },
-            "set_sharpness" => {
-                match args.parse::<f32>() {
-                    Ok(sharpness) => {
-                        let mut mc_opt = app_context.motor_controller.lock().unwrap();
-                        if let Some(mc) = mc_opt.as_mut() {
-                            if let Err(e) = mc.update_config(|config| {
-                                config.sharpness = sharpness;
-                            }) {
-                                log::error!("Failed to set motor config: {}", e);
-                            } else {
-                                log::info!("Sharpness set to {}", sharpness);
+                _ => log::error!("Invalid crossfade time: {}. Use 0 to 10 seconds", args),
+            }
+        },
+        "set_idle_park" => {
+            let values = args.split_whitespace().map(|s| s.parse::<f32>()).collect::<Result<Vec<_>, _>>();
+            let (timeout, position) = match values.as_deref() {
+                Ok([timeout]) => (Some(*timeout), None),
+                Ok([timeout, position]) => (Some(*timeout), Some(*position)),
+                _ => (None, None),
+            };
+            match timeout {
+                Some(timeout) if timeout >= 0.0 && position.map_or(true, |p| (0.0..=1.0).contains(&p)) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.idle_park_timeout = timeout;
+                            if let Some(position) = position {
+                                config.idle_park_position = position;
                             }
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else if timeout > 0.0 {
+                            log::info!("Parking at {} after {}s paused", mc.get_config().idle_park_position, timeout);
                         } else {
-                            log::error!("Motor controller not initialized");
-                        }
-                    }
-                    Err(_) => log::error!("Invalid sharpness value: {}", args),
-                }
-            },
-            "help" => {
-                log::info!("Available commands:");
-                log::info!("  help                           - Show this help message");
-                log::info!("  set_wifi_ssid <ssid>                - Set WiFi SSID");
-                log::info!("  set_wifi_password <password>        - Set WiFi password");
-                log::info!("  get_pin_configuration          - Get pin configuration in JSON format");
-                log::info!("  set_pin_modbus_tx <pin>        - Set Modbus TX pin");
-                log::info!("  set_pin_modbus_rx <pin>        - Set Modbus RX pin");
-                log::info!("  set_pin_modbus_de_re <pin>     - Set Modbus DE/RE pin");
-                log::info!("  get_motor_config               - Get motor config in JSON format");
-                log::info!("  set_motor_config <json>        - Set motor config from a JSON string");
-                log::info!("  pause                          - Pause the motor");
-                log::info!("  start                          - Start the motor");
-                log::info!("  set_bpm <bpm>                  - Set motor BPM");
-                log::info!("  set_wave <sine|thrust|spline>         - Set motor waveform");
-                log::info!("  set_paused_position <position> - Set motor position when paused (0.0 to 1.0)");
-                log::info!("  set_depth <depth>              - Set motor stroke depth (0.0 to 1.0)");
-                log::info!("  set_depth_top <true|false>     - Set depth direction");
-                log::info!("  set_sharpness <sharpness>      - Set sharpness for thrust wave (0.01 to 0.99)");
-                log::info!("  set_spline_points <p1> <p2> ... - Set points for spline wave (0.0 to 1.0)");
-            },
-            "set_spline_points" => {
-                let points: Result<Vec<f32>, _> = args.split_whitespace().map(|s| s.parse::<f32>()).collect();
-                match points {
-                    Ok(points) => {
-                        if points.is_empty() {
-                            log::error!("Spline points cannot be empty");
-                            return;
-                        }
-                        for &p in &points {
-                            if !(0.0..=1.0).contains(&p) {
-                                log::error!("Spline points must be between 0.0 and 1.0");
-                                return;
+                            log::info!("Idle parking off");
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                _ => log::error!("Invalid idle park: {}. Use: set_idle_park <seconds> [position 0.0 to 1.0]", args),
+            }
+        },
+        "set_bpm_limits" => {
+            let values = args.split_whitespace().map(|s| s.parse::<f32>()).collect::<Result<Vec<_>, _>>();
+            match values.as_deref() {
+                Ok([bpm_min, bpm_max]) => {
+                    // The motor loop locks the controller before storage, so storage has to be
+                    // released before the controller is locked here
+                    let saved = {
+                        let mut sm = app_context.storage_manager.lock().unwrap();
+                        let limits = motion::SpeedLimits {
+                            bpm_min: *bpm_min,
+                            bpm_max: *bpm_max,
+                            ..sm.get_speed_limits().unwrap_or_default()
+                        };
+                        sm.set_speed_limits(&limits).map(|_| limits)
+                    };
+                    match saved {
+                        Ok(limits) => {
+                            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                            if let Some(mc) = mc_opt.as_mut() {
+                                if let Err(e) = mc.set_speed_limits(limits) {
+                                    log::error!("Failed to apply BPM limits: {}", e);
+                                }
                             }
+                            log::info!("BPM limits set to [{}, {}]", bpm_min, bpm_max);
                         }
-
-                        let mut mc_opt = app_context.motor_controller.lock().unwrap();
-                        if let Some(mc) = mc_opt.as_mut() {
-                            if let Err(e) = mc.update_config(|config| {
-                                config.spline_points = points.clone();
-                            }) {
-                                log::error!("Failed to set motor config: {}", e);
+                        Err(e) => log::error!("Failed to set BPM limits: {}", e),
+                    }
+                }
+                _ => log::error!("Invalid BPM limits: {}. Use: set_bpm_limits <min> <max>", args),
+            }
+        },
+        "set_bpm_depth_factor" => {
+            match args.parse::<f32>() {
+                Ok(k) => {
+                    let saved = {
+                        let mut sm = app_context.storage_manager.lock().unwrap();
+                        let limits = motion::SpeedLimits {
+                            depth_factor: k,
+                            ..sm.get_speed_limits().unwrap_or_default()
+                        };
+                        sm.set_speed_limits(&limits).map(|_| limits)
+                    };
+                    match saved {
+                        Ok(limits) => {
+                            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                            if let Some(mc) = mc_opt.as_mut() {
+                                if let Err(e) = mc.set_speed_limits(limits) {
+                                    log::error!("Failed to apply BPM limits: {}", e);
+                                }
+                            }
+                            log::info!("BPM depth factor set to {}", k);
+                        }
+                        Err(e) => log::error!("Failed to set BPM depth factor: {}", e),
+                    }
+                }
+                Err(_) => log::error!("Invalid depth factor: {}", args),
+            }
+        },
+        "set_counts_per_mm" => {
+            match args.parse::<f32>() {
+                Ok(v) if v >= 0.0 => {
+                    // 0 clears the calibration
+                    let counts_per_mm = (v > 0.0).then_some(v);
+                    // Bound first so the storage guard is dropped before the controller is locked
+                    let saved = app_context.storage_manager.lock().unwrap().set_counts_per_mm(counts_per_mm);
+                    if let Err(e) = saved {
+                        log::error!("Failed to set counts per mm: {}", e);
+                    } else {
+                        if let Some(mc) = app_context.motor_controller.lock().unwrap().as_mut() {
+                            mc.set_counts_per_mm(counts_per_mm);
+                        }
+                        match counts_per_mm {
+                            Some(v) => log::info!("Counts per mm set to {}", v),
+                            None => log::info!("Counts per mm cleared"),
+                        }
+                    }
+                }
+                _ => log::error!("Invalid counts per mm: {}", args),
+            }
+        },
+        "get_range" => {
+            let mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_ref() {
+                println!("{}", serde_json::to_string_pretty(&mc.range_info()).unwrap());
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        "set_range_policy" => {
+            let policy = match args {
+                "clamp" => Some(motion::RangePolicy::Clamp),
+                "reject" => Some(motion::RangePolicy::Reject),
+                _ => None,
+            };
+            match policy {
+                Some(policy) => {
+                    let saved = app_context.storage_manager.lock().unwrap().set_range_policy(policy);
+                    if let Err(e) = saved {
+                        log::error!("Failed to set range policy: {}", e);
+                    } else {
+                        if let Some(mc) = app_context.motor_controller.lock().unwrap().as_mut() {
+                            mc.set_range_policy(policy);
+                        }
+                        log::info!("Out of range values are now {}", if policy == motion::RangePolicy::Clamp { "clamped" } else { "rejected" });
+                    }
+                }
+                None => log::error!("Invalid range policy: {}. Use clamp or reject", args),
+            }
+        },
+        "set_jog_limits" => {
+            let values = args.split_whitespace().map(|s| s.parse::<f32>()).collect::<Result<Vec<_>, _>>();
+            match values.as_deref() {
+                Ok(&[step, max_step, max_rate]) => {
+                    // One storage guard for the read and the write, dropped before the controller is locked
+                    let saved = {
+                        let mut sm = app_context.storage_manager.lock().unwrap();
+                        let limits = motion::JogLimits { step, max_step, max_rate, ..sm.get_jog_limits().unwrap_or_default() };
+                        sm.set_jog_limits(&limits).map(|_| limits)
+                    };
+                    match saved {
+                        Ok(limits) => {
+                            if let Some(mc) = app_context.motor_controller.lock().unwrap().as_mut() {
+                                mc.set_jog_limits(limits);
+                            }
+                            log::info!("Jog step {}, at most {} per jog and {} per second", step, max_step, max_rate);
+                        }
+                        Err(e) => log::error!("Failed to set jog limits: {}", e),
+                    }
+                }
+                _ => log::error!("Invalid jog limits: {}. Use: set_jog_limits <step> <max step> <max rate>", args),
+            }
+        },
+        "set_jog_recenter" => {
+            let mut parts = args.split_whitespace();
+            let seconds = parts.next().map(|s| s.parse::<f32>());
+            let center = parts.next().map(|s| s.parse::<f32>()).transpose();
+            match (seconds, center) {
+                (Some(Ok(seconds)), Ok(center)) => {
+                    let saved = {
+                        let mut sm = app_context.storage_manager.lock().unwrap();
+                        let current = sm.get_jog_limits().unwrap_or_default();
+                        let limits = motion::JogLimits { recenter_after: seconds, center: center.unwrap_or(current.center), ..current };
+                        sm.set_jog_limits(&limits).map(|_| limits)
+                    };
+                    match saved {
+                        Ok(limits) => {
+                            if seconds > 0.0 {
+                                log::info!("Returning to {} after {}s without a jog", limits.center, seconds);
                             } else {
-                                log::info!("Spline points set to {:?}", points);
+                                log::info!("Jog recentering off");
+                            }
+                            if let Some(mc) = app_context.motor_controller.lock().unwrap().as_mut() {
+                                mc.set_jog_limits(limits);
                             }
+                        }
+                        Err(e) => log::error!("Failed to set jog recentering: {}", e),
+                    }
+                }
+                _ => log::error!("Invalid arguments: {}. Use: set_jog_recenter <seconds> [center]", args),
+            }
+        },
+        "get_jog_limits" => {
+            let limits = app_context.storage_manager.lock().unwrap().get_jog_limits().unwrap_or_default();
+            println!("{}", serde_json::to_string_pretty(&limits).unwrap());
+        },
+        "get_bpm_limits" => {
+            let limits = app_context.storage_manager.lock().unwrap().get_speed_limits().unwrap_or_default();
+            println!("{}", serde_json::to_string_pretty(&limits).unwrap());
+        },
+        "save_spline" => {
+            let points = {
+                let mc_opt = app_context.motor_controller.lock().unwrap();
+                mc_opt.as_ref().map(|mc| mc.get_config().spline.points)
+            };
+            match points {
+                Some(points) => {
+                    if let Err(e) = app_context.storage_manager.lock().unwrap().save_spline(args, &points) {
+                        log::error!("Failed to save spline: {}", e);
+                    } else {
+                        log::info!("Saved spline '{}' ({} points)", args, points.len());
+                    }
+                }
+                None => log::error!("Motor controller not initialized"),
+            }
+        },
+        "load_spline" => {
+            let splines = app_context.storage_manager.lock().unwrap().get_splines();
+            match splines.map(|mut s| s.remove(args)) {
+                Ok(Some(points)) => {
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.wave_func = "spline".to_string();
+                            config.spline.points = points;
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
                         } else {
-                            log::error!("Motor controller not initialized");
+                            log::info!("Loaded spline '{}'", args);
                         }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Ok(None) => log::error!("No spline named '{}'", args),
+                Err(e) => log::error!("Failed to read splines: {}", e),
+            }
+        },
+        "list_splines" => {
+            match app_context.storage_manager.lock().unwrap().get_splines() {
+                Ok(splines) => {
+                    for (name, points) in &splines {
+                        log::info!("  {} - {:?}", name, points);
+                    }
+                    if splines.is_empty() {
+                        log::info!("No saved splines");
                     }
-                    Err(_) => log::error!("Invalid spline points value: {}", args),
                 }
-            },
-            _ => {
-                log::error!("Unknown command: {}", command);
-                continue
-            },
+                Err(e) => log::error!("Failed to read splines: {}", e),
+            }
+        },
+        "delete_spline" => {
+            match app_context.storage_manager.lock().unwrap().delete_spline(args) {
+                Ok(true) => log::info!("Deleted spline '{}'", args),
+                Ok(false) => log::error!("No spline named '{}'", args),
+                Err(e) => log::error!("Failed to delete spline: {}", e),
+            }
+        },
+        "begin" => {
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                match mc.begin_transaction() {
+                    Ok(()) => log::info!("Transaction started, config changes apply together on commit"),
+                    Err(e) => log::error!("{}", e),
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        "commit" => {
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                match mc.commit_transaction() {
+                    Ok(changed) => log::info!("Transaction committed, {} fields changed", changed),
+                    Err(e) => log::error!("Failed to commit: {}", e),
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        "abort" => {
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                if mc.abort_transaction() {
+                    log::info!("Transaction aborted, nothing changed");
+                } else {
+                    log::error!("No transaction open");
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        "help" => {
+            log::info!("Available commands:");
+            log::info!("  help                           - Show this help message");
+            log::info!("  set_wifi_ssid <ssid>                - Set WiFi SSID");
+            log::info!("  set_wifi_password <password>        - Set WiFi password");
+            log::info!("  set_http_port <port>           - Set HTTP server port");
+            log::info!("  set_cors_origin <origin>       - Set allowed CORS origin ('*' for any)");
+            log::info!("  set_mqtt_url <url|off>         - Publish to an MQTT broker, e.g. mqtt://192.168.1.10:1883");
+            log::info!("  set_mqtt_credentials <user> <password> - Set the MQTT login, or none");
+            log::info!("  set_mqtt_topic_prefix <prefix> - Set the MQTT topic prefix (default ossm)");
+            log::info!("  set_mqtt_interval <ms>         - Set how often state and health are published (default 1000)");
+            log::info!("  set_ntp_server <host|off>      - Sync the clock from this NTP server (default pool.ntp.org)");
+            log::info!("  set_timezone <tz>              - Set the timezone for log times as a POSIX TZ string (default UTC0)");
+            log::info!("  set_schedule <schedule>        - Run on a schedule: 'daily <HH:MM> <HH:MM>', 'for <duration>' or 'in <delay> for <duration>'");
+            log::info!("  clear_schedule                 - Remove the schedule");
+            log::info!("  get_pin_configuration          - Get pin configuration in JSON format");
+            log::info!("  get_available_pins             - List which GPIOs are free for the pin settings and what uses the others");
+            log::info!("  set_pin_modbus_tx <pin> [live] - Set Modbus TX pin, 'live' applies it without a reboot");
+            log::info!("  set_pin_modbus_rx <pin> [live] - Set Modbus RX pin, 'live' applies it without a reboot");
+            log::info!("  set_pin_modbus_de_re <pin> [live] - Set Modbus DE/RE pin, 'live' applies it without a reboot");
+            log::info!("  set_status_led <off|gpio|ws2812> [pin] - Set up a status LED (gpio takes an optional active_low)");
+            log::info!("  set_buzzer <pin|off>           - Beep on a piezo buzzer for homing, faults and finished patterns");
+            log::info!("  set_interlock <pin> [active_high] | off - Only run while an enable switch on the pin is closed (default: to GND)");
+            log::info!("  set_limit_switches <min|none> <max|none> [active_high] | off - Halt when a switch at either end of travel is pressed (default: to GND)");
+            log::info!("  set_modbus_framing <8N1|8E1|8O1|8N2> - Set Modbus parity and stop bits (default 8N1)");
+            log::info!("  set_position_format <low_first|high_first> <signed|unsigned> - How the motor stores its 32-bit position (default low_first signed)");
+            log::info!("  set_de_re_polarity <active_high|active_low> - Level on DE/RE while sending, for inverted transceiver wiring (default active_high)");
+            log::info!("  set_de_re_control <rts|gpio>   - Drive DE/RE from the UART RTS or as a plain GPIO around each frame (default rts)");
+            log::info!("  set_uart_buffers <rx> <tx>     - UART driver buffer sizes in bytes, more RX helps against dropped bytes (default 1024 512)");
+            log::info!("  modbus_set_device_id <id>      - Restart and change the motor's Modbus device id (1 to 247)");
+            log::info!("  set_control_rate <hz>          - Set the motor loop rate (default 100 Hz)");
+            log::info!("  set_motor_task_priority <n>    - Set the motor task priority, 1-17 (default 10)");
+            log::info!("  set_motor_task_core <0|1>      - Pin the motor task to a core on dual-core chips (default 1)");
+            log::info!("  set_pause_after_brownout <bool> - Start paused after a brownout reset (default true)");
+            log::info!("  set_skip_homing <bool>         - Use the last homed range instead of homing, absolute encoders only (default false)");
+            log::info!("  set_homing_stability <threshold> <reads> - Reads in a row within threshold counts before homing trusts an end (default 10 1)");
+            log::info!("  set_homing_margins <min> <max> - Counts homing keeps clear of each end of travel (default 3000 3000)");
+            log::info!("  set_homing_speed <approach> <touch> [backoff] - Counts per second homing approaches and touches each end at, backing off in between (default 10000 2000 2000), 0 = one push");
+            log::info!("  set_homing_verify <tolerance> | off - Home twice and warn if the ends differ by more than tolerance counts, doubles the homing time (default off)");
+            log::info!("  diagnose_modbus                - Restart and check the Modbus wiring, printing a report");
+            log::info!("  reboot_safe_mode               - Restart once without starting the motor");
+            log::info!("  motor_stop_loop                - Park the motor and stop the motor loop");
+            log::info!("  motor_start_loop               - Start the motor loop again, initializing and homing the motor");
+            log::info!("  get_motor_config               - Get motor config in JSON format");
+            log::info!("  set_motor_config <json>        - Set motor config from a JSON string");
+            log::info!("  reset_motor_config             - Reset motor config to defaults, keeping pause state and position");
+            log::info!("  begin                          - Collect the following config changes, apply them together on commit");
+            log::info!("  commit                         - Apply the changes since begin as one update");
+            log::info!("  abort                          - Drop the changes since begin");
+            log::info!("  get_telemetry                  - Read position, speed, current, voltage, temperature and alarm code");
+            log::info!("  modbus_dump <start> <count>    - Print a block of holding registers (modbus-debug builds only)");
+            log::info!("  pause                          - Pause the motor");
+            log::info!("  start                          - Start the motor");
+            log::info!("  set_bpm <bpm>                  - Set motor BPM");
+            log::info!("  set_bpm_limits <min> <max>     - Limit the BPM this device accepts (1 to 500)");
+            log::info!("  set_bpm_depth_factor <k>       - Lower max BPM with depth: bpm_max * (1 - k * depth)");
+            log::info!("  get_bpm_limits                 - Get BPM limits in JSON format");
+            log::info!("  set_counts_per_mm <counts>     - Motor counts per mm of travel, to report lengths in mm (0 = clear)");
+            log::info!("  get_range                      - Get the homed range, in counts and mm");
+            log::info!("  set_range_policy <clamp|reject> - Clamp out of range config values from the API, or reject the update");
+            log::info!("  set_jog_limits <step> <max_step> <max_rate> - Jog step size, largest jog and fastest jog rate (default 0.01 0.1 0.3)");
+            log::info!("  set_jog_recenter <seconds> [center] - Drift back to center (default 0.5) after seconds without a jog, 0 = off");
+            log::info!("  get_jog_limits                 - Get jog limits in JSON format");
+            log::info!("  list_waves                     - List waveforms and the config fields they use");
+            log::info!("  set_wave <sine|thrust|spline>         - Set motor waveform");
+            log::info!("  set_paused_position <position> [fraction|counts|mm] - Set motor position when paused (default 0.0 to 1.0 of the full stroke)");
+            log::info!("  set_depth <depth>              - Set motor stroke depth (0.0 to 1.0)");
+            log::info!("  set_depth_top <true|false>     - Set depth direction");
+            log::info!("  set_sharpness <sharpness>      - Set sharpness for thrust wave (0.01 to 0.99)");
+            log::info!("  set_sharpness_speed <per_second> - How fast a running thrust eases into a new sharpness (default 0.2), 0 = at once");
+            log::info!("  set_acceleration <value>       - Set the drive's acceleration, applied at once (1000 to 60000, default 40000)");
+            log::info!("  set_spline_points <p1> <p2> ... - Set points for spline wave (0.0 to 1.0, up to 600 points)");
+            log::info!("  morph_spline <seconds> <p1> <p2>... - Blend into a new spline over the given time");
+            log::info!("  run_pattern <text>             - Run a pattern, e.g. sine 40bpm 30s; thrust 0.2 60bpm 60s");
+            log::info!("  stop_pattern                   - Stop the running pattern, keeping the current settings");
+            log::info!("  record_start                   - Record the carriage while you move it by hand (paused only)");
+            log::info!("  record_stop                    - Stop recording and play it back as a spline");
+            log::info!("  save_spline <name>             - Save the current spline points under a name");
+            log::info!("  load_spline <name>             - Switch to a saved spline");
+            log::info!("  list_splines                   - List saved splines");
+            log::info!("  delete_spline <name>           - Delete a saved spline");
+            log::info!("  set_dwell_top <seconds>        - Hold time at the top of each stroke");
+            log::info!("  set_dwell_bottom <seconds>     - Hold time at the bottom of each stroke");
+            log::info!("  set_speed_ratio <up> <down>    - Relative speed of the up and down strokes, bpm stays the same");
+            log::info!("  set_tease <probability> [intensity] - Chance per stroke to shorten or hold it (0 = off), intensity 0 to 1 (default 0.5)");
+            log::info!("  tease_seed <n>                 - Seed the tease rolls to repeat the same sequence");
+            log::info!("  set_escalation <step> [max] [reset|hold] - Deepen by step each stroke up to max (default 1.0), 0 = off");
+            log::info!("  set_output_smoothing <seconds> - Low-pass time constant on the output, 0 = off");
+            log::info!("  set_reversal_smoothing <seconds> - Extra smoothing at the stroke ends, 0 = off");
+            log::info!("  set_end_deadzone <fraction>    - Ease into the ends over this fraction of the stroke (0 to 0.25), 0 = off");
+            log::info!("  set_crossfade_time <seconds>   - Fade into a new waveform over this time while running (default 1), 0 = jump");
+            log::info!("  set_idle_park <seconds> [position] - Park at position (default 0.0) with reduced power after this long paused, 0 = off");
+            log::info!("  set_resync_on_unpause <true|false> - Re-read the motor position when unpausing");
+            log::info!("  set_warmup_on_unpause <true|false> - Re-send drive tuning and dither briefly before resuming");
+            log::info!("  set_power_save_on_pause <true|false> - Turn the drive power down while paused");
+            log::info!("  set_demo_mode <true|false>     - Slow demo motion after 30s paused and untouched");
+        },
+        "reset_motor_config" => {
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                if let Err(e) = mc.reset_config() {
+                    log::error!("Failed to reset motor config: {}", e);
+                } else {
+                    log::info!("Motor config reset to defaults");
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        "set_spline_points" => {
+            let points: Result<Vec<f32>, _> = args.split_whitespace().map(|s| s.parse::<f32>()).collect();
+            match points {
+                Ok(points) => {
+                    if let Err(e) = motion::check_spline_points(&points) {
+                        log::error!("{}", e);
+                        return;
+                    }
+                    if points.len() < 3 {
+                        log::warn!("{} spline point(s) only give {}, use 3 or more to shape the stroke",
+                            points.len(), if points.len() == 1 { "a fixed position" } else { "a plain back and forth" });
+                    }
+
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.update_config(|config| {
+                            config.spline.points = points.clone();
+                        }) {
+                            log::error!("Failed to set motor config: {}", e);
+                        } else {
+                            log::info!("Spline points set to {:?}", points);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(_) => log::error!("Invalid spline points value: {}", args),
+            }
+        },
+        "morph_spline" => {
+            let mut values = args.split_whitespace().map(|s| s.parse::<f32>());
+            let duration = match values.next() {
+                Some(Ok(duration)) if duration >= 0.0 => duration,
+                _ => {
+                    log::error!("Usage: morph_spline <seconds> <p1> <p2> ...");
+                    return;
+                }
+            };
+            match values.collect::<Result<Vec<f32>, _>>() {
+                Ok(points) => {
+                    if let Err(e) = motion::check_spline_points(&points) {
+                        log::error!("{}", e);
+                        return;
+                    }
+
+                    let mut mc_opt = app_context.motor_controller.lock().unwrap();
+                    if let Some(mc) = mc_opt.as_mut() {
+                        if let Err(e) = mc.morph_to_spline(points.clone(), duration) {
+                            log::error!("Failed to morph spline: {}", e);
+                        } else {
+                            log::info!("Morphing to {:?} over {}s", points, duration);
+                        }
+                    } else {
+                        log::error!("Motor controller not initialized");
+                    }
+                }
+                Err(_) => log::error!("Invalid spline points value: {}", args),
+            }
+        },
+        "run_pattern" => {
+            let pattern = match pattern::parse(args) {
+                Ok(pattern) => pattern,
+                Err(e) => {
+                    log::error!("Invalid pattern: {}", e);
+                    return;
+                }
+            };
+            let steps = pattern.steps.len();
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                if let Err(e) = mc.run_pattern(pattern) {
+                    log::error!("Failed to run pattern: {}", e);
+                } else {
+                    log::info!("Running pattern with {} steps", steps);
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        "stop_pattern" => {
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                if mc.stop_pattern() {
+                    log::info!("Pattern stopped");
+                } else {
+                    log::info!("No pattern running");
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        "record_start" => {
+            // Keep at least half of the free heap for everything else
+            let free_heap = unsafe { esp_idf_svc::sys::esp_get_free_heap_size() } as usize;
+            let max_samples = free_heap / 2 / std::mem::size_of::<f32>();
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                if let Err(e) = mc.start_recording(max_samples) {
+                    log::error!("Failed to start recording: {}", e);
+                } else {
+                    log::info!("Recording, move the carriage by hand and run record_stop when done");
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        "record_stop" => {
+            let mut mc_opt = app_context.motor_controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                match mc.stop_recording() {
+                    Ok(points) => log::info!("Recorded {} spline points at {:.1} BPM", points.len(), mc.get_config().bpm),
+                    Err(e) => log::error!("Failed to stop recording: {}", e),
+                }
+            } else {
+                log::error!("Motor controller not initialized");
+            }
+        },
+        _ => log::error!("Unknown command: {}", command),
+    }
+}
+
+// "<pin>" or "<pin> live"
+fn parse_pin_args(args: &str) -> (Option<u32>, bool) {
+    let mut parts = args.split_whitespace();
+    let pin = parts.next().and_then(|p| p.parse::<u32>().ok());
+    match parts.next() {
+        None => (pin, false),
+        Some("live") if parts.next().is_none() => (pin, true),
+        Some(_) => (None, false),
+    }
+}
+
+// Decimal, or hex with a 0x prefix
+#[cfg(feature = "modbus-debug")]
+fn parse_register_number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+// Stops the motor loop, which hands its pins back, and starts it again with the saved pin configuration
+fn restart_motor_loop(app_context: &AppContext) {
+    match app_context.status.lock().unwrap().init_state {
+        InitState::SafeMode => {
+            log::info!("The motor is not started in safe mode, reboot to apply");
+            return;
+        }
+        InitState::Stopped => {
+            log::info!("The motor loop is stopped, the new pins apply with motor_start_loop");
+            return;
+        }
+        _ => {}
+    }
+
+    app_context.motor_loop_enabled.store(false, Ordering::Relaxed);
+    let started = Instant::now();
+    while app_context.status.lock().unwrap().init_state != InitState::Stopped {
+        if started.elapsed() > LOOP_STOP_TIMEOUT {
+            log::error!("The motor loop did not stop, use motor_start_loop once it has");
+            return;
         }
+        FreeRtos::delay_ms(100);
     }
+    app_context.motor_loop_enabled.store(true, Ordering::Relaxed);
 }
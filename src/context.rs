@@ -1,11 +1,17 @@
 use crate::motion::MotorController;
+use crate::status::DeviceStatus;
 use crate::storage::StorageManager;
-use esp_idf_svc::hal::gpio::AnyIOPin;
+use crate::pins::PinPool;
+use crate::schedule::Scheduler;
+use std::sync::atomic::AtomicBool;
 use std::sync::{Arc, Mutex};
 
 #[derive(Clone)]
 pub struct AppContext {
     pub storage_manager: Arc<Mutex<Box<StorageManager>>>,
     pub motor_controller: Arc<Mutex<Option<Box<MotorController<'static>>>>>,
-    pub all_pins: Arc<Mutex<Vec<Option<AnyIOPin>>>>,
+    pub all_pins: PinPool,
+    pub status: Arc<Mutex<DeviceStatus>>,
+    pub motor_loop_enabled: Arc<AtomicBool>,    // Cleared to park the motor and stop the motor loop
+    pub scheduler: Arc<Mutex<Scheduler>>,
 }
@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    Rising,     // Became active
+    Falling,    // Became inactive
+}
+
+// Takes a level as settled once it has held for `hold`, so contact bounce and short spikes
+// don't come through. Apart from the pin in input.rs so it builds and is tested on the host.
+#[derive(Clone, Debug)]
+pub struct Debouncer {
+    hold: Duration,
+    stable: bool,
+    candidate: bool,
+    candidate_since: Instant,
+}
+
+impl Debouncer {
+    // Starts out settled at `level`
+    pub fn new(level: bool, hold: Duration, now: Instant) -> Self {
+        Self { hold, stable: level, candidate: level, candidate_since: now }
+    }
+
+    // Feeds a raw reading, returns the edge if the settled level changed
+    pub fn update(&mut self, level: bool, now: Instant) -> Option<Edge> {
+        if level != self.candidate {
+            self.candidate = level;
+            self.candidate_since = now;
+        }
+        if self.candidate == self.stable || now.duration_since(self.candidate_since) < self.hold {
+            return None;
+        }
+        self.stable = self.candidate;
+        Some(if self.stable { Edge::Rising } else { Edge::Falling })
+    }
+
+    pub fn level(&self) -> bool {
+        self.stable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOLD: Duration = Duration::from_millis(20);
+
+    fn ms(start: Instant, millis: u64) -> Instant {
+        start + Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn bounce_shorter_than_hold_is_ignored() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(false, HOLD, start);
+        for (millis, level) in [(1, true), (3, false), (5, true), (8, false), (15, true), (19, false), (30, false)] {
+            assert_eq!(debouncer.update(level, ms(start, millis)), None, "at {} ms", millis);
+        }
+        assert!(!debouncer.level());
+    }
+
+    #[test]
+    fn held_level_changes_once() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(false, HOLD, start);
+        assert_eq!(debouncer.update(true, ms(start, 10)), None);
+        assert_eq!(debouncer.update(true, ms(start, 40)), Some(Edge::Rising));
+        assert!(debouncer.level());
+        // Settled, no further edges while it stays there
+        assert_eq!(debouncer.update(true, ms(start, 50)), None);
+        assert_eq!(debouncer.update(false, ms(start, 60)), None);
+        assert_eq!(debouncer.update(false, ms(start, 90)), Some(Edge::Falling));
+        assert!(!debouncer.level());
+    }
+
+    #[test]
+    fn bouncing_restarts_the_hold() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(false, HOLD, start);
+        assert_eq!(debouncer.update(true, ms(start, 0)), None);
+        assert_eq!(debouncer.update(false, ms(start, 15)), None);
+        assert_eq!(debouncer.update(true, ms(start, 16)), None);
+        // 25 ms since the first press, but only 9 since the last bounce
+        assert_eq!(debouncer.update(true, ms(start, 25)), None);
+        assert_eq!(debouncer.update(true, ms(start, 36)), Some(Edge::Rising));
+    }
+
+    #[test]
+    fn settles_exactly_at_the_hold_time() {
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(true, HOLD, start);
+        assert_eq!(debouncer.update(false, ms(start, 5)), None);
+        assert_eq!(debouncer.update(false, ms(start, 24)), None);
+        assert_eq!(debouncer.update(false, ms(start, 25)), Some(Edge::Falling));
+    }
+}
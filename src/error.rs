@@ -0,0 +1,52 @@
+use esp_idf_svc::sys::{EspError, ESP_ERR_TIMEOUT};
+use thiserror::Error;
+
+// Errors from the motor and storage layers. Callers match on the kind: the HTTP API maps them
+// to status codes, the motor loop can tell a bus hiccup from a setting that will never work.
+#[derive(Debug, Error)]
+pub enum OssmError {
+    #[error("Modbus timeout, the motor did not answer")]
+    ModbusTimeout,
+    #[error("Modbus error: {0}")]
+    Modbus(String),             // Malformed or exception reply
+    #[error("UART error: {0}")]
+    Uart(EspError),
+    #[error("Homing failed: {0}")]
+    Homing(String),
+    #[error("Storage error: {0}")]
+    Storage(EspError),
+    #[error("Stored value is not valid JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("{0} not found")]
+    NotFound(String),
+    #[error("{0}")]
+    Invalid(String),            // Rejected input, the caller can fix it
+}
+
+pub type Result<T, E = OssmError> = std::result::Result<T, E>;
+
+impl OssmError {
+    pub fn invalid(message: impl Into<String>) -> Self {
+        OssmError::Invalid(message.into())
+    }
+
+    // A UART read that ran out of time means the motor didn't answer
+    pub fn uart(e: EspError) -> Self {
+        if e.code() == ESP_ERR_TIMEOUT {
+            OssmError::ModbusTimeout
+        } else {
+            OssmError::Uart(e)
+        }
+    }
+
+    // Errors on the motor link, as opposed to bad input or storage trouble
+    pub fn is_communication(&self) -> bool {
+        matches!(self, OssmError::ModbusTimeout | OssmError::Modbus(_) | OssmError::Uart(_))
+    }
+}
+
+impl From<rmodbus::ErrorKind> for OssmError {
+    fn from(e: rmodbus::ErrorKind) -> Self {
+        OssmError::Modbus(format!("{:?}", e))
+    }
+}
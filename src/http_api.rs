@@ -1,10 +1,18 @@
 use std::sync::{Arc, Mutex};
-use esp_idf_svc::http::server::{EspHttpServer, Method};
+use std::time;
+use esp_idf_svc::http::server::{EspHttpConnection, EspHttpServer, Method};
 use serde::{Deserialize, Serialize};
-use crate::motion::{MotorControllerConfig, MotorController};
+use crate::motion::{self, Clamped, MotorControllerConfig, MotorController};
 use esp_idf_svc::io::{Read, Write};
 use embedded_svc::http::Headers;
+use embedded_svc::http::server::Request;
 use crate::context::AppContext;
+use crate::error::OssmError;
+use crate::metrics;
+use crate::pattern;
+use crate::pins;
+use crate::status::{DeviceStatus, HealthReport, InitState};
+use crate::units::Units;
 
 #[derive(Serialize, Deserialize)]
 pub struct PausedControl {
@@ -13,55 +21,266 @@ pub struct PausedControl {
     pub adjust: Option<f32>,               // Adjust position relatively (positive or negative)
 }
 
+#[derive(Deserialize)]
+pub struct JogRequest {
+    pub delta: Option<f32>,                // Relative move of the paused position
+    pub steps: Option<f32>,                // Or a number of jog steps, see set_jog_limits
+}
+
+#[derive(Deserialize)]
+pub struct SplineRequest {
+    pub name: String,
+    pub points: Option<Vec<f32>>,          // Points to save, defaults to the current spline
+}
+
+#[derive(Deserialize)]
+pub struct PatternRequest {
+    pub pattern: String,
+}
+
 const APP_HTML: &str = include_str!("../frontend/dist/index.html");
 
+// FNV-1a, only used to derive a stable ETag for the embedded frontend
+fn fnv1a_64(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+// If-None-Match may hold a list of (possibly weak) tags, or "*"
+fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match
+        .split(',')
+        .map(|tag| tag.trim())
+        .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
+
+// Every response goes through here so no endpoint can miss the CORS headers
+#[derive(Clone)]
+struct Cors {
+    allow_origin: Arc<str>,
+}
+
+impl Cors {
+    fn new(allow_origin: &str) -> Self {
+        Self { allow_origin: allow_origin.into() }
+    }
+
+    fn respond(
+        &self,
+        req: Request<&mut EspHttpConnection>,
+        status: u16,
+        message: Option<&str>,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> anyhow::Result<()> {
+        let mut all_headers = vec![("Access-Control-Allow-Origin", &*self.allow_origin)];
+        if &*self.allow_origin != "*" {
+            // the header value depends on the configured origin, keep caches from mixing them up
+            all_headers.push(("Vary", "Origin"));
+        }
+        all_headers.extend_from_slice(headers);
+        req.into_response(status, message, &all_headers)?
+            .write_all(body)?;
+        Ok(())
+    }
+
+    fn respond_error(&self, req: Request<&mut EspHttpConnection>, e: &OssmError) -> anyhow::Result<()> {
+        let (status, message) = error_status(e);
+        self.respond(req, status, Some(message), &[], e.to_string().as_bytes())
+    }
+
+    // For errors from the motor controller. An OssmError inside picks the status, anything else
+    // is the controller turning the request down.
+    fn respond_controller_error(&self, req: Request<&mut EspHttpConnection>, e: &anyhow::Error) -> anyhow::Result<()> {
+        match e.downcast_ref::<OssmError>() {
+            Some(e) => self.respond_error(req, e),
+            None => self.respond(req, 400, Some("Bad Request"), &[], e.to_string().as_bytes()),
+        }
+    }
+
+    fn register_preflight(&self, server: &mut EspHttpServer, uri: &str, methods: &'static str) {
+        let cors = self.clone();
+        server.fn_handler::<anyhow::Error, _>(uri, Method::Options, move |req| {
+            cors.respond(req, 200, Some("OK"), &[
+                ("Access-Control-Allow-Methods", methods),
+                ("Access-Control-Allow-Headers", "*"),
+            ], &[])
+        }).unwrap();
+    }
+}
+
+// 503 body telling the client why there is no motor controller yet (e.g. still homing)
+fn not_ready_body(status: &Mutex<DeviceStatus>) -> String {
+    let status = status.lock().unwrap().clone();
+    serde_json::json!({
+        "error": "Motor controller not initialized",
+        "init_state": status.init_state,
+        "init_error": status.init_error,
+        "homing_phase": status.homing_phase,
+        "homing_elapsed": status.homing_elapsed(),
+        "motor_retry_in": status.motor_retry_in(),
+    }).to_string()
+}
+
+
+// Token bucket shared by all clients of one endpoint. The UI debounces its requests
+// (200 ms for /config, 100 ms for /paused), so these limits only bite on misbehaving clients.
+struct RateLimiter {
+    capacity: f32,
+    refill_per_sec: f32,
+    tokens: f32,
+    last_refill: time::Instant,
+}
+
+impl RateLimiter {
+    fn new(capacity: f32, refill_per_sec: f32) -> Self {
+        Self {
+            capacity,
+            refill_per_sec,
+            tokens: capacity,
+            last_refill: time::Instant::now(),
+        }
+    }
+
+    fn try_acquire(&mut self) -> bool {
+        let now = time::Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f32();
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+// Request body limits. /config has to fit a full config with a spline of a few hundred
+// points, at up to ~12 bytes per point in JSON.
+const CONFIG_BODY_LIMIT: usize = 16 * 1024;
+const PAUSED_BODY_LIMIT: usize = 4096;
+
+// Status code for an error from the motor or storage layer
+fn error_status(e: &OssmError) -> (u16, &'static str) {
+    match e {
+        OssmError::Invalid(_) => (400, "Bad Request"),
+        OssmError::NotFound(_) => (404, "Not Found"),
+        OssmError::ModbusTimeout => (504, "Gateway Timeout"),
+        OssmError::Modbus(_) | OssmError::Uart(_) => (502, "Bad Gateway"),
+        OssmError::Homing(_) | OssmError::Storage(_) | OssmError::Json(_) => (500, "Internal Server Error"),
+    }
+}
+
+fn too_large_body(len: usize, limit: usize) -> String {
+    format!("Request body too large: {} bytes, the limit is {} bytes", len, limit)
+}
+
+// "/state?format=binary", "format" -> "binary"
+fn query_param<'a>(uri: &'a str, name: &str) -> Option<&'a str> {
+    let query = uri.split_once('?').map_or("", |(_, query)| query);
+    query.split('&').find_map(|param| param.strip_prefix(name)?.strip_prefix('='))
+}
+
+// The `units` query parameter, fractions without one
+fn query_units(uri: &str) -> Result<Units, OssmError> {
+    query_param(uri, "units").map_or(Ok(Units::Fraction), Units::parse)
+}
+
+// "/config/bpm?x=1" -> "bpm"
+fn field_name(uri: &str, prefix: &str) -> String {
+    let path = uri.split('?').next().unwrap_or("");
+    path.strip_prefix(prefix).unwrap_or("").to_string()
+}
+
+// The config with paused_position in `units`, for responses
+fn config_in_units(mc: &MotorController, units: Units) -> Result<MotorControllerConfig, OssmError> {
+    let mut config = mc.get_config();
+    config.paused_position = mc.unit_converter().position_from_fraction(config.paused_position, units)?;
+    Ok(config)
+}
+
+// Responds with the config in `units`, or why it can't be given in them. Values that were
+// pulled into range are listed in X-Clamped.
+fn respond_config(req: Request<&mut EspHttpConnection>, cors: &Cors, mc: &MotorController, units: Units, clamped: &[Clamped]) -> anyhow::Result<()> {
+    match config_in_units(mc, units) {
+        Ok(config) => {
+            let json = serde_json::to_string(&config).unwrap();
+            let clamped = clamped.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+            // Exposed so browser clients can read it across origins
+            let header = [("X-Clamped", clamped.as_str()), ("Access-Control-Expose-Headers", "X-Clamped")];
+            let headers: &[(&str, &str)] = if clamped.is_empty() { &[] } else { &header };
+            cors.respond(req, 200, Some("OK"), headers, json.as_bytes())
+        }
+        Err(e) => cors.respond_error(req, &e),
+    }
+}
+
+// None: not ready yet, Some(None): no such field
+fn respond_field(req: Request<&mut EspHttpConnection>, cors: &Cors, status: &Mutex<DeviceStatus>, value: Option<Option<serde_json::Value>>) -> anyhow::Result<()> {
+    match value {
+        Some(Some(value)) => cors.respond(req, 200, Some("OK"), &[], value.to_string().as_bytes()),
+        Some(None) => cors.respond(req, 404, Some("Not Found"), &[], "No such field".as_bytes()),
+        None => cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(status).as_bytes()),
+    }
+}
+
+const CONFIG_RATE_BURST: f32 = 10.0;
+const CONFIG_RATE_PER_SEC: f32 = 5.0;
+const PAUSED_RATE_BURST: f32 = 20.0;
+const PAUSED_RATE_PER_SEC: f32 = 10.0;
+const JOG_RATE_BURST: f32 = 40.0;
+const JOG_RATE_PER_SEC: f32 = 30.0;
+
 pub fn register_handlers<'a>(
     server: &mut EspHttpServer<'a>,
     app_context: AppContext,
 ) {
+    let http_config = app_context.storage_manager.lock().unwrap().get_http_configuration().unwrap_or_default();
+    let cors = Cors::new(&http_config.allowed_origin);
+    log::info!("HTTP API allows origin: {}", http_config.allowed_origin);
+
     // CORS preflight handlers
     {
-        server.fn_handler::<anyhow::Error, _>("/config", Method::Options, |req| {
-            req.into_response(200, Some("OK"), &[
-                ("Access-Control-Allow-Origin", "*"),
-                ("Access-Control-Allow-Methods", "GET, POST, OPTIONS"),
-                ("Access-Control-Allow-Headers", "*"),
-            ])?
-                .write_all(&[])?;
-            Ok(())
-        }).unwrap();
-        server.fn_handler::<anyhow::Error, _>("/paused", Method::Options, |req| {
-            req.into_response(200, Some("OK"), &[
-                ("Access-Control-Allow-Origin", "*"),
-                ("Access-Control-Allow-Methods", "POST, OPTIONS"),
-                ("Access-Control-Allow-Headers", "*"),
-            ])?
-                .write_all(&[])?;
-            Ok(())
-        }).unwrap();
-        server.fn_handler::<anyhow::Error, _>("/state", Method::Options, |req| {
-            req.into_response(200, Some("OK"), &[
-                ("Access-Control-Allow-Origin", "*"),
-                ("Access-Control-Allow-Methods", "GET, OPTIONS"),
-                ("Access-Control-Allow-Headers", "*"),
-            ])?
-                .write_all(&[])?;
-            Ok(())
-        }).unwrap();
+        cors.register_preflight(server, "/config", "GET, POST, OPTIONS");
+        cors.register_preflight(server, "/config/reset", "POST, OPTIONS");
+        cors.register_preflight(server, "/paused", "POST, OPTIONS");
+        cors.register_preflight(server, "/jog", "POST, OPTIONS");
+        cors.register_preflight(server, "/state", "GET, OPTIONS");
+        cors.register_preflight(server, "/config/*", "GET, OPTIONS");
+        cors.register_preflight(server, "/state/*", "GET, OPTIONS");
+        cors.register_preflight(server, "/health", "GET, OPTIONS");
+        cors.register_preflight(server, "/ready", "GET, OPTIONS");
+        cors.register_preflight(server, "/range", "GET, OPTIONS");
+        cors.register_preflight(server, "/pins", "GET, OPTIONS");
+        cors.register_preflight(server, "/metrics", "GET, OPTIONS");
+        cors.register_preflight(server, "/waveforms", "GET, OPTIONS");
+        cors.register_preflight(server, "/telemetry", "GET, OPTIONS");
+        cors.register_preflight(server, "/splines", "GET, POST, OPTIONS");
+        cors.register_preflight(server, "/splines/load", "POST, OPTIONS");
+        cors.register_preflight(server, "/splines/delete", "POST, OPTIONS");
+        cors.register_preflight(server, "/pattern", "POST, OPTIONS");
+        cors.register_preflight(server, "/pattern/stop", "POST, OPTIONS");
     }
 
     {
         let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
         server.fn_handler::<anyhow::Error, _>("/config", Method::Get, move |req| {
-            let mut mc_opt = controller.lock().unwrap();
-            if let Some(mc) = mc_opt.as_mut() {
-                let config = mc.get_config();
-                let json = serde_json::to_string(&config).unwrap();
-                req.into_response(200, Some("OK"), &[("Access-Control-Allow-Origin", "*")])?
-                    .write_all(json.as_bytes())?;
+            let units = match query_units(req.uri()) {
+                Ok(units) => units,
+                Err(e) => return cors.respond_error(req, &e),
+            };
+            let mc_opt = controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_ref() {
+                respond_config(req, &cors, mc, units, &[])?;
             } else {
-                req.into_response(503, Some("Service Unavailable"), &[("Access-Control-Allow-Origin", "*")])?
-                    .write_all("Motor controller not initialized".as_bytes())?;
+                cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes())?;
             }
             Ok(())
         }).unwrap();
@@ -69,34 +288,52 @@ pub fn register_handlers<'a>(
 
     {
         let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        let limiter = Mutex::new(RateLimiter::new(CONFIG_RATE_BURST, CONFIG_RATE_PER_SEC));
         server.fn_handler::<anyhow::Error, _>("/config", Method::Post, move |mut req| {
+            if !limiter.lock().unwrap().try_acquire() {
+                cors.respond(req, 429, Some("Too Many Requests"), &[("Retry-After", "1")], "Too many requests".as_bytes())?;
+                return Ok(());
+            }
+            let units = match query_units(req.uri()) {
+                Ok(units) => units,
+                Err(e) => return cors.respond_error(req, &e),
+            };
+
             let len = req.content_len().unwrap_or(0) as usize;
-            if len > 1024 {
-                req.into_response(413, None, &[("Access-Control-Allow-Origin", "*")])?
-                    .write_all("Request too big".as_bytes())?;
+            if len > CONFIG_BODY_LIMIT {
+                cors.respond(req, 413, Some("Payload Too Large"), &[], too_large_body(len, CONFIG_BODY_LIMIT).as_bytes())?;
                 return Ok(());
             }
 
             let mut buf = vec![0; len];
             req.read_exact(&mut buf)?;
-            
-            match serde_json::from_slice::<MotorControllerConfig>(&buf) {
-                Ok(config) => {
-                    let json = serde_json::to_string(&config).unwrap();
+
+            match MotorControllerConfig::from_json(&buf) {
+                Ok(mut config) => {
                     let mut mc_opt = controller.lock().unwrap();
                     if let Some(mc) = mc_opt.as_mut() {
-                        mc.set_config(config).unwrap();
-                        req.into_response(200, Some("OK"), &[("Access-Control-Allow-Origin", "*")])?
-                            .write_all(json.as_bytes())?;
+                        match mc.unit_converter().position_to_fraction(config.paused_position, units) {
+                            Ok(position) => config.paused_position = position,
+                            Err(e) => return cors.respond_error(req, &e),
+                        }
+                        let clamped = match mc.check_ranges(&mut config) {
+                            Ok(clamped) => clamped,
+                            Err(e) => return cors.respond_error(req, &e),
+                        };
+                        if let Err(e) = mc.set_config(config) {
+                            return cors.respond(req, 400, Some("Bad Request"), &[], e.to_string().as_bytes());
+                        }
+                        // Send back what was applied
+                        respond_config(req, &cors, mc, units, &clamped)?;
                     } else {
-                        req.into_response(503, Some("Service Unavailable"), &[("Access-Control-Allow-Origin", "*")])?
-                            .write_all("Motor controller not initialized".as_bytes())?;
+                        cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes())?;
                     }
                 }
                 Err(e) => {
-                    log::error!("Failed to parse config: {}", e);
-                    req.into_response(400, None, &[("Access-Control-Allow-Origin", "*")])?
-                        .write_all("Bad Request".as_bytes())?;
+                    log::error!("Failed to parse config: {}", e.to_string().lines().next().unwrap_or(""));
+                    cors.respond_error(req, &e)?;
                 }
             }
             Ok(())
@@ -105,11 +342,47 @@ pub fn register_handlers<'a>(
 
     {
         let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        let limiter = Mutex::new(RateLimiter::new(CONFIG_RATE_BURST, CONFIG_RATE_PER_SEC));
+        server.fn_handler::<anyhow::Error, _>("/config/reset", Method::Post, move |req| {
+            if !limiter.lock().unwrap().try_acquire() {
+                cors.respond(req, 429, Some("Too Many Requests"), &[("Retry-After", "1")], "Too many requests".as_bytes())?;
+                return Ok(());
+            }
+
+            let mut mc_opt = controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                if let Err(e) = mc.reset_config() {
+                    return cors.respond_controller_error(req, &e);
+                }
+                let json = serde_json::to_string(&mc.get_config()).unwrap();
+                cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+            } else {
+                cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes())?;
+            }
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        let limiter = Mutex::new(RateLimiter::new(PAUSED_RATE_BURST, PAUSED_RATE_PER_SEC));
         server.fn_handler::<anyhow::Error, _>("/paused", Method::Post, move |mut req| {
+            if !limiter.lock().unwrap().try_acquire() {
+                cors.respond(req, 429, Some("Too Many Requests"), &[("Retry-After", "1")], "Too many requests".as_bytes())?;
+                return Ok(());
+            }
+            let units = match query_units(req.uri()) {
+                Ok(units) => units,
+                Err(e) => return cors.respond_error(req, &e),
+            };
+
             let len = req.content_len().unwrap_or(0) as usize;
-            if len > 4096 {
-                req.into_response(413, None, &[("Access-Control-Allow-Origin", "*")])?
-                    .write_all("Request too big".as_bytes())?;
+            if len > PAUSED_BODY_LIMIT {
+                cors.respond(req, 413, Some("Payload Too Large"), &[], too_large_body(len, PAUSED_BODY_LIMIT).as_bytes())?;
                 return Ok(());
             }
 
@@ -121,57 +394,512 @@ pub fn register_handlers<'a>(
                     let mut mc_opt = controller.lock().unwrap();
                     if let Some(mc) = mc_opt.as_mut() {
                         let mut config = mc.get_config();
+                        let previous_position = config.paused_position;
+                        let converter = mc.unit_converter();
+                        let position = control.position.map(|position| converter.position_to_fraction(position, units)).transpose();
+                        let adjust = control.adjust.map(|adjust| converter.length_to_fraction(adjust, units)).transpose();
+                        let (position, adjust) = match (position, adjust) {
+                            (Ok(position), Ok(adjust)) => (position, adjust),
+                            (Err(e), _) | (_, Err(e)) => return cors.respond_error(req, &e),
+                        };
 
                         if let Some(paused) = control.paused {
                             config.paused = paused;
                         }
-                        if let Some(position) = control.position {
-                            config.paused_position = position.max(0.0).min(1.0);
+                        if let Some(position) = position {
+                            config.paused_position = position;
                         }
-                        if let Some(adjust) = control.adjust {
-                            config.paused_position = (config.paused_position + adjust).max(0.0).min(1.0);
+                        if let Some(adjust) = adjust {
+                            config.paused_position += adjust;
                         }
 
-                        mc.set_config(config.clone()).unwrap();
-                        let json = serde_json::to_string(&config).unwrap();
-                        req.into_response(200, Some("OK"), &[("Access-Control-Allow-Origin", "*")])?
-                            .write_all(json.as_bytes())?;
+                        let clamped = match mc.check_ranges(&mut config) {
+                            Ok(clamped) => clamped,
+                            Err(e) => return cors.respond_error(req, &e),
+                        };
+                        // Moving the parked carriage alone skips the full config update, which
+                        // keeps rapid adjusts from a slider or buttons responsive
+                        let result = if control.paused.map_or(true, |paused| paused == mc.is_paused()) {
+                            if config.paused_position != previous_position {
+                                mc.move_paused_position(config.paused_position)
+                            } else {
+                                Ok(())
+                            }
+                        } else {
+                            mc.set_config(config)
+                        };
+                        if let Err(e) = result {
+                            return cors.respond(req, 400, Some("Bad Request"), &[], e.to_string().as_bytes());
+                        }
+                        respond_config(req, &cors, mc, units, &clamped)?;
                     } else {
-                        req.into_response(503, Some("Service Unavailable"), &[("Access-Control-Allow-Origin", "*")])?
-                            .write_all("Motor controller not initialized".as_bytes())?;
+                        cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes())?;
                     }
                 }
                 Err(e) => {
                     log::error!("Failed to parse paused control: {}", e);
-                    req.into_response(400, None, &[("Access-Control-Allow-Origin", "*")])?
-                        .write_all("Bad Request".as_bytes())?;
+                    cors.respond(req, 400, None, &[], "Bad Request".as_bytes())?;
                 }
             }
             Ok(())
         }).unwrap();
     }
 
+    // Moves the parked carriage with as little work as possible, for sliders and buttons
+    {
+        let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        let limiter = Mutex::new(RateLimiter::new(JOG_RATE_BURST, JOG_RATE_PER_SEC));
+        server.fn_handler::<anyhow::Error, _>("/jog", Method::Post, move |mut req| {
+            if !limiter.lock().unwrap().try_acquire() {
+                cors.respond(req, 429, Some("Too Many Requests"), &[("Retry-After", "1")], "Too many requests".as_bytes())?;
+                return Ok(());
+            }
+            let units = match query_units(req.uri()) {
+                Ok(units) => units,
+                Err(e) => return cors.respond_error(req, &e),
+            };
+
+            let len = req.content_len().unwrap_or(0) as usize;
+            if len > PAUSED_BODY_LIMIT {
+                cors.respond(req, 413, Some("Payload Too Large"), &[], too_large_body(len, PAUSED_BODY_LIMIT).as_bytes())?;
+                return Ok(());
+            }
+
+            let mut buf = vec![0; len];
+            req.read_exact(&mut buf)?;
+
+            let request = match serde_json::from_slice::<JogRequest>(&buf) {
+                Ok(request) => request,
+                Err(e) => return cors.respond(req, 400, Some("Bad Request"), &[], format!("Invalid jog request: {}", e).as_bytes()),
+            };
+            let mut mc_opt = controller.lock().unwrap();
+            let Some(mc) = mc_opt.as_mut() else {
+                return cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes());
+            };
+            if !mc.is_paused() {
+                return cors.respond(req, 409, Some("Conflict"), &[], "Jogging only works while paused".as_bytes());
+            }
+            if !mc.interlock_engaged() {
+                return cors.respond(req, 409, Some("Conflict"), &[], "The interlock is open".as_bytes());
+            }
+            let converter = mc.unit_converter();
+            let delta = match (request.delta, request.steps) {
+                (Some(delta), None) => converter.length_to_fraction(delta, units),
+                (None, Some(steps)) => Ok(steps * mc.get_jog_limits().step),
+                _ => Err(OssmError::invalid("Give either delta or steps")),
+            };
+            let delta = match delta {
+                Ok(delta) => delta,
+                Err(e) => return cors.respond_error(req, &e),
+            };
+            let position = match mc.jog(delta) {
+                Ok(position) => position,
+                Err(e) => return cors.respond(req, 400, Some("Bad Request"), &[], e.to_string().as_bytes()),
+            };
+            match converter.position_from_fraction(position, units) {
+                Ok(position) => {
+                    let json = serde_json::json!({ "paused_position": position }).to_string();
+                    cors.respond(req, 200, Some("OK"), &[], json.as_bytes())
+                }
+                Err(e) => cors.respond_error(req, &e),
+            }
+        }).unwrap();
+    }
+
     {
         let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
         server.fn_handler::<anyhow::Error, _>("/state", Method::Get, move |req| {
+            let binary = match query_param(req.uri(), "format") {
+                None | Some("json") => false,
+                Some("binary") => true,
+                Some(format) => {
+                    let e = OssmError::invalid(format!("Unknown format \"{}\", use json or binary", format));
+                    return cors.respond_error(req, &e);
+                }
+            };
             let mut mc_opt = controller.lock().unwrap();
             if let Some(mc) = mc_opt.as_mut() {
                 let state = mc.get_current_state();
-                let json = serde_json::to_string(&state).unwrap();
-                req.into_response(200, Some("OK"), &[("Access-Control-Allow-Origin", "*")])?
-                    .write_all(json.as_bytes())?;
+                if binary {
+                    cors.respond(req, 200, Some("OK"), &[("Content-Type", "application/octet-stream")], &state.to_binary())?;
+                } else {
+                    let json = serde_json::to_string(&state).unwrap();
+                    cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+                }
             } else {
-                req.into_response(503, Some("Service Unavailable"), &[("Access-Control-Allow-Origin", "*")])?
-                    .write_all("Motor controller not initialized".as_bytes())?;
+                cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes())?;
             }
             Ok(())
         }).unwrap();
     }
 
+    // Single fields, GET /config/<field> and GET /state/<field>, for clients polling one value
     {
+        let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        server.fn_handler::<anyhow::Error, _>("/config/*", Method::Get, move |req| {
+            let name = field_name(req.uri(), "/config/");
+            let config = match query_units(req.uri()) {
+                Ok(units) => controller.lock().unwrap().as_ref().map(|mc| config_in_units(mc, units)).transpose(),
+                Err(e) => Err(e),
+            };
+            match config {
+                Ok(config) => respond_field(req, &cors, &status, config.map(|config| config.field(&name))),
+                Err(e) => cors.respond_error(req, &e),
+            }
+        }).unwrap();
+    }
+
+    {
+        let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        server.fn_handler::<anyhow::Error, _>("/state/*", Method::Get, move |req| {
+            let name = field_name(req.uri(), "/state/");
+            let value = controller.lock().unwrap().as_ref().map(|mc| mc.get_current_state().field(&name));
+            respond_field(req, &cors, &status, value)
+        }).unwrap();
+    }
+
+    {
+        let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        server.fn_handler::<anyhow::Error, _>("/range", Method::Get, move |req| {
+            let range = controller.lock().unwrap().as_ref().map(|mc| mc.range_info());
+            match range {
+                Some(range) => {
+                    let json = serde_json::to_string(&range).unwrap();
+                    cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+                }
+                None => cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes())?,
+            }
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        server.fn_handler::<anyhow::Error, _>("/telemetry", Method::Get, move |req| {
+            let mut mc_opt = controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                match mc.read_telemetry() {
+                    Ok(telemetry) => {
+                        let json = serde_json::to_string(&telemetry).unwrap();
+                        cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+                    }
+                    Err(e) => {
+                        log::error!("Failed to read telemetry: {}", e);
+                        cors.respond_error(req, &e)?;
+                    }
+                }
+            } else {
+                cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes())?;
+            }
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let storage = app_context.storage_manager.clone();
+        let cors = cors.clone();
+        server.fn_handler::<anyhow::Error, _>("/splines", Method::Get, move |req| {
+            match storage.lock().unwrap().get_splines() {
+                Ok(splines) => {
+                    let json = serde_json::to_string(&splines).unwrap();
+                    cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+                }
+                Err(e) => {
+                    log::error!("Failed to read splines: {}", e);
+                    cors.respond_error(req, &e)?;
+                }
+            }
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let controller = app_context.motor_controller.clone();
+        let storage = app_context.storage_manager.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        let limiter = Mutex::new(RateLimiter::new(CONFIG_RATE_BURST, CONFIG_RATE_PER_SEC));
+        server.fn_handler::<anyhow::Error, _>("/splines", Method::Post, move |mut req| {
+            if !limiter.lock().unwrap().try_acquire() {
+                cors.respond(req, 429, Some("Too Many Requests"), &[("Retry-After", "1")], "Too many requests".as_bytes())?;
+                return Ok(());
+            }
+            let len = req.content_len().unwrap_or(0) as usize;
+            if len > CONFIG_BODY_LIMIT {
+                cors.respond(req, 413, Some("Payload Too Large"), &[], too_large_body(len, CONFIG_BODY_LIMIT).as_bytes())?;
+                return Ok(());
+            }
+
+            let mut buf = vec![0; len];
+            req.read_exact(&mut buf)?;
+
+            let request = match serde_json::from_slice::<SplineRequest>(&buf) {
+                Ok(request) => request,
+                Err(e) => {
+                    log::error!("Failed to parse spline: {}", e);
+                    cors.respond(req, 400, None, &[], "Bad Request".as_bytes())?;
+                    return Ok(());
+                }
+            };
+            let points = match request.points {
+                Some(points) => points,
+                None => match controller.lock().unwrap().as_ref() {
+                    Some(mc) => mc.get_config().spline.points,
+                    None => {
+                        cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes())?;
+                        return Ok(());
+                    }
+                },
+            };
+            match storage.lock().unwrap().save_spline(&request.name, &points) {
+                Ok(()) => cors.respond(req, 200, Some("OK"), &[], "OK".as_bytes())?,
+                Err(e) => cors.respond_error(req, &e)?,
+            }
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let controller = app_context.motor_controller.clone();
+        let storage = app_context.storage_manager.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        let limiter = Mutex::new(RateLimiter::new(CONFIG_RATE_BURST, CONFIG_RATE_PER_SEC));
+        server.fn_handler::<anyhow::Error, _>("/splines/load", Method::Post, move |mut req| {
+            if !limiter.lock().unwrap().try_acquire() {
+                cors.respond(req, 429, Some("Too Many Requests"), &[("Retry-After", "1")], "Too many requests".as_bytes())?;
+                return Ok(());
+            }
+            let len = req.content_len().unwrap_or(0) as usize;
+            if len > PAUSED_BODY_LIMIT {
+                cors.respond(req, 413, Some("Payload Too Large"), &[], too_large_body(len, PAUSED_BODY_LIMIT).as_bytes())?;
+                return Ok(());
+            }
+
+            let mut buf = vec![0; len];
+            req.read_exact(&mut buf)?;
+
+            let request = match serde_json::from_slice::<SplineRequest>(&buf) {
+                Ok(request) => request,
+                Err(e) => {
+                    log::error!("Failed to parse spline: {}", e);
+                    cors.respond(req, 400, None, &[], "Bad Request".as_bytes())?;
+                    return Ok(());
+                }
+            };
+            let splines = storage.lock().unwrap().get_splines();
+            let points = match splines.map(|mut s| s.remove(&request.name)) {
+                Ok(Some(points)) => points,
+                Ok(None) => {
+                    cors.respond(req, 404, Some("Not Found"), &[], "No spline with that name".as_bytes())?;
+                    return Ok(());
+                }
+                Err(e) => {
+                    cors.respond_error(req, &e)?;
+                    return Ok(());
+                }
+            };
+            let mut mc_opt = controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                let result = mc.update_config(|config| {
+                    config.wave_func = "spline".to_string();
+                    config.spline.points = points;
+                });
+                if let Err(e) = result {
+                    return cors.respond_controller_error(req, &e);
+                }
+                let json = serde_json::to_string(&mc.get_config()).unwrap();
+                cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+            } else {
+                cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes())?;
+            }
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let storage = app_context.storage_manager.clone();
+        let cors = cors.clone();
+        let limiter = Mutex::new(RateLimiter::new(CONFIG_RATE_BURST, CONFIG_RATE_PER_SEC));
+        server.fn_handler::<anyhow::Error, _>("/splines/delete", Method::Post, move |mut req| {
+            if !limiter.lock().unwrap().try_acquire() {
+                cors.respond(req, 429, Some("Too Many Requests"), &[("Retry-After", "1")], "Too many requests".as_bytes())?;
+                return Ok(());
+            }
+            let len = req.content_len().unwrap_or(0) as usize;
+            if len > PAUSED_BODY_LIMIT {
+                cors.respond(req, 413, Some("Payload Too Large"), &[], too_large_body(len, PAUSED_BODY_LIMIT).as_bytes())?;
+                return Ok(());
+            }
+
+            let mut buf = vec![0; len];
+            req.read_exact(&mut buf)?;
+
+            match serde_json::from_slice::<SplineRequest>(&buf) {
+                Ok(request) => match storage.lock().unwrap().delete_spline(&request.name) {
+                    Ok(true) => cors.respond(req, 200, Some("OK"), &[], "OK".as_bytes())?,
+                    Ok(false) => cors.respond(req, 404, Some("Not Found"), &[], "No spline with that name".as_bytes())?,
+                    Err(e) => cors.respond_error(req, &e)?,
+                },
+                Err(e) => {
+                    log::error!("Failed to parse spline: {}", e);
+                    cors.respond(req, 400, None, &[], "Bad Request".as_bytes())?;
+                }
+            }
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        let limiter = Mutex::new(RateLimiter::new(CONFIG_RATE_BURST, CONFIG_RATE_PER_SEC));
+        server.fn_handler::<anyhow::Error, _>("/pattern", Method::Post, move |mut req| {
+            if !limiter.lock().unwrap().try_acquire() {
+                cors.respond(req, 429, Some("Too Many Requests"), &[("Retry-After", "1")], "Too many requests".as_bytes())?;
+                return Ok(());
+            }
+            let len = req.content_len().unwrap_or(0) as usize;
+            if len > CONFIG_BODY_LIMIT {
+                cors.respond(req, 413, Some("Payload Too Large"), &[], too_large_body(len, CONFIG_BODY_LIMIT).as_bytes())?;
+                return Ok(());
+            }
+
+            let mut buf = vec![0; len];
+            req.read_exact(&mut buf)?;
+
+            let pattern = match serde_json::from_slice::<PatternRequest>(&buf) {
+                Ok(request) => match pattern::parse(&request.pattern) {
+                    Ok(pattern) => pattern,
+                    Err(e) => {
+                        cors.respond(req, 400, None, &[], e.to_string().as_bytes())?;
+                        return Ok(());
+                    }
+                },
+                Err(e) => {
+                    log::error!("Failed to parse pattern request: {}", e);
+                    cors.respond(req, 400, None, &[], "Bad Request".as_bytes())?;
+                    return Ok(());
+                }
+            };
+            let mut mc_opt = controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                if let Err(e) = mc.run_pattern(pattern) {
+                    return cors.respond_controller_error(req, &e);
+                }
+                let json = serde_json::to_string(&mc.get_config()).unwrap();
+                cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+            } else {
+                cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes())?;
+            }
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let controller = app_context.motor_controller.clone();
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        let limiter = Mutex::new(RateLimiter::new(CONFIG_RATE_BURST, CONFIG_RATE_PER_SEC));
+        server.fn_handler::<anyhow::Error, _>("/pattern/stop", Method::Post, move |req| {
+            if !limiter.lock().unwrap().try_acquire() {
+                cors.respond(req, 429, Some("Too Many Requests"), &[("Retry-After", "1")], "Too many requests".as_bytes())?;
+                return Ok(());
+            }
+            let mut mc_opt = controller.lock().unwrap();
+            if let Some(mc) = mc_opt.as_mut() {
+                mc.stop_pattern();
+                let json = serde_json::to_string(&mc.get_config()).unwrap();
+                cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+            } else {
+                cors.respond(req, 503, Some("Service Unavailable"), &[], not_ready_body(&status).as_bytes())?;
+            }
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        server.fn_handler::<anyhow::Error, _>("/health", Method::Get, move |req| {
+            let health = HealthReport::new(status.lock().unwrap().clone());
+            let json = serde_json::to_string(&health).unwrap();
+            cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+            Ok(())
+        }).unwrap();
+    }
+
+    // Polled during boot, so only the init state is copied out of the status
+    {
+        let status = app_context.status.clone();
+        let cors = cors.clone();
+        server.fn_handler::<anyhow::Error, _>("/ready", Method::Get, move |req| {
+            let init_state = status.lock().unwrap().init_state;
+            let json = serde_json::json!({
+                "ready": init_state == InitState::Ready,
+                "phase": init_state,
+            }).to_string();
+            cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let app_context = app_context.clone();
+        let cors = cors.clone();
+        server.fn_handler::<anyhow::Error, _>("/metrics", Method::Get, move |req| {
+            let body = metrics::render(&app_context);
+            cors.respond(req, 200, Some("OK"), &[("Content-Type", "text/plain; version=0.0.4")], body.as_bytes())?;
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let all_pins = app_context.all_pins.clone();
+        let storage = app_context.storage_manager.clone();
+        let cors = cors.clone();
+        server.fn_handler::<anyhow::Error, _>("/pins", Method::Get, move |req| {
+            let report = pins::pin_report(&all_pins, &storage.lock().unwrap());
+            let json = serde_json::to_string(&report).unwrap();
+            cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        let cors = cors.clone();
+        server.fn_handler::<anyhow::Error, _>("/waveforms", Method::Get, move |req| {
+            let json = serde_json::to_string(&motion::waveform_infos()).unwrap();
+            cors.respond(req, 200, Some("OK"), &[], json.as_bytes())?;
+            Ok(())
+        }).unwrap();
+    }
+
+    {
+        // The page only changes with a firmware update, so hash it once at startup.
+        // "no-cache" still lets the browser keep a copy, it just has to revalidate it.
+        let etag = format!("\"{:016x}\"", fnv1a_64(APP_HTML.as_bytes()));
+        let cors = cors.clone();
         server.fn_handler::<anyhow::Error, _>("/", Method::Get, move |req| {
-            req.into_response(200, Some("OK"), &[("Access-Control-Allow-Origin", "*"), ("Content-Type", "text/html")])?
-                .write_all(APP_HTML.as_bytes())?;
+            let not_modified = req.header("If-None-Match").is_some_and(|v| etag_matches(v, &etag));
+            if not_modified {
+                cors.respond(req, 304, Some("Not Modified"), &[("ETag", &etag), ("Cache-Control", "no-cache")], &[])?;
+                return Ok(());
+            }
+            cors.respond(req, 200, Some("OK"), &[("Content-Type", "text/html"), ("ETag", &etag), ("Cache-Control", "no-cache")], APP_HTML.as_bytes())?;
             Ok(())
         }).unwrap();
     }
@@ -0,0 +1,46 @@
+use std::time::{Duration, Instant};
+
+use esp_idf_svc::hal::gpio::{AnyIOPin, Input, PinDriver, Pull};
+
+use crate::debounce::{Debouncer, Edge};
+use crate::pins::{PinLease, PinPool};
+
+// Switch contacts settle within a few ms, this leaves margin without delaying a halt noticeably
+pub const DEFAULT_DEBOUNCE: Duration = Duration::from_millis(20);
+
+// A GPIO input taken from the pool, read as active/inactive with the wiring's polarity and
+// debounced. Pulled to the inactive level, so a broken wire reads as inactive.
+pub struct DebouncedInput {
+    driver: PinDriver<'static, AnyIOPin, Input>,
+    active_low: bool,
+    debouncer: Debouncer,
+    _pin_lease: PinLease,   // Declared after the driver so the pin is handed back once it is gone
+}
+
+impl DebouncedInput {
+    pub fn take(pool: &PinPool, pin: u32, active_low: bool, debounce: Duration, role: &str) -> anyhow::Result<Self> {
+        let Some((pin_lease, [io])) = PinLease::take(pool, [pin as usize]) else {
+            anyhow::bail!("GPIO{} is not available for the {}", pin, role);
+        };
+        let mut driver = PinDriver::input(io)?;
+        driver.set_pull(if active_low { Pull::Up } else { Pull::Down })?;
+        let active = driver.is_low() == active_low;
+        Ok(Self {
+            driver,
+            active_low,
+            debouncer: Debouncer::new(active, debounce, Instant::now()),
+            _pin_lease: pin_lease,
+        })
+    }
+
+    // Reads the pin, call it regularly. Returns the edge if the debounced level changed.
+    pub fn poll(&mut self) -> Option<Edge> {
+        let active = self.driver.is_low() == self.active_low;
+        self.debouncer.update(active, Instant::now())
+    }
+
+    // Debounced level as of the last poll
+    pub fn is_active(&self) -> bool {
+        self.debouncer.level()
+    }
+}
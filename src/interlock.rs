@@ -0,0 +1,28 @@
+use crate::input::{self, DebouncedInput};
+use crate::pins::PinPool;
+use crate::storage::InterlockConfiguration;
+
+// Enable switch the motor only runs with. While it is open the motor stays paused where it is,
+// and closing it again doesn't resume by itself: that takes a start command as well.
+pub struct Interlock {
+    input: DebouncedInput,
+}
+
+impl Interlock {
+    // None without an interlock configured. A configured one that can't be set up is an error,
+    // the motor must not run without it.
+    pub fn start(pool: &PinPool, config: &InterlockConfiguration) -> anyhow::Result<Option<Self>> {
+        if !config.enabled {
+            return Ok(None);
+        }
+        // Pulled to the open level, so a broken wire stops the motor too
+        let input = DebouncedInput::take(pool, config.pin, config.active_low, input::DEFAULT_DEBOUNCE, "interlock")?;
+        log::info!("Interlock on GPIO{}, closed when {}", config.pin, if config.active_low { "low" } else { "high" });
+        Ok(Some(Self { input }))
+    }
+
+    pub fn engaged(&mut self) -> bool {
+        self.input.poll();
+        self.input.is_active()
+    }
+}
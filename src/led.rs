@@ -0,0 +1,140 @@
+use std::time::{Duration, Instant};
+
+use esp_idf_svc::hal::gpio::{AnyIOPin, Output, PinDriver};
+use esp_idf_svc::hal::rmt::config::TransmitConfig;
+use esp_idf_svc::hal::rmt::{FixedLengthSignal, PinState, Pulse, TxRmtDriver, CHANNEL0};
+
+use crate::context::AppContext;
+use crate::pins::PinLease;
+use crate::status::InitState;
+use crate::storage::{LedConfiguration, LedKind};
+
+const UPDATE_INTERVAL_MS: u64 = 50;
+const LED_TASK_STACK_SIZE: usize = 4096;
+const WS2812_BRIGHTNESS: u8 = 32;   // Out of 255, full brightness is blinding up close
+
+// What the LED shows, from the device status and the motor config
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LedState {
+    Booting,
+    Homing,
+    Running,
+    Paused,
+    Stopped,
+    Fault,
+    SafeMode,
+}
+
+impl LedState {
+    fn current(app_context: &AppContext) -> Self {
+        let (init_state, fault) = {
+            let status = app_context.status.lock().unwrap();
+            (status.init_state, status.motor_fault.is_some())
+        };
+        match init_state {
+            InitState::Booting | InitState::NoMotor => LedState::Booting,
+            InitState::Homing => LedState::Homing,
+            InitState::Failed => LedState::Fault,
+            InitState::SafeMode => LedState::SafeMode,
+            InitState::Stopped => LedState::Stopped,
+            InitState::Ready if fault => LedState::Fault,
+            InitState::Ready => {
+                let paused = app_context.motor_controller.lock().unwrap().as_ref().map_or(true, |mc| mc.is_paused());
+                if paused { LedState::Paused } else { LedState::Running }
+            }
+        }
+    }
+
+    // Color for an RGB LED, and the blink period and on time in ms (equal for steady light)
+    fn pattern(&self) -> ((u8, u8, u8), u64, u64) {
+        match self {
+            LedState::Booting => ((0, 0, 255), 1000, 500),        // blue, slow blink
+            LedState::Homing => ((255, 160, 0), 250, 125),        // amber, fast blink
+            LedState::Running => ((0, 255, 0), 1000, 1000),       // green, steady
+            LedState::Paused => ((0, 255, 0), 2000, 100),         // green, short flash
+            LedState::Stopped => ((0, 0, 255), 2000, 100),        // blue, short flash
+            LedState::Fault => ((255, 0, 0), 200, 100),           // red, rapid blink
+            LedState::SafeMode => ((160, 0, 255), 1000, 100),     // purple, short flash
+        }
+    }
+}
+
+enum LedDriver {
+    Gpio(PinDriver<'static, AnyIOPin, Output>, bool),   // bool: on when low
+    Ws2812(TxRmtDriver<'static>),
+}
+
+impl LedDriver {
+    fn set(&mut self, color: Option<(u8, u8, u8)>) -> anyhow::Result<()> {
+        match self {
+            LedDriver::Gpio(driver, active_low) => {
+                if color.is_some() != *active_low {
+                    driver.set_high()?;
+                } else {
+                    driver.set_low()?;
+                }
+            }
+            LedDriver::Ws2812(tx) => {
+                let (r, g, b) = color.unwrap_or((0, 0, 0));
+                let scale = |c: u8| (c as u16 * WS2812_BRIGHTNESS as u16 / 255) as u32;
+                write_ws2812(tx, (scale(g) << 16) | (scale(r) << 8) | scale(b))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Sends one GRB pixel, 24 bits MSB first
+fn write_ws2812(tx: &mut TxRmtDriver, grb: u32) -> anyhow::Result<()> {
+    let ticks_hz = tx.counter_clock()?;
+    let t0h = Pulse::new_with_duration(ticks_hz, PinState::High, &Duration::from_nanos(350))?;
+    let t0l = Pulse::new_with_duration(ticks_hz, PinState::Low, &Duration::from_nanos(800))?;
+    let t1h = Pulse::new_with_duration(ticks_hz, PinState::High, &Duration::from_nanos(700))?;
+    let t1l = Pulse::new_with_duration(ticks_hz, PinState::Low, &Duration::from_nanos(600))?;
+    let mut signal = FixedLengthSignal::<24>::new();
+    for i in 0..24 {
+        let bit = grb & (1 << (23 - i)) != 0;
+        signal.set(i, &if bit { (t1h, t1l) } else { (t0h, t0l) })?;
+    }
+    tx.start_blocking(&signal)?;
+    Ok(())
+}
+
+// Starts a task that keeps the status LED in line with the device state. Polls the shared state
+// on its own, so the motor loop never waits on the LED.
+pub fn start(app_context: AppContext, rmt_channel: CHANNEL0, config: &LedConfiguration) -> anyhow::Result<()> {
+    if config.kind == LedKind::None {
+        return Ok(());
+    }
+    let Some((pin_lease, [pin])) = PinLease::take(&app_context.all_pins, [config.pin as usize]) else {
+        anyhow::bail!("GPIO{} is not available for the status LED", config.pin);
+    };
+    let mut driver = match config.kind {
+        LedKind::Gpio => LedDriver::Gpio(PinDriver::output(pin)?, config.active_low),
+        LedKind::Ws2812 => LedDriver::Ws2812(TxRmtDriver::new(rmt_channel, pin, &TransmitConfig::new().clock_divider(1))?),
+        LedKind::None => unreachable!(),
+    };
+    log::info!("Status LED on GPIO{} ({:?})", config.pin, config.kind);
+
+    std::thread::Builder::new()
+        .stack_size(LED_TASK_STACK_SIZE)
+        .spawn(move || {
+            let _pin_lease = pin_lease;
+            let started = Instant::now();
+            let mut shown = None;
+            loop {
+                let state = LedState::current(&app_context);
+                let (color, period, on_time) = state.pattern();
+                let lit = started.elapsed().as_millis() as u64 % period < on_time;
+                let output = lit.then_some(color);
+                if shown != Some(output) {
+                    if let Err(e) = driver.set(output) {
+                        log::error!("Failed to set the status LED: {}", e);
+                    }
+                    shown = Some(output);
+                }
+                std::thread::sleep(Duration::from_millis(UPDATE_INTERVAL_MS));
+            }
+        })?;
+    Ok(())
+}
@@ -0,0 +1,53 @@
+use serde::Serialize;
+
+use crate::input::{self, DebouncedInput};
+use crate::pins::PinPool;
+use crate::storage::LimitSwitchConfiguration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitEnd {
+    Min,
+    Max,
+}
+
+// Hardware backstop at the ends of travel, independent of the position the motor reports
+pub struct LimitSwitches {
+    min: Option<DebouncedInput>,
+    max: Option<DebouncedInput>,
+}
+
+impl LimitSwitches {
+    // None without any switch configured. A configured one that can't be set up is an error,
+    // like the interlock.
+    pub fn start(pool: &PinPool, config: &LimitSwitchConfiguration) -> anyhow::Result<Option<Self>> {
+        if config.min_pin.is_none() && config.max_pin.is_none() {
+            return Ok(None);
+        }
+        let take = |pin: Option<u32>, role| {
+            pin.map(|pin| DebouncedInput::take(pool, pin, config.active_low, input::DEFAULT_DEBOUNCE, role)).transpose()
+        };
+        let min = take(config.min_pin, "min limit switch")?;
+        let max = take(config.max_pin, "max limit switch")?;
+        log::info!("Limit switches: min {:?}, max {:?}, triggered when {}", config.min_pin, config.max_pin,
+            if config.active_low { "low" } else { "high" });
+        Ok(Some(Self { min, max }))
+    }
+
+    // The end whose switch is pressed, if any
+    pub fn triggered(&mut self) -> Option<LimitEnd> {
+        let pressed = |input: &mut Option<DebouncedInput>| input.as_mut().is_some_and(|input| {
+            input.poll();
+            input.is_active()
+        });
+        let min = pressed(&mut self.min);
+        let max = pressed(&mut self.max);
+        if min {
+            Some(LimitEnd::Min)
+        } else if max {
+            Some(LimitEnd::Max)
+        } else {
+            None
+        }
+    }
+}
@@ -1,11 +1,14 @@
 
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time;
 
+use esp_idf_svc::hal::cpu::{self, Core};
 use esp_idf_svc::hal::delay::FreeRtos;
 use esp_idf_svc::hal::gpio::{AnyInputPin, AnyIOPin, AnyOutputPin};
 use esp_idf_svc::hal::peripherals::Peripherals;
 use esp_idf_svc::hal::prelude::*;
+use esp_idf_svc::hal::task::thread::ThreadSpawnConfiguration;
 use esp_idf_svc::hal::uart;
 use esp_idf_svc::hal::uart::UART1;
 use esp_idf_svc::eventloop::EspSystemEventLoop;
@@ -13,24 +16,57 @@ use esp_idf_svc::nvs::EspDefaultNvsPartition;
 use esp_idf_svc::wifi::{AuthMethod, ClientConfiguration, Configuration, EspWifi};
 use esp_idf_svc::io::vfs::BlockingStdIo;
 use esp_idf_svc::hal::usb_serial;
-use esp_idf_svc::http::server::EspHttpServer;
+use esp_idf_svc::http::server::{self as http_server, EspHttpServer};
 
+mod boot;
+mod buzzer;
+mod clock;
 mod command;
 mod context;
+mod debounce;
+mod error;
 mod http_api;
+mod input;
+mod interlock;
+mod led;
+mod limit_switch;
+mod metrics;
 mod motion;
 mod motor;
 mod motor_57aim30;
 mod motor_pwm;
+mod mqtt;
+mod pattern;
+mod pins;
+mod schedule;
+mod status;
 mod storage;
+mod units;
 
 use command::handle_stdin_command;
 use context::AppContext;
+use interlock::Interlock;
+use limit_switch::LimitSwitches;
 use motion::{MotorController, MotorControllerConfig};
 use motor_57aim30::{Modbus57AIM30Motor, ModbusRTUMaster};
+use pins::PinLease;
+use schedule::{ScheduleAction, Scheduler};
+use error::OssmError;
+use status::{DeviceStatus, InitState};
 
 
 const TARGET_BAUD_RATE: u32 = 115200;
+const MOTOR_TASK_STACK_SIZE: usize = 8192;
+const MOTOR_TASK_RESTART_DELAY: time::Duration = time::Duration::from_secs(2);   // Before restarting a motor task that exited, doubling while it never gets ready
+const MOTOR_TASK_MAX_RESTART_DELAY: time::Duration = time::Duration::from_secs(60);
+const PARK_SETTLE_TIME: time::Duration = time::Duration::from_secs(1);  // Cycles run after parking before the loop stops
+const MAX_CONSECUTIVE_CYCLE_ERRORS: u32 = 50;   // Failed cycles in a row before the loop halts the motor
+const HALTED_RETRY_INTERVAL_MS: u32 = 1000;     // How often a halted loop checks whether the motor is back
+const MOTOR_PROBE_INTERVAL_MS: u32 = 1000;      // How often a missing motor is looked for again
+const MOTOR_WAIT_TIME: time::Duration = time::Duration::from_secs(30);   // Probing for a missing motor before scanning for it
+const HTTP_MAX_URI_HANDLERS: usize = 48;        // Every endpoint and its CORS preflight take one
+const MAX_REINIT_ATTEMPTS: u32 = 30;            // Link re-initializations before the loop gives up on the motor
+const CONFIG_SAVE_INTERVAL: time::Duration = time::Duration::from_millis(200);  // Coalesces config writes while a slider is dragged
 
 
 fn main() {
@@ -81,11 +117,74 @@ fn run_app() -> anyhow::Result<()> {
     // setup storage manager
     let storage_manager = Arc::new(Mutex::new(Box::new(storage::StorageManager::new(nvs))));
 
+    let mut status = DeviceStatus::default();
+    status.homed_range = storage_manager.lock().unwrap().get_homed_range().ok();
+    status.reset_reason = boot::ResetReason::read();
+    log::info!("Reset reason: {:?}", status.reset_reason);
+    if status.reset_reason == boot::ResetReason::Brownout {
+        log::warn!("**************************************************************");
+        log::warn!("* The last reset was a BROWNOUT: the supply voltage dropped. *");
+        log::warn!("* The motor can draw more current than the power supply      *");
+        log::warn!("* delivers. Use a stronger supply or separate the motor and  *");
+        log::warn!("* controller supplies.                                       *");
+        log::warn!("**************************************************************");
+    }
+    status.last_boot_crashed = status.reset_reason.is_crash();
+    match storage_manager.lock().unwrap().record_boot(status.last_boot_crashed) {
+        Ok((boot_count, crash_count)) => {
+            status.boot_count = boot_count;
+            log::info!("Boot #{}, crashes in a row: {}", boot_count, crash_count);
+            if crash_count >= boot::CRASH_LOOP_THRESHOLD {
+                status.safe_mode_reason = Some(format!("crash loop ({} crashes in a row)", crash_count));
+            }
+        }
+        Err(e) => log::error!("Failed to record boot: {}", e),
+    }
+    // Always take the request so it only applies once, whatever else triggers safe mode
+    if storage_manager.lock().unwrap().take_safe_mode_request() {
+        status.safe_mode_reason.get_or_insert_with(|| "requested with reboot_safe_mode".to_string());
+    }
+    if boot::safe_mode_jumper_set(&mut all_pins.lock().unwrap()) {
+        status.safe_mode_reason.get_or_insert_with(|| format!("jumper on GPIO{}", boot::SAFE_MODE_JUMPER_PIN));
+    }
+    if let Some(reason) = &status.safe_mode_reason {
+        log::error!("Starting in safe mode without the motor: {}", reason);
+        status.init_state = InitState::SafeMode;
+    }
+
+    let schedule = storage_manager.lock().unwrap().get_schedule().unwrap_or_default();
+    if let Some(schedule) = &schedule {
+        log::info!("Schedule: {:?}", schedule);
+    }
     let app_context = AppContext {
         storage_manager: storage_manager.clone(),
         motor_controller: Arc::new(Mutex::new(None)),
         all_pins,
+        status: Arc::new(Mutex::new(status)),
+        motor_loop_enabled: Arc::new(AtomicBool::new(true)),
+        scheduler: Arc::new(Mutex::new(Scheduler::restore(schedule))),
     };
+    boot::flush_on_exit(&app_context);
+
+    let led_config = storage_manager.lock().unwrap().get_led_configuration().unwrap_or_default();
+    if let Err(e) = led::start(app_context.clone(), peripherals.rmt.channel0, &led_config) {
+        log::error!("Failed to start the status LED: {}", e);
+    }
+    let buzzer_config = storage_manager.lock().unwrap().get_buzzer_configuration().unwrap_or_default();
+    if let Err(e) = buzzer::start(app_context.clone(), peripherals.ledc.timer0, peripherals.ledc.channel0, &buzzer_config) {
+        log::error!("Failed to start the buzzer: {}", e);
+    }
+
+    // Once up for a while the crashes are over, a later one starts a new count
+    {
+        let storage_manager = storage_manager.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(time::Duration::from_secs(boot::STABLE_UPTIME_SECS));
+            if let Err(e) = storage_manager.lock().unwrap().clear_crash_count() {
+                log::error!("Failed to clear crash count: {}", e);
+            }
+        });
+    }
 
     // setup stdin command handler
     {
@@ -103,16 +202,93 @@ fn run_app() -> anyhow::Result<()> {
         log::error!("Failed to connect to wifi: {}", e);
     }
 
+    let time_config = storage_manager.lock().unwrap().get_time_configuration().unwrap_or_default();
+    let _sntp = match clock::start_sntp(&time_config) {
+        Ok(sntp) => sntp,
+        Err(e) => {
+            log::error!("Failed to start SNTP, timestamps stay relative to boot: {}", e);
+            None
+        }
+    };
+
+    let mqtt_config = storage_manager.lock().unwrap().get_mqtt_configuration().unwrap_or_default();
+    if let Err(e) = mqtt::start(app_context.clone(), &mqtt_config) {
+        log::error!("Failed to start MQTT: {}", e);
+    }
+
     // setup http api
-    let mut server = EspHttpServer::new(&Default::default())?;
+    let http_config = storage_manager.lock().unwrap().get_http_configuration().unwrap_or_default();
+    let server_config = http_server::Configuration {
+        http_port: http_config.port,
+        max_uri_handlers: HTTP_MAX_URI_HANDLERS,
+        uri_match_wildcard: true,    // For GET /config/<field> and /state/<field>
+        ..Default::default()
+    };
+    log::info!("Starting HTTP server on port {}", http_config.port);
+    let mut server = EspHttpServer::new(&server_config)?;
     http_api::register_handlers(&mut server, app_context.clone());
 
-    if let Err(e) = run_motor(app_context, peripherals.uart1) {
-        log::error!("Motor task failed: {}", e);
+    let safe_mode = app_context.status.lock().unwrap().init_state == InitState::SafeMode;
+    if safe_mode {
+        log::warn!("Safe mode: the motor is not started, WiFi, HTTP and serial configuration still work. Reboot to leave safe mode.");
+        loop {
+            FreeRtos::delay_ms(1000);
+        }
     }
+    supervise_motor_task(app_context, peripherals.uart1)
+}
 
+// Keeps the motor task running, starting it again whenever it exits
+fn supervise_motor_task(app_context: AppContext, uart_peripheral: UART1) -> anyhow::Result<()> {
+    let mut uart_peripheral = Some(uart_peripheral);
+    let mut restart_delay = MOTOR_TASK_RESTART_DELAY;
     loop {
-        FreeRtos::delay_ms(1000);
+        // The task that exited dropped its driver, so the UART is free again
+        let uart = uart_peripheral.take().unwrap_or_else(|| unsafe { UART1::new() });
+        let handle = spawn_motor_task(app_context.clone(), uart)?;
+        let mut got_ready = false;
+        while !handle.is_finished() {
+            FreeRtos::delay_ms(1000);
+            got_ready |= app_context.status.lock().unwrap().init_state == InitState::Ready;
+        }
+        if let Err(panic) = handle.join() {
+            // The shared state may be half-updated, crash the whole firmware so the reboot counts as one
+            log::error!("Motor task panicked");
+            std::panic::resume_unwind(panic);
+        }
+
+        // A motor that isn't powered yet comes up on a quick retry, one that stays away isn't
+        // hammered. A task that ran starts over from the short delay.
+        if got_ready {
+            restart_delay = MOTOR_TASK_RESTART_DELAY;
+        }
+        if app_context.motor_loop_enabled.load(Ordering::Relaxed) {
+            let retry_at = time::Instant::now() + restart_delay;
+            let restarts = {
+                let mut status = app_context.status.lock().unwrap();
+                status.motor_task_restarts += 1;
+                status.motor_retry_at = Some(retry_at);
+                status.motor_task_restarts
+            };
+            log::warn!("Motor task exited, restarting it in {}s (restart #{})", restart_delay.as_secs(), restarts);
+            while time::Instant::now() < retry_at && app_context.motor_loop_enabled.load(Ordering::Relaxed) {
+                FreeRtos::delay_ms(100);
+            }
+            app_context.status.lock().unwrap().motor_retry_at = None;
+            restart_delay = (restart_delay * 2).min(MOTOR_TASK_MAX_RESTART_DELAY);
+        }
+        if !app_context.motor_loop_enabled.load(Ordering::Relaxed) {
+            app_context.status.lock().unwrap().init_state = InitState::Stopped;
+            log::info!("Motor loop stopped, use motor_start_loop to start it again");
+            while !app_context.motor_loop_enabled.load(Ordering::Relaxed) {
+                FreeRtos::delay_ms(100);
+            }
+            log::info!("Starting the motor loop");
+        }
+        let mut status = app_context.status.lock().unwrap();
+        status.init_state = InitState::Booting;
+        status.init_error = None;
+        status.motor_fault = None;
     }
 }
 
@@ -162,53 +338,83 @@ fn connect_wifi(
     Ok(())
 }
 
+// Runs the motor loop in its own FreeRTOS task so WiFi and HTTP load don't jitter its timing
+fn spawn_motor_task(app_context: AppContext, uart_peripheral: UART1) -> anyhow::Result<std::thread::JoinHandle<()>> {
+    let loop_config = app_context.storage_manager.lock().unwrap().get_motor_loop_configuration().unwrap_or_default();
+    let pin_to_core = if (loop_config.task_core as usize) < cpu::CORES as usize {
+        Some(Core::from(loop_config.task_core as i32))
+    } else {
+        None    // Single-core chip
+    };
+    log::info!("Starting motor task, priority {}, core {:?}", loop_config.task_priority, pin_to_core);
+
+    ThreadSpawnConfiguration {
+        name: Some(b"motor\0"),
+        stack_size: MOTOR_TASK_STACK_SIZE,
+        priority: loop_config.task_priority,
+        pin_to_core,
+        ..Default::default()
+    }
+    .set()?;
+    let handle = std::thread::Builder::new()
+        .stack_size(MOTOR_TASK_STACK_SIZE)
+        .spawn(move || {
+            if let Err(e) = run_motor(app_context, uart_peripheral) {
+                log::error!("Motor task failed: {}", e);
+            }
+        });
+    // Threads spawned later get the defaults again
+    ThreadSpawnConfiguration::default().set()?;
+    Ok(handle?)
+}
+
 fn run_motor(app_context: AppContext, uart_peripheral: UART1) -> anyhow::Result<()> {
+    let interlock_config = app_context.storage_manager.lock().unwrap().get_interlock_configuration().unwrap_or_default();
+    let mut interlock = Interlock::start(&app_context.all_pins, &interlock_config)?;
+    let limit_config = app_context.storage_manager.lock().unwrap().get_limit_switch_configuration().unwrap_or_default();
+    let mut limit_switches = LimitSwitches::start(&app_context.all_pins, &limit_config)?;
     let motor_controller_result = (|| -> anyhow::Result<MotorController<'static>> {
-        let uart: uart::UartDriver = {
+        let mut modbus_config = app_context.storage_manager.lock().unwrap().get_modbus_configuration().unwrap_or_default();
+        log::info!("Modbus framing: {}, device id: {}, DE/RE control: {:?}", modbus_config.framing(), modbus_config.device_id, modbus_config.de_re_control);
+
+        let (uart, ctrl_pin, pin_lease) = {
             let pin_config = app_context.storage_manager.lock().unwrap().get_pin_configuration().unwrap_or_default();
     
             let config = uart::config::Config::default()
                 .baudrate(Hertz(TARGET_BAUD_RATE))
-                .mode(uart::config::Mode::RS485HalfDuplex);    // the driver software will control rts pin, which is connected to the rs485 transceiver's DE/~RE pin
-    
-            let mut all_pins = app_context.all_pins.lock().unwrap();
-            let tx_pin_num = pin_config.modbus_tx as usize;
-            let rx_pin_num = pin_config.modbus_rx as usize;
-            let rts_pin_num = pin_config.modbus_de_re as usize;
-    
-            let tx = all_pins.get_mut(tx_pin_num).and_then(|p| p.take());
-            let rx = all_pins.get_mut(rx_pin_num).and_then(|p| p.take());
-            let rts = all_pins.get_mut(rts_pin_num).and_then(|p| p.take());
+                .rx_fifo_size(modbus_config.rx_buffer_size)
+                .tx_fifo_size(modbus_config.tx_buffer_size);
+            let config = match modbus_config.de_re_control {
+                // The driver software will control rts pin, which is connected to the rs485 transceiver's DE/~RE pin
+                storage::DeReControl::Rts => config.mode(uart::config::Mode::RS485HalfDuplex),
+                // ModbusRTUMaster sets the pin around each frame
+                storage::DeReControl::Gpio => config.mode(uart::config::Mode::UART),
+            };
+            let config = match modbus_config.parity {
+                storage::Parity::None => config.parity_none(),
+                storage::Parity::Even => config.parity_even(),
+                storage::Parity::Odd => config.parity_odd(),
+            };
+            let config = config.stop_bits(if modbus_config.stop_bits == 2 {
+                uart::config::StopBits::STOP2
+            } else {
+                uart::config::StopBits::STOP1
+            });
     
-            match (tx, rx, rts) {
-                (Some(tx), Some(rx), Some(rts)) => {
-                    log::info!("Using configured pins for UART: tx={}, rx={}, rts={}", tx_pin_num, rx_pin_num, rts_pin_num);
-                    uart::UartDriver::new(
-                        uart_peripheral,
-                        <AnyIOPin as Into<AnyOutputPin>>::into(tx),
-                        <AnyIOPin as Into<AnyInputPin>>::into(rx),
-                        Option::<AnyIOPin>::None,
-                        Some(<AnyIOPin as Into<AnyOutputPin>>::into(rts)),
-                        &config,
-                    )?
+            let configured_pins = [pin_config.modbus_tx as usize, pin_config.modbus_rx as usize, pin_config.modbus_de_re as usize];
+            let (pin_lease, [tx, rx, rts]) = match PinLease::take(&app_context.all_pins, configured_pins) {
+                Some(taken) => {
+                    log::info!("Using configured pins for UART: tx={}, rx={}, rts={}", configured_pins[0], configured_pins[1], configured_pins[2]);
+                    taken
                 }
-                _ => {
+                None => {
                     log::warn!("Failed to get configured pins, searching for available pins.");
-    
-                    let mut tx_pin_num = 0;
-                    let mut rx_pin_num = 0;
-                    let mut rts_pin_num = 0;
-    
-                    let tx = all_pins.iter_mut().enumerate().find_map(|(i, p)| if p.is_some() { tx_pin_num = i; p.take() } else { None });
-                    let rx = all_pins.iter_mut().enumerate().find_map(|(i, p)| if p.is_some() { rx_pin_num = i; p.take() } else { None });
-                    let rts = all_pins.iter_mut().enumerate().find_map(|(i, p)| if p.is_some() { rts_pin_num = i; p.take() } else { None });
-    
-                    if tx.is_none() || rx.is_none() || rts.is_none() {
+                    let Some((pin_lease, pins)) = PinLease::take_any(&app_context.all_pins) else {
                         anyhow::bail!("Not enough available pins for UART.");
-                    }
-    
+                    };
+                    let &[tx_pin_num, rx_pin_num, rts_pin_num] = pin_lease.pins() else { unreachable!() };
                     log::info!("Found available pins for UART: tx={}, rx={}, rts={}", tx_pin_num, rx_pin_num, rts_pin_num);
-    
+
                     let new_pin_config = storage::PinConfiguration {
                         modbus_tx: tx_pin_num as u32,
                         modbus_rx: rx_pin_num as u32,
@@ -216,30 +422,76 @@ fn run_motor(app_context: AppContext, uart_peripheral: UART1) -> anyhow::Result<
                     };
                     app_context.storage_manager.lock().unwrap().set_pin_configuration(&new_pin_config)?;
                     log::info!("Saved new pin configuration to NVS.");
-    
-                    let tx: AnyOutputPin = tx.unwrap().into();
-                    let rx: AnyInputPin = rx.unwrap().into();
-                    let rts: AnyOutputPin = rts.unwrap().into();
-    
-                    uart::UartDriver::new(
-                        uart_peripheral,
-                        tx,
-                        rx,
-                        Option::<AnyIOPin>::None,
-                        Some(rts),
-                        &config,
-                    )?
+                    (pin_lease, pins)
                 }
-            }
+            };
+
+            let (rts, ctrl_pin) = match modbus_config.de_re_control {
+                storage::DeReControl::Rts => (Some(<AnyIOPin as Into<AnyOutputPin>>::into(rts)), None),
+                storage::DeReControl::Gpio => (None, Some(<AnyIOPin as Into<AnyOutputPin>>::into(rts))),
+            };
+            // If this fails the pins go back to the pool with the lease
+            let uart = uart::UartDriver::new(
+                uart_peripheral,
+                <AnyIOPin as Into<AnyOutputPin>>::into(tx),
+                <AnyIOPin as Into<AnyInputPin>>::into(rx),
+                Option::<AnyIOPin>::None,
+                rts,
+                &config,
+            )?;
+            (uart, ctrl_pin, pin_lease)
         };
 
-        let modbus = ModbusRTUMaster::new(uart, Option::<AnyOutputPin>::None, 1);
-
-        let mut motor = Modbus57AIM30Motor::new(modbus);
+        // The master drops the lease after the driver, handing the pins back
+        let modbus = ModbusRTUMaster::new(uart, ctrl_pin, modbus_config.device_id)
+            .with_pin_lease(pin_lease)
+            .with_de_re_active_low(modbus_config.de_re_active_low)?;
+
+        let homing_config = app_context.storage_manager.lock().unwrap().get_homing_configuration().unwrap_or_default();
+        let mut motor = Modbus57AIM30Motor::new(modbus)
+            .with_homing(homing_config)
+            .with_position_format(modbus_config.position_word_order, modbus_config.position_signed);
+        if app_context.storage_manager.lock().unwrap().take_modbus_diagnosis_request() {
+            if let Err(e) = motor.diagnose_modbus() {
+                log::error!("Modbus diagnosis failed: {}", e);
+            }
+        }
+        // With nothing connected the scan below takes close to a minute to say so. A quick probe
+        // says it at once, and picks up a motor that is powered up later right away. Only after
+        // a while does it go on to the scan, which also finds a motor with another device id.
+        if !motor.answers()? {
+            log::warn!("No motor detected, check that it is powered and connected. Looking again every {}s", MOTOR_PROBE_INTERVAL_MS / 1000);
+            app_context.status.lock().unwrap().init_state = InitState::NoMotor;
+            let waiting_since = time::Instant::now();
+            let mut found = false;
+            while !found && waiting_since.elapsed() < MOTOR_WAIT_TIME {
+                if !app_context.motor_loop_enabled.load(Ordering::Relaxed) {
+                    anyhow::bail!("Motor loop stopped while waiting for the motor");
+                }
+                FreeRtos::delay_ms(MOTOR_PROBE_INTERVAL_MS);
+                found = motor.answers()?;
+            }
+            if found {
+                log::info!("Motor detected");
+            } else {
+                log::warn!("Still no motor after {}s, scanning for one with other settings", MOTOR_WAIT_TIME.as_secs());
+            }
+            app_context.status.lock().unwrap().init_state = InitState::Booting;
+        }
         if let Err(e) = motor.enable_modbus_communication() {
             log::info!("Failed to enable modbus, trying to scan and configure: {}", e);
-            let motor_scan_result = motor.modbus_scan().map_err(|e| anyhow::anyhow!("Failed to scan motor device. Please check connection to the motor. {:?}", e))?;
+            let hint = match motor.probe_line() {
+                Ok(responses) => motor_57aim30::line_hint(&responses),
+                Err(e) => format!("Line probe failed: {}", e),
+            };
+            log::info!("{}", hint);
+            let motor_scan_result = motor.modbus_scan().map_err(|e| anyhow::anyhow!("Failed to scan motor device. {} Run diagnose_modbus for a full report. {:?}", hint, e))?;
             log::info!("Motor device found, baud rate: {}, device id: {}", motor_scan_result.baud_rate, motor_scan_result.device_id);
+            if motor_scan_result.device_id != modbus_config.device_id {
+                modbus_config.device_id = motor_scan_result.device_id;
+                app_context.storage_manager.lock().unwrap().set_modbus_configuration(&modbus_config)?;
+                log::info!("Saved device id {} to NVS.", modbus_config.device_id);
+            }
             if motor_scan_result.baud_rate != TARGET_BAUD_RATE {
                 motor.modbus_set_baud_rate(TARGET_BAUD_RATE).map_err(|e| anyhow::anyhow!("Failed to set baud rate to {}: {:?}", TARGET_BAUD_RATE, e))?;
                 log::info!("Motor baud rate set to {}, please power cycle the motor.", TARGET_BAUD_RATE);
@@ -247,12 +499,40 @@ fn run_motor(app_context: AppContext, uart_peripheral: UART1) -> anyhow::Result<
         }
         motor.enable_modbus_communication().map_err(|e| anyhow::anyhow!("Failed to enable modbus communication: {:?}", e))?;
 
+        let motor_info = match motor.read_device_info() {
+            Ok(Some(info)) => {
+                log::info!("Motor model {:#06x}, firmware {:#06x}", info.model, info.firmware_version);
+                Some(info)
+            }
+            Ok(None) => {
+                log::info!("The motor doesn't report its model and firmware version");
+                None
+            }
+            Err(e) => {
+                log::warn!("Failed to read the motor model and firmware version: {}", e);
+                None
+            }
+        };
+        app_context.status.lock().unwrap().motor_info = motor_info;
+
+        let device_id_change = app_context.storage_manager.lock().unwrap().take_device_id_change();
+        if let Some(device_id) = device_id_change {
+            match motor.modbus_set_device_id(device_id) {
+                Ok(()) => {
+                    modbus_config.device_id = device_id;
+                    app_context.storage_manager.lock().unwrap().set_modbus_configuration(&modbus_config)?;
+                    log::info!("Motor device id set to {}. If the motor stops responding, please power cycle it.", device_id);
+                }
+                Err(e) => log::error!("Failed to set motor device id to {}: {}", device_id, e),
+            }
+        }
+
         let motor_config = {
             let sm = app_context.storage_manager.lock().unwrap();
             sm.get_motor_config()
         };
 
-        let motor_config = match motor_config {
+        let mut motor_config = match motor_config {
             Ok(config) => {
                 log::info!("Loaded motor config from NVS");
                 config
@@ -265,41 +545,238 @@ fn run_motor(app_context: AppContext, uart_peripheral: UART1) -> anyhow::Result<
             }
         };
 
+        // Don't resume motion into a supply that just collapsed
+        let brownout = app_context.status.lock().unwrap().reset_reason == boot::ResetReason::Brownout;
+        let pause_after_brownout = app_context.storage_manager.lock().unwrap().get_motor_loop_configuration().unwrap_or_default().pause_after_brownout;
+        if brownout && pause_after_brownout && !motor_config.paused {
+            log::warn!("Starting paused after a brownout, unpause once the power supply is sorted out");
+            motor_config.paused = true;
+        }
+
+        // Not even homing moves the carriage before the interlock closes, and the motor starts
+        // paused once it does
+        if let Some(interlock) = interlock.as_mut() {
+            let engaged = interlock.engaged();
+            app_context.status.lock().unwrap().interlock_engaged = Some(engaged);
+            if !engaged {
+                log::warn!("Interlock open, waiting for it to close before homing");
+                while !interlock.engaged() {
+                    if !app_context.motor_loop_enabled.load(Ordering::Relaxed) {
+                        anyhow::bail!("Motor loop stopped while waiting for the interlock");
+                    }
+                    FreeRtos::delay_ms(100);
+                }
+                log::info!("Interlock closed, homing. The motor starts paused");
+                app_context.status.lock().unwrap().interlock_engaged = Some(true);
+                motor_config.paused = true;
+            }
+        }
+
+        let speed_limits = app_context.storage_manager.lock().unwrap().get_speed_limits().unwrap_or_default();
+        let counts_per_mm = app_context.storage_manager.lock().unwrap().get_counts_per_mm().unwrap_or(None);
+        let range_policy = app_context.storage_manager.lock().unwrap().get_range_policy().unwrap_or_default();
+        let jog_limits = app_context.storage_manager.lock().unwrap().get_jog_limits().unwrap_or_default();
+
+        let skip_homing = app_context.storage_manager.lock().unwrap().get_motor_loop_configuration().unwrap_or_default().skip_homing;
+        let known_range = if skip_homing {
+            match app_context.storage_manager.lock().unwrap().get_homed_range() {
+                Ok(range) => {
+                    log::warn!("Skipping homing, using the saved range [{}, {}]. This trusts the position the motor reports, \
+                        if the carriage moved while the motor was off or the encoder isn't absolute it can drive into the ends.", range.pos_min, range.pos_max);
+                    Some((range.pos_min, range.pos_max))
+                }
+                Err(_) => {
+                    log::warn!("skip_homing is set but no homed range is saved yet, homing");
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
         let mut motor_controller = MotorController::new(Box::new(motor), motor_config);
-        motor_controller.init_motor().map_err(|e| anyhow::anyhow!("Failed to init motor: {:?}", e))?;
+        motor_controller.set_speed_limits(speed_limits)?;
+        motor_controller.set_counts_per_mm(counts_per_mm);
+        motor_controller.set_range_policy(range_policy);
+        motor_controller.set_jog_limits(jog_limits);
+        motor_controller.seed_tease(unsafe { esp_idf_svc::sys::esp_random() });
+        app_context.status.lock().unwrap().init_state = InitState::Homing;
+        let homing_started = time::Instant::now();
+        let init_result = motor_controller.init_motor(&mut |phase| {
+            log::info!("Homing: {:?} ({:.1}s elapsed)", phase, homing_started.elapsed().as_secs_f32());
+            app_context.status.lock().unwrap().set_homing_phase(phase);
+        }, known_range);
+        app_context.status.lock().unwrap().finish_homing();
+        init_result.map_err(|e| anyhow::anyhow!("Failed to init motor: {:?}", e))?;
+
+        let (pos_min, pos_max) = motor_controller.range();
+        let homed_range = storage::HomedRange { pos_min, pos_max };
+        if known_range != Some((pos_min, pos_max)) {
+            log::info!("Homed range: [{}, {}]", pos_min, pos_max);
+            if let Err(e) = app_context.storage_manager.lock().unwrap().set_homed_range(&homed_range) {
+                log::error!("Failed to save the homed range: {}", e);
+            }
+        }
+        {
+            let mut status = app_context.status.lock().unwrap();
+            status.homed_range = Some(homed_range);
+            status.homing_repeatability = motor_controller.homing_repeatability();
+        }
         Ok(motor_controller)
     })();
 
     match motor_controller_result {
         Ok(mc) => {
             log::info!("Motor initialized, starting motor loop");
-            *app_context.motor_controller.lock().unwrap() = Some(Box::new(mc));
+            let mut mc = Box::new(mc);
+            let config_changes = mc.subscribe_config_changes();
+            *app_context.motor_controller.lock().unwrap() = Some(mc);
+            app_context.status.lock().unwrap().init_state = InitState::Ready;
 
-            let mut last_config_check = time::Instant::now();
-            let mut last_saved_config_version = app_context.motor_controller.lock().unwrap().as_ref().map_or(0, |mc| mc.get_config_version());
+            let mut config_dirty = false;
+            let mut last_config_save = time::Instant::now();
             let mut update_counter = 0;
             let mut last_update_counter_reset = time::Instant::now();
+            let mut consecutive_errors = 0u32;
+            let mut halted = false;
+            let mut needs_reinit = false;   // Halted because the motor stopped answering
+            let mut reinit_attempts = 0u32;
+            let mut stopping_since: Option<time::Instant> = None;
+
+            let loop_config = app_context.storage_manager.lock().unwrap().get_motor_loop_configuration().unwrap_or_default();
+            let cycle_period = time::Duration::from_secs_f32(1.0 / loop_config.control_rate_hz);
+            log::info!("Motor loop target rate: {} Hz", loop_config.control_rate_hz);
 
             loop {
+                let cycle_started = time::Instant::now();
                 {
                     let mut motor_controller_lock = app_context.motor_controller.lock().unwrap();
                     if let Some(controller) = motor_controller_lock.as_mut() {
-                        if last_config_check.elapsed() > time::Duration::from_millis(200) {
-                            last_config_check = time::Instant::now();
-                            let current_version = controller.get_config_version();
-                            if current_version != last_saved_config_version {
-                                let config = controller.get_config();
-                                log::info!("Config updated, saving to NVS");
-                                if let Err(e) = app_context.storage_manager.lock().unwrap().set_motor_config(&config) {
-                                    log::error!("Failed to save motor config: {}", e);
-                                } else {
-                                    last_saved_config_version = current_version;
+                        // Stop requested: park, give the motor a moment to settle there, then let go of the hardware
+                        if !app_context.motor_loop_enabled.load(Ordering::Relaxed) {
+                            match stopping_since {
+                                None => {
+                                    log::info!("Stopping the motor loop, parking the motor");
+                                    if let Err(e) = controller.halt() {
+                                        log::error!("Failed to park: {}", e);
+                                    }
+                                    stopping_since = Some(time::Instant::now());
                                 }
+                                Some(since) if halted || since.elapsed() > PARK_SETTLE_TIME => {
+                                    let config = controller.get_config();
+                                    if let Err(e) = app_context.storage_manager.lock().unwrap().set_motor_config(&config) {
+                                        log::error!("Failed to save motor config: {}", e);
+                                    }
+                                    *motor_controller_lock = None;
+                                    break;
+                                }
+                                Some(_) => {}
+                            }
+                        }
+
+                        let schedule_action = app_context.scheduler.lock().unwrap().poll();
+                        if let Some(action) = schedule_action {
+                            apply_schedule_action(&app_context, controller, action);
+                        }
+
+                        config_dirty |= config_changes.try_iter().count() > 0;
+                        if config_dirty && last_config_save.elapsed() > CONFIG_SAVE_INTERVAL {
+                            last_config_save = time::Instant::now();
+                            match controller.unsaved_config(false) {
+                                Some((version, config)) => {
+                                    log::info!("Config updated, saving to NVS");
+                                    if let Err(e) = app_context.storage_manager.lock().unwrap().set_motor_config(&config) {
+                                        log::error!("Failed to save motor config: {}", e);
+                                    } else {
+                                        controller.mark_saved(version);
+                                        config_dirty = false;
+                                    }
+                                }
+                                // Saved already, e.g. by flush_pending()
+                                None => config_dirty = false,
                             }
                         }
             
-                        if let Err(e) = controller.cycle() {
-                            log::error!("Failed to cycle: {}", e);
+                        if needs_reinit {
+                            // The link is stale after a motor power blip or bus glitch, rebuild it
+                            // instead of cycling into the same error
+                            match controller.reinitialize() {
+                                Ok(()) => {
+                                    log::info!("Motor link re-initialized, the motor stays paused");
+                                    app_context.status.lock().unwrap().metrics.reinitializations += 1;
+                                    needs_reinit = false;
+                                    halted = false;
+                                    consecutive_errors = 0;
+                                    reinit_attempts = 0;
+                                    app_context.status.lock().unwrap().motor_fault = None;
+                                }
+                                Err(e) => {
+                                    reinit_attempts += 1;
+                                    log::error!("Failed to re-initialize the motor link ({}/{}): {}", reinit_attempts, MAX_REINIT_ATTEMPTS, e);
+                                    if reinit_attempts >= MAX_REINIT_ATTEMPTS {
+                                        log::error!("Giving up on the motor, the motor task will start over");
+                                        let mut status = app_context.status.lock().unwrap();
+                                        status.init_state = InitState::Failed;
+                                        status.init_error = Some(format!("Lost the motor link and could not re-initialize it: {}", e));
+                                        status.motor_fault = None;
+                                        *motor_controller_lock = None;
+                                        break;
+                                    }
+                                }
+                            }
+                        } else {
+                            let interlock_engaged = interlock.as_mut().map(Interlock::engaged);
+                            if let Some(engaged) = interlock_engaged {
+                                if let Err(e) = controller.set_interlock(engaged) {
+                                    log::error!("Failed to halt for the interlock: {}", e);
+                                }
+                            }
+                            // Halts on the press and keeps the carriage from going further that way
+                            // while the switch stays pressed
+                            let limit = limit_switches.as_mut().and_then(LimitSwitches::triggered);
+                            if let Err(e) = controller.set_limit_switch(limit) {
+                                log::error!("Failed to halt for the limit switch: {}", e);
+                            }
+                            let result = controller.cycle();
+                            {
+                                let mut status = app_context.status.lock().unwrap();
+                                status.interlock_engaged = interlock_engaged;
+                                status.limit_switch = limit;
+                                status.metrics.cycles += 1;
+                                status.metrics.cycle_errors += result.is_err() as u64;
+                                status.metrics.round_trip = controller.round_trip_time();
+                                status.metrics.strokes = controller.stroke_count();
+                            }
+                            match result {
+                                Ok(()) => {
+                                    if consecutive_errors > 0 {
+                                        log::info!("Motor loop recovered after {} failed cycles", consecutive_errors);
+                                        consecutive_errors = 0;
+                                    }
+                                    if halted {
+                                        halted = false;
+                                        app_context.status.lock().unwrap().motor_fault = None;
+                                    }
+                                }
+                                Err(e) => {
+                                    consecutive_errors += 1;
+                                    // A dropped or garbled Modbus reply is worth retrying, anything else won't go away by itself
+                                    let transient = e.downcast_ref::<OssmError>().is_some_and(|e| e.is_communication());
+                                    // Log the first failure of a run and then only now and then, not once per cycle
+                                    if consecutive_errors == 1 || consecutive_errors % 500 == 0 {
+                                        log::error!("Failed to cycle ({} in a row): {}", consecutive_errors, e);
+                                    }
+                                    if !halted && (!transient || consecutive_errors >= MAX_CONSECUTIVE_CYCLE_ERRORS) {
+                                        log::error!("Halting the motor after {} failed cycles: {}", consecutive_errors, e);
+                                        halted = true;
+                                        needs_reinit = transient;
+                                        if let Err(e) = controller.halt() {
+                                            log::error!("Failed to halt: {}", e);
+                                        }
+                                        app_context.status.lock().unwrap().motor_fault = Some(e.to_string());
+                                    }
+                                }
+                            }
                         }
                     } else {
                         log::error!("Motor controller lost, stopping motor loop");
@@ -310,15 +787,53 @@ fn run_motor(app_context: AppContext, uart_peripheral: UART1) -> anyhow::Result<
                 update_counter += 1;
                 if last_update_counter_reset.elapsed() > time::Duration::from_secs(60) {
                     log::info!("Motor task update per second: {}", update_counter as f64 / 60.0);
+                    app_context.status.lock().unwrap().metrics.loop_rate_hz = update_counter as f32 / 60.0;
                     last_update_counter_reset = time::Instant::now();
                     update_counter = 0;
                 }
+
+                // Sleep off the rest of the period, keeps the rate steady and leaves CPU time for the other tasks
+                let busy = cycle_started.elapsed();
+                if halted {
+                    FreeRtos::delay_ms(HALTED_RETRY_INTERVAL_MS);
+                } else if busy < cycle_period {
+                    FreeRtos::delay_ms((cycle_period - busy).as_millis() as u32);
+                }
             }
+            Ok(())
         },
         Err(e) => {
-            log::error!("Failed to initialize motor: {}. Motor task will not run.", e);
-            return Err(e);
+            log::error!("Failed to initialize motor: {}", e);
+            {
+                let mut status = app_context.status.lock().unwrap();
+                status.init_state = InitState::Failed;
+                status.init_error = Some(e.to_string());
+            }
+            Err(e)
+        }
+    }
+}
+
+// Starts or pauses the motor when the schedule says so
+fn apply_schedule_action(app_context: &AppContext, controller: &mut MotorController, action: ScheduleAction) {
+    let paused = action != ScheduleAction::Start;
+    if paused != controller.is_paused() {
+        log::info!("Schedule: {}", if paused { "pausing" } else { "starting" });
+        // A stop can't wait for an open transaction, a start can
+        let result = if paused {
+            controller.stop_pattern();
+            controller.force_pause(None)
+        } else {
+            controller.update_config(|config| config.paused = false)
+        };
+        if let Err(e) = result {
+            log::error!("Failed to apply the schedule: {}", e);
+        }
+    }
+    if action == ScheduleAction::Finished {
+        log::info!("Schedule finished");
+        if let Err(e) = app_context.storage_manager.lock().unwrap().set_schedule(None) {
+            log::error!("Failed to clear the schedule: {}", e);
         }
     }
-    Ok(())
 }
@@ -0,0 +1,48 @@
+use std::fmt::Write;
+
+use crate::context::AppContext;
+use crate::clock;
+use crate::status::InitState;
+
+// Renders GET /metrics in the Prometheus text exposition format
+pub fn render(app_context: &AppContext) -> String {
+    let status = app_context.status.lock().unwrap().clone();
+    let metrics = &status.metrics;
+    let mut out = String::new();
+
+    metric(&mut out, "ossm_uptime_seconds", "gauge", "Seconds since boot", clock::uptime_secs());
+    metric(&mut out, "ossm_boot_count", "gauge", "Boots recorded in NVS", status.boot_count);
+    metric(&mut out, "ossm_ready", "gauge", "1 when the motor controller is running", (status.init_state == InitState::Ready) as u8);
+    metric(&mut out, "ossm_motor_fault", "gauge", "1 while the motor loop has halted the motor", status.motor_fault.is_some() as u8);
+    metric(&mut out, "ossm_motor_cycles_total", "counter", "Motor loop cycles", metrics.cycles);
+    metric(&mut out, "ossm_motor_cycle_errors_total", "counter", "Motor loop cycles that failed", metrics.cycle_errors);
+    metric(&mut out, "ossm_motor_reinitializations_total", "counter", "Modbus link re-initializations after the motor stopped answering", metrics.reinitializations);
+    metric(&mut out, "ossm_motor_task_restarts_total", "counter", "Times the motor task exited and was started again", status.motor_task_restarts);
+    metric(&mut out, "ossm_motor_loop_rate_hz", "gauge", "Motor loop cycles per second over the last minute", metrics.loop_rate_hz);
+    if let Some(round_trip) = metrics.round_trip {
+        metric(&mut out, "ossm_modbus_round_trip_seconds", "gauge", "Time the last Modbus request took to get its answer", round_trip.as_secs_f64());
+    }
+    metric(&mut out, "ossm_strokes_total", "counter", "Strokes since the motor controller started", metrics.strokes);
+
+    // Drive readings cost a Modbus request, skip them while the motor isn't there
+    let telemetry = app_context.motor_controller.lock().unwrap().as_mut().map(|mc| (mc.is_paused(), mc.read_telemetry()));
+    if let Some((paused, telemetry)) = telemetry {
+        metric(&mut out, "ossm_paused", "gauge", "1 while paused", paused as u8);
+        match telemetry {
+            Ok(telemetry) => {
+                metric(&mut out, "ossm_motor_temperature", "gauge", "Drive temperature, in the drive's units", telemetry.temperature);
+                metric(&mut out, "ossm_motor_current", "gauge", "Drive current, in the drive's units", telemetry.current);
+                metric(&mut out, "ossm_motor_voltage", "gauge", "Drive supply voltage, in the drive's units", telemetry.voltage);
+                metric(&mut out, "ossm_motor_alarm_code", "gauge", "Drive alarm code, 0 = no alarm", telemetry.alarm_code);
+            }
+            Err(e) => log::error!("Failed to read telemetry for metrics: {}", e),
+        }
+    }
+    out
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: impl std::fmt::Display) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
@@ -1,11 +1,40 @@
+use std::collections::VecDeque;
+use std::sync::mpsc;
 use std::time;
 
 use serde::{Serialize, Deserialize};
 use anyhow::Result;
 
-use crate::motor::Motor;
+use crate::motor::{HomingPhase, HomingRepeatability, Motor, MotorTelemetry};
+use crate::pattern::{Pattern, PatternProgress, PatternRunner};
+use crate::error::OssmError;
+use crate::limit_switch::LimitEnd;
+use crate::units::UnitConverter;
 
 const SPLINE_RESOLUTION: usize = 1500;
+const SPLINE_SAMPLES_PER_POINT: usize = 8;     // Detailed splines get more samples than SPLINE_RESOLUTION
+const MORPH_STEP: f32 = 0.005;                 // Blend increment between rebuilds of a morphing spline
+pub const MAX_SPLINE_POINTS: usize = 600;      // Each point adds SPLINE_SAMPLES_PER_POINT samples, a morph holds three copies
+const RECORD_INTERVAL: f32 = 0.05;             // Seconds between recorded samples
+const RECORD_MAX_SECONDS: f32 = 60.0;          // One cycle of the playback, longer would need less than 1 BPM
+const RECORD_POINTS_PER_SECOND: f32 = 10.0;    // Spline points per second of recording, plenty for hand motion
+const RECORD_POWER: u16 = 60;                  // Drive power while recording, low enough to move by hand
+const RUN_POWER: u16 = 350;
+const MIN_ACCELERATION: u16 = 1000;            // Lower makes the carriage lag far behind the waveform
+const MAX_ACCELERATION: u16 = 60000;           // Top of the drive's range, the register reads back what it took
+const IDLE_POWER: u16 = 100;                   // Drive power while parked idle, holds the carriage with little heat
+const TEASE_SEED: u32 = 0x05511;               // Until seed_tease() is called
+const STROKE_LOG_SIZE: usize = 32;             // Stroke events kept for strokes_since()
+const POSITION_SETTLE: f32 = 1.0;              // Seconds without a paused position move before it counts as a config change
+const JOG_BURST: f32 = 0.25;                   // Seconds of max_rate a burst of jogs may move at once
+const RECENTER_SPEED: f32 = 0.05;              // Stroke fractions per second the paused position drifts back to center at
+const TRANSACTION_TIMEOUT: f32 = 10.0;         // Seconds before an open transaction commits by itself
+const LOST_POSITION_MARGIN: f32 = 0.05;        // Fraction of the homed range past either end a reconnected motor may report
+const MAX_CYCLE_SECONDS: f32 = 60.0;          // One cycle at the slowest bpm, bounds how far t0 is set back
+const DEMO_DELAY: f32 = 30.0;                  // Seconds paused without a config change before the demo starts
+const DEMO_BPM: f32 = 10.0;
+const DEMO_AMPLITUDE: f32 = 0.1;               // Fraction of the stroke either side of the center
+const DEMO_RAMP: f32 = 5.0;                    // Seconds to ease from the parked position into the full demo motion
 
 // ===== Layer 1: Waveform Generator =====
 // Generates y ∈ [0, 1] given time, handles BPM internally
@@ -15,6 +44,41 @@ trait WaveformGenerator: Send {
     
     // Find phase x ∈ [0, 1] that produces y ∈ [0, 1]
     fn find_x_for_y(&self, y: f32) -> f32;
+
+    // Like find_x_for_y, but picks a phase where y passes through the value rising (or falling).
+    // Scans one cycle for a crossing in that direction and bisects it, falls back to find_x_for_y
+    // when there is none (e.g. y is an extreme).
+    fn find_x_for_y_moving(&self, y: f32, rising: bool) -> f32 {
+        const STEPS: usize = 200;
+        // At 60 bpm one second is one cycle, so time offset == phase
+        let sample = |x: f32| self.evaluate(x, 60.0).0;
+
+        let mut x0 = 0.0;
+        let mut y0 = sample(x0);
+        for i in 1..=STEPS {
+            let x1 = i as f32 / STEPS as f32;
+            let y1 = sample(x1);
+            let crosses = if rising { y0 <= y && y < y1 } else { y0 >= y && y > y1 };
+            if crosses {
+                let (mut lo, mut hi) = (x0, x1);
+                for _ in 0..20 {
+                    let mid = (lo + hi) / 2.0;
+                    if (sample(mid) < y) == rising {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                return ((lo + hi) / 2.0) % 1.0;
+            }
+            x0 = x1;
+            y0 = y1;
+        }
+        self.find_x_for_y(y)
+    }
+
+    // Phases (x_max, x_min) where y reaches its maximum and minimum
+    fn extreme_phases(&self) -> (f32, f32);
 }
 
 struct SineWaveform;
@@ -49,6 +113,20 @@ impl WaveformGenerator for SineWaveform {
             x
         }
     }
+
+    fn find_x_for_y_moving(&self, y: f32, rising: bool) -> f32 {
+        // asin only gives the rising half, the falling half mirrors it around the peak at x = 0.25
+        let x = self.find_x_for_y(y);
+        if rising {
+            x
+        } else {
+            (0.5 - x).rem_euclid(1.0)
+        }
+    }
+
+    fn extreme_phases(&self) -> (f32, f32) {
+        (0.25, 0.75)
+    }
 }
 
 struct ThrustWaveform {
@@ -83,6 +161,8 @@ impl WaveformGenerator for ThrustWaveform {
             30.0 * t * t * (t - 1.0) * (t - 1.0)
         };
         
+        // Both phases meet with zero speed (s'(0) = s'(1) = 0), at the peak and at the
+        // wrap-around, so position and speed are continuous across the branch switch
         let (y, dy_dx) = if x < rise_duration {
             // Rise phase
             let t = x / rise_duration;
@@ -106,9 +186,11 @@ impl WaveformGenerator for ThrustWaveform {
     }
 
     fn find_x_for_y(&self, y: f32) -> f32 {
-        // Binary search to find x such that evaluate(x, 1.0) ≈ y
+        // Binary search to find x such that evaluate(x, 1.0) ≈ y. y is only monotonic during
+        // the rise, searching the whole cycle can end up in the fall phase or, with a short
+        // rise, miss the target completely.
         let mut left = 0.0;
-        let mut right = 1.0;
+        let mut right = self.sharpness.max(0.01).min(0.99);
         let target_y = y.max(0.0).min(1.0);
         
         for _ in 0..20 {  // 20 iterations should be enough precision
@@ -129,6 +211,11 @@ impl WaveformGenerator for ThrustWaveform {
         
         (left + right) / 2.0
     }
+
+    fn extreme_phases(&self) -> (f32, f32) {
+        // Peak at the end of the rise, trough at the start of the cycle
+        (self.sharpness.max(0.01).min(0.99), 0.0)
+    }
 }
 
 struct SplineWaveform {
@@ -214,10 +301,42 @@ impl SplineWaveform {
             speeds[i] = if segment_width > 0.0 { dy_du / segment_width } else { 0.0 };
         }
 
-        // Normalize positions to [0, 1] range and adjust speeds accordingly
+        let mut spline = Self { resolution, positions, speeds };
+        spline.normalize();
+        Ok(spline)
+    }
+
+    // Samples any waveform at the given resolution, so it can be blended with a spline
+    fn sample(waveform: &dyn WaveformGenerator, resolution: usize) -> Self {
+        let scale = (resolution as f32 - 1.0).max(1.0);
+        // At 60 BPM one cycle takes a second, so time is phase and the speed is dy/dx
+        let (positions, speeds) = (0..resolution)
+            .map(|i| waveform.evaluate(i as f32 / scale, 60.0))
+            .unzip();
+        Self { resolution, positions, speeds }
+    }
+
+    // Linear blend of the samples, `alpha` = 0 is self and 1 is `other`. Both must have the same
+    // resolution. The blend of two normalized curves can fall short of [0, 1] (peaks at different
+    // phases), so it's normalized again to keep the full depth.
+    fn blend(&self, other: &Self, alpha: f32) -> Self {
+        let mix = |a: &[f32], b: &[f32]| -> Vec<f32> {
+            a.iter().zip(b).map(|(a, b)| a + alpha * (b - a)).collect()
+        };
+        let mut spline = Self {
+            resolution: self.resolution,
+            positions: mix(&self.positions, &other.positions),
+            speeds: mix(&self.speeds, &other.speeds),
+        };
+        spline.normalize();
+        spline
+    }
+
+    // Normalize positions to [0, 1] range and adjust speeds accordingly
+    fn normalize(&mut self) {
         let mut min_pos = f32::MAX;
         let mut max_pos = f32::MIN;
-        for &pos in &positions {
+        for &pos in &self.positions {
             if pos < min_pos { min_pos = pos; }
             if pos > max_pos { max_pos = pos; }
         }
@@ -225,21 +344,78 @@ impl SplineWaveform {
         let range = max_pos - min_pos;
         if range > 1e-6 {
             let inv_range = 1.0 / range;
-            for i in 0..resolution {
-                positions[i] = (positions[i] - min_pos) * inv_range;
-                speeds[i] *= inv_range;
+            for i in 0..self.resolution {
+                self.positions[i] = (self.positions[i] - min_pos) * inv_range;
+                self.speeds[i] *= inv_range;
             }
         } else {
             // All positions are the same, set to 0.5 and zero speed
-            for i in 0..resolution {
-                positions[i] = 0.5;
-                speeds[i] = 0.0;
+            for i in 0..self.resolution {
+                self.positions[i] = 0.5;
+                self.speeds[i] = 0.0;
             }
         }
-        Ok(Self { resolution, positions, speeds })
     }
 }
 
+// The output from before a waveform change, fading out while the new waveform fades in. Both
+// are evaluated at the same phase, so the carriage never jumps or turns around early.
+struct Crossfade {
+    from: SplineWaveform,   // The old output sampled over one cycle, dwell included
+    start: time::Instant,
+    duration: f32,
+}
+
+impl Crossfade {
+    fn alpha(&self) -> f32 {
+        (self.start.elapsed().as_secs_f32() / self.duration).min(1.0)
+    }
+
+    // Blends the old output into the new (y, dy/dt) at phase u. With `fading` the speed also
+    // includes how fast the fade itself moves y.
+    fn blend(&self, u: f32, bpm: f32, (y, speed): (f32, f32), fading: bool) -> (f32, f32) {
+        let alpha = self.alpha();
+        let (y_old, speed_old) = self.from.evaluate(u * 60.0 / bpm, bpm);
+        let rate = if fading && alpha < 1.0 { 1.0 / self.duration } else { 0.0 };
+        (y_old + alpha * (y - y_old), speed_old + alpha * (speed - speed_old) + rate * (y - y_old))
+    }
+}
+
+// Hand-guided motion capture, one normalized position every RECORD_INTERVAL
+struct Recording {
+    samples: Vec<f32>,
+    max_samples: usize,
+    last_sample: time::Instant,
+}
+
+impl Recording {
+    // Averages the samples into evenly spaced spline points, returns them with the duration
+    fn to_spline_points(&self) -> Result<(Vec<f32>, f32)> {
+        if self.samples.len() < 2 {
+            return Err(anyhow::anyhow!("Recording is too short"));
+        }
+        let duration = self.samples.len() as f32 * RECORD_INTERVAL;
+        let num_points = ((duration * RECORD_POINTS_PER_SECOND).round() as usize).clamp(2, self.samples.len());
+        let points = (0..num_points)
+            .map(|i| {
+                let bin = &self.samples[i * self.samples.len() / num_points..(i + 1) * self.samples.len() / num_points];
+                bin.iter().sum::<f32>() / bin.len() as f32
+            })
+            .collect();
+        Ok((points, duration))
+    }
+}
+
+// A spline morph in progress: the waveform is rebuilt from the blend as time passes
+struct SplineMorph {
+    from: SplineWaveform,
+    to: SplineWaveform,
+    points: Vec<f32>,       // Target points, applied to the config once the morph completes
+    start: time::Instant,
+    duration: f32,
+    alpha: f32,             // Blend currently loaded as the waveform
+}
+
 impl WaveformGenerator for SplineWaveform {
     fn evaluate(&self, time_offset_seconds: f32, bpm: f32) -> (f32, f32) {
         let freq = bpm / 60.0;
@@ -286,6 +462,264 @@ impl WaveformGenerator for SplineWaveform {
         
         best_index as f32 / (self.resolution - 1) as f32
     }
+
+    fn extreme_phases(&self) -> (f32, f32) {
+        let mut max_index = 0;
+        let mut min_index = 0;
+        for i in 0..self.resolution {
+            if self.positions[i] > self.positions[max_index] { max_index = i; }
+            if self.positions[i] < self.positions[min_index] { min_index = i; }
+        }
+        let scale = (self.resolution - 1).max(1) as f32;
+        (max_index as f32 / scale, min_index as f32 / scale)
+    }
+}
+
+// Config fields each waveform reads. Switching waveforms leaves the other waveforms'
+// fields untouched, so e.g. spline points survive a detour through sine.
+const WAVEFORM_PARAMS: &[(&str, &[&str])] = &[
+    ("sine", &[]),
+    ("thrust", &["sharpness", "sharpness_speed"]),
+    ("spline", &["spline_points"]),
+];
+
+pub fn is_known_waveform(name: &str) -> bool {
+    WAVEFORM_PARAMS.iter().any(|(wave, _)| *wave == name)
+}
+
+pub fn waveform_names() -> Vec<&'static str> {
+    WAVEFORM_PARAMS.iter().map(|(wave, _)| *wave).collect()
+}
+
+#[derive(Serialize)]
+pub struct WaveformInfo {
+    pub name: &'static str,
+    pub params: serde_json::Map<String, serde_json::Value>,   // relevant config fields and their defaults
+}
+
+pub fn waveform_infos() -> Vec<WaveformInfo> {
+    let defaults = serde_json::to_value(MotorControllerConfig::default()).unwrap_or_default();
+    WAVEFORM_PARAMS
+        .iter()
+        .map(|(name, params)| WaveformInfo {
+            name,
+            params: params.iter().map(|p| (p.to_string(), defaults[*p].clone())).collect(),
+        })
+        .collect()
+}
+
+// One point holds still and two give a plain back and forth, it takes three or more to shape a stroke
+pub fn check_spline_points(points: &[f32]) -> Result<()> {
+    if points.is_empty() {
+        return Err(anyhow::anyhow!("Spline points cannot be empty"));
+    }
+    if points.len() > MAX_SPLINE_POINTS {
+        return Err(anyhow::anyhow!("Too many spline points ({}), at most {} are allowed", points.len(), MAX_SPLINE_POINTS));
+    }
+    if points.iter().any(|p| !(0.0..=1.0).contains(p)) {
+        return Err(anyhow::anyhow!("Spline points must be between 0.0 and 1.0"));
+    }
+    Ok(())
+}
+
+// Building is linear in the number of samples and only happens on config changes
+fn spline_resolution(num_points: usize) -> usize {
+    SPLINE_RESOLUTION.max(num_points * SPLINE_SAMPLES_PER_POINT)
+}
+
+fn build_waveform(config: &MotorControllerConfig) -> Box<dyn WaveformGenerator> {
+    match config.wave_func.as_str() {
+        "sine" => Box::new(SineWaveform),
+        "thrust" => Box::new(ThrustWaveform::new(config.thrust.sharpness)),
+        "spline" => match SplineWaveform::from_points(&config.spline.points, spline_resolution(config.spline.points.len())) {
+            Ok(wf) => Box::new(wf),
+            Err(e) => {
+                log::error!("Error creating spline waveform: {}. Falling back to sine wave.", e);
+                Box::new(SineWaveform)
+            }
+        },
+        _ => Box::new(SineWaveform),
+    }
+}
+
+// ===== Layer 1b: Time Warp =====
+// Maps the linear cycle phase u = (t * bpm / 60) % 1 to the phase x fed to the waveform.
+//
+// Chain rule bookkeeping: evaluating the waveform at time x / freq puts it at phase x and
+// returns its speed assuming x advances at freq, i.e. dy/dx * freq. With x = w(u) the real
+// speed is dy/dt = dy/dx * dx/du * du/dt = (dy/dx * freq) * w'(u), because du/dt = freq too.
+// So the layer only has to multiply the waveform speed by w'(u).
+//
+// Warps must be monotonic and map [0, 1) onto [0, 1) so a cycle still takes 60 / bpm seconds.
+
+trait TimeWarp: Send {
+    // Returns (x, dx/du)
+    fn warp(&self, u: f32) -> (f32, f32);
+
+    // Inverse of warp, used to resync t0 to a waveform phase
+    fn unwarp(&self, x: f32) -> f32;
+}
+
+struct IdentityWarp;
+
+impl TimeWarp for IdentityWarp {
+    fn warp(&self, u: f32) -> (f32, f32) {
+        (u, 1.0)
+    }
+
+    fn unwarp(&self, x: f32) -> f32 {
+        x
+    }
+}
+
+// Holds the waveform at its extremes for a fixed time every stroke. The moving part of the
+// cycle is compressed to make room, so BPM keeps meaning strokes per minute. The holds sit
+// where the waveform's speed is already zero, so speed stays continuous.
+struct DwellWarp {
+    holds: [(f32, f32); 2],   // (waveform phase, fraction of the cycle) in the order reached
+    moving: f32,              // Fraction of the cycle left for moving
+}
+
+// Always leave at least this fraction of the cycle for actual motion
+const MIN_MOVING_FRACTION: f32 = 0.1;
+
+impl DwellWarp {
+    // `extremes` are the phases (top, bottom) to hold at
+    fn new(extremes: (f32, f32), dwell_top: f32, dwell_bottom: f32, bpm: f32) -> Self {
+        let period = 60.0 / bpm;
+        let mut top = dwell_top.max(0.0) / period;
+        let mut bottom = dwell_bottom.max(0.0) / period;
+        let total = top + bottom;
+        if total > 1.0 - MIN_MOVING_FRACTION {
+            let scale = (1.0 - MIN_MOVING_FRACTION) / total;
+            top *= scale;
+            bottom *= scale;
+        }
+
+        let (x_top, x_bottom) = extremes;
+        let holds = if x_top <= x_bottom {
+            [(x_top, top), (x_bottom, bottom)]
+        } else {
+            [(x_bottom, bottom), (x_top, top)]
+        };
+        Self { holds, moving: 1.0 - top - bottom }
+    }
+}
+
+impl TimeWarp for DwellWarp {
+    fn warp(&self, u: f32) -> (f32, f32) {
+        let mut held = 0.0;  // Hold time already passed, as a fraction of the cycle
+        for (x_hold, duration) in self.holds {
+            let hold_start = x_hold * self.moving + held;
+            if u < hold_start {
+                break;
+            }
+            if u < hold_start + duration {
+                return (x_hold, 0.0);
+            }
+            held += duration;
+        }
+        ((u - held) / self.moving, 1.0 / self.moving)
+    }
+
+    fn unwarp(&self, x: f32) -> f32 {
+        // Lands on the start of a hold when x is an extreme
+        let mut u = x * self.moving;
+        for (x_hold, duration) in self.holds {
+            if x > x_hold {
+                u += duration;
+            }
+        }
+        u
+    }
+}
+
+// Runs the rising half of the waveform (minimum to maximum) and the falling half at different
+// speeds. Both halves keep their shape, only stretched in time, and the cycle keeps its length.
+// The seams sit at the extremes where the speed is zero, so speed stays continuous.
+struct AsymmetricWarp {
+    knots: Vec<(f32, f32)>,   // (u, x) corners of the piecewise linear map, from (0, 0) to (1, 1)
+}
+
+impl AsymmetricWarp {
+    fn new((x_top, x_bottom): (f32, f32), up_speed: f32, down_speed: f32) -> Self {
+        let (x1, x2) = if x_top < x_bottom { (x_top, x_bottom) } else { (x_bottom, x_top) };
+        // Between the extremes the waveform rises if the minimum comes first
+        let middle_rising = x_bottom < x_top;
+        let segments = [(x1, !middle_rising), (x2 - x1, middle_rising), (1.0 - x2, !middle_rising)];
+        let time = |(length, rising): (f32, bool)| length / if rising { up_speed } else { down_speed };
+        let total: f32 = segments.into_iter().map(time).sum();
+
+        let mut knots = vec![(0.0, 0.0)];
+        let (mut u, mut x) = (0.0, 0.0);
+        for segment in segments.into_iter().filter(|&(length, _)| length > 0.0) {
+            u += time(segment) / total;
+            x += segment.0;
+            knots.push((u, x));
+        }
+        // No rounding error at the end, the next cycle has to start where this one ends
+        *knots.last_mut().unwrap() = (1.0, 1.0);
+        Self { knots }
+    }
+
+    // Segment of the map that `key` of a knot falls into
+    fn segment(&self, value: f32, key: impl Fn(&(f32, f32)) -> f32) -> ((f32, f32), (f32, f32)) {
+        let i = self.knots.partition_point(|knot| key(knot) <= value).clamp(1, self.knots.len() - 1);
+        (self.knots[i - 1], self.knots[i])
+    }
+}
+
+impl TimeWarp for AsymmetricWarp {
+    fn warp(&self, u: f32) -> (f32, f32) {
+        let ((u0, x0), (u1, x1)) = self.segment(u, |&(u, _)| u);
+        let slope = (x1 - x0) / (u1 - u0);
+        (x0 + (u - u0) * slope, slope)
+    }
+
+    fn unwarp(&self, x: f32) -> f32 {
+        let ((u0, x0), (u1, x1)) = self.segment(x, |&(_, x)| x);
+        u0 + (x - x0) * (u1 - u0) / (x1 - x0)
+    }
+}
+
+// Applies `first`, then `then` to its output
+struct ChainWarp {
+    first: Box<dyn TimeWarp>,
+    then: Box<dyn TimeWarp>,
+}
+
+impl TimeWarp for ChainWarp {
+    fn warp(&self, u: f32) -> (f32, f32) {
+        let (v, dv_du) = self.first.warp(u);
+        let (x, dx_dv) = self.then.warp(v);
+        (x, dx_dv * dv_du)
+    }
+
+    fn unwarp(&self, x: f32) -> f32 {
+        self.first.unwarp(self.then.unwarp(x))
+    }
+}
+
+fn build_time_warp(config: &MotorControllerConfig, waveform: &dyn WaveformGenerator) -> Box<dyn TimeWarp> {
+    let extremes = waveform.extreme_phases();
+    let has_dwell = config.dwell_top > 0.0 || config.dwell_bottom > 0.0;
+    if config.up_speed_ratio == config.down_speed_ratio {
+        return if has_dwell {
+            Box::new(DwellWarp::new(extremes, config.dwell_top, config.dwell_bottom, config.bpm))
+        } else {
+            Box::new(IdentityWarp)
+        };
+    }
+    let asymmetric = AsymmetricWarp::new(extremes, config.up_speed_ratio, config.down_speed_ratio);
+    if !has_dwell {
+        return Box::new(asymmetric);
+    }
+    // The holds go where the asymmetric warp reaches the extremes
+    let hold_phases = (asymmetric.unwarp(extremes.0), asymmetric.unwarp(extremes.1));
+    Box::new(ChainWarp {
+        first: Box::new(DwellWarp::new(hold_phases, config.dwell_top, config.dwell_bottom, config.bpm)),
+        then: Box::new(asymmetric),
+    })
 }
 
 // ===== Layer 2: Shaper =====
@@ -302,17 +736,18 @@ pub struct Shaper {
     target_depth: f32,       // Target depth
     current_depth: f32,      // Current depth (transitions smoothly to target)
     direction: DepthDirection,
-    target_reversed: bool,
-    current_reversal: f32,   // 0.0 = normal, 1.0 = reversed (transitions smoothly)
+    // Applied instantly: any blend between y and 1 - y squeezes the stroke to nothing halfway
+    // through, so MotorController re-phases the waveform instead to keep the output continuous
+    reversed: bool,
     
     // Transition state
     transitioning: bool,
 }
 
 const TRANSITION_SPEED: f32 = 0.1;  // Depth units per second
-const REVERSAL_SPEED: f32 = 0.5;    // Reversal units per second (faster)
-const PAUSE_SPEED: f32 = 0.3;       // Pause position transition speed (y units per second)
+pub const PAUSE_SPEED: f32 = 0.3;   // Pause position transition speed (stroke fractions per second)
 const TRANSITION_THRESHOLD: f32 = 0.01;
+const MAX_CYCLE_DT: f32 = 0.1;      // A longer stall (Modbus retry, NVS write) is stepped as if it were this long
 
 impl Shaper {
     pub fn new(depth: f32, direction: DepthDirection, reversed: bool) -> Self {
@@ -320,38 +755,41 @@ impl Shaper {
             target_depth: depth,
             current_depth: depth,
             direction,
-            target_reversed: reversed,
-            current_reversal: if reversed { 1.0 } else { 0.0 },
+            reversed,
             transitioning: true,
         }
     }
     
     pub fn set_params(&mut self, new_depth: f32, new_direction: DepthDirection, new_reversed: bool) {
-        // Check if depth or reversal changed significantly
+        // Check if depth changed significantly
         let depth_changed = (self.target_depth - new_depth).abs() > TRANSITION_THRESHOLD;
-        let reversal_changed = self.target_reversed != new_reversed;
         
-        if depth_changed || reversal_changed {
+        if depth_changed {
             self.transitioning = true;
         }
         
         // Update target parameters
         self.target_depth = new_depth;
         self.direction = new_direction;
-        self.target_reversed = new_reversed;
+        self.reversed = new_reversed;
     }
     
-    pub fn shape(&mut self, y_in: f32, speed_in: f32, dt: f32) -> (f32, f32) {
-        // Update transitions if needed
+    // Moves the depth alone, easing into it like set_params()
+    pub fn set_depth(&mut self, new_depth: f32) {
+        if new_depth != self.current_depth {
+            self.transitioning = true;
+        }
+        self.target_depth = new_depth;
+    }
+
+    // Advance the depth transition, also called while the carriage is parked
+    pub fn update(&mut self, dt: f32) {
         if self.transitioning {
-            let mut depth_done = false;
-            let mut reversal_done = false;
-            
             // Update depth
             let depth_diff = self.target_depth - self.current_depth;
             if depth_diff.abs() < TRANSITION_THRESHOLD {
                 self.current_depth = self.target_depth;
-                depth_done = true;
+                self.transitioning = false;
             } else {
                 let step = TRANSITION_SPEED * dt;
                 if depth_diff > 0.0 {
@@ -360,36 +798,18 @@ impl Shaper {
                     self.current_depth = (self.current_depth - step).max(self.target_depth);
                 }
             }
-            
-            // Update reversal
-            let target_reversal = if self.target_reversed { 1.0 } else { 0.0 };
-            let reversal_diff = target_reversal - self.current_reversal;
-            if reversal_diff.abs() < TRANSITION_THRESHOLD {
-                self.current_reversal = target_reversal;
-                reversal_done = true;
-            } else {
-                let step = REVERSAL_SPEED * dt;
-                if reversal_diff > 0.0 {
-                    self.current_reversal = (self.current_reversal + step).min(target_reversal);
-                } else {
-                    self.current_reversal = (self.current_reversal - step).max(target_reversal);
-                }
-            }
-            
-            // Clear transitioning flag when both are done
-            if depth_done && reversal_done {
-                self.transitioning = false;
-            }
         }
+    }
+
+    pub fn shape(&mut self, y_in: f32, speed_in: f32, dt: f32) -> (f32, f32) {
+        self.update(dt);
         
-        // Apply smooth reversal: lerp between y_in and (1 - y_in)
-        let r = self.current_reversal;
-        let y = y_in * (1.0 - r) + (1.0 - y_in) * r;
-        // Simplified: y = y_in * (1 - 2r) + r
-        
-        // Chain rule for speed: dy/dt = (∂y/∂y_in) * (dy_in/dt)
-        // ∂y/∂y_in = 1 - 2r
-        let speed = speed_in * (1.0 - 2.0 * r);
+        // Apply reversal: y = 1 - y_in, so dy/dt = -dy_in/dt
+        let (y, speed) = if self.reversed {
+            (1.0 - y_in, -speed_in)
+        } else {
+            (y_in, speed_in)
+        };
         
         // Then apply depth and direction
         match self.direction {
@@ -408,8 +828,16 @@ impl Shaper {
         }
     }
     
+    // Range of shaped y the waveform currently sweeps
+    pub fn window(&self) -> (f32, f32) {
+        match self.direction {
+            DepthDirection::Top => (0.0, self.current_depth),
+            DepthDirection::Bottom => (1.0 - self.current_depth, 1.0),
+        }
+    }
+
     // Reverse the shaping transformation to get unshaped y from shaped y
-    // Returns None if currently transitioning or if reversal makes inversion ambiguous
+    // Returns None if currently transitioning or depth is too small to invert
     pub fn unshape(&self, y_shaped: f32) -> Option<f32> {
         // Can't reliably unshape during transitions
         if self.transitioning {
@@ -436,76 +864,423 @@ impl Shaper {
             }
         };
         
-        // Then, reverse the reversal transformation
-        // Forward: y = y_in * (1 - r) + (1 - y_in) * r
-        // Simplify: y = y_in * (1 - 2r) + r
-        // Solve for y_in: y_in = (y - r) / (1 - 2r)
-        let r = self.current_reversal;
-        let denominator = 1.0 - 2.0 * r;
-        
-        // When r ≈ 0.5, the transformation loses information (everything maps to 0.5)
-        if denominator.abs() < TRANSITION_THRESHOLD {
-            return None;
-        }
-        
-        let y_in = (y_after_reversal - r) / denominator;
+        // Then, reverse the reversal transformation (it is its own inverse)
+        let y_in = if self.reversed {
+            1.0 - y_after_reversal
+        } else {
+            y_after_reversal
+        };
         
         // Clamp to valid range
         Some(y_in.max(0.0).min(1.0))
     }
 }
 
+// ===== Tease =====
+// Now and then a stroke comes up short, or the carriage holds still for a moment at the end the
+// depth is measured from. Both ease in and out, the carriage never jumps.
+
+// Small deterministic generator (xorshift32), so a seed replays the same teasing
+#[derive(Clone)]
+pub struct Rng(u32);
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        Self(seed.max(1))   // Zero would stay zero
+    }
+
+    // Uniform in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        let mut x = self.0;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.0 = x;
+        (x >> 8) as f32 / (1u32 << 24) as f32
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum TeaseStroke {
+    Normal,
+    Short,                              // The depth dips by the intensity and back over the stroke
+    Hold { phase: f32, seconds: f32 },  // Waiting for the turning point at `phase` to hold there
+    Holding { seconds: f32 },           // Seconds left of the hold
+}
+
+struct Tease {
+    rng: Rng,
+    stroke: TeaseStroke,
+}
+
+// Config changes collected between begin_transaction() and commit_transaction()
+struct Transaction {
+    base: MotorControllerConfig,        // The config when it began, to tell what the changes touched
+    pending: MotorControllerConfig,
+    started: time::Instant,
+}
+
+// What a set_config() touches, worked out up front so the changes can be applied in one order
+// however many fields change together
+struct ConfigChange {
+    waveform: bool,         // Rebuild the waveform
+    warp: bool,             // Rebuild the time warp
+    ramp_sharpness: bool,   // A running thrust eases into the new sharpness in cycle() instead
+    crossfade: bool,        // Fade the new shape in from the old output
+    escalation_reset: bool,
+    resync: Resync,
+}
+
+// How t0 follows a config change, exactly one per set_config()
+enum Resync {
+    Unpause,                        // Pick the waveform up from wherever the carriage is parked
+    Pause,
+    MatchOutput { mirrored: bool }, // Carry on from the old output at its phase in the new shape
+    KeepPhase,                      // Same phase at the new bpm
+    None,
+}
+
+impl ConfigChange {
+    fn between(old: &MotorControllerConfig, new: &MotorControllerConfig) -> Self {
+        let wave_changed = old.wave_func != new.wave_func || old.spline.points != new.spline.points;
+        let sharpness_changed = (old.thrust.sharpness - new.thrust.sharpness).abs() > 0.001;
+        let bpm_changed = (old.bpm - new.bpm).abs() > 0.001;
+        let has_dwell = new.dwell_top > 0.0 || new.dwell_bottom > 0.0;
+        // Dwell times are in seconds, so where they sit in the cycle depends on bpm
+        let dwell_changed = old.dwell_top != new.dwell_top
+            || old.dwell_bottom != new.dwell_bottom
+            || (has_dwell && bpm_changed);
+        let speed_ratio_changed = old.up_speed_ratio != new.up_speed_ratio
+            || old.down_speed_ratio != new.down_speed_ratio;
+        let reversal_changed = old.reversed != new.reversed;
+
+        // A running thrust eases into a new sharpness in cycle(), anything else takes it at once
+        let ramp_sharpness = sharpness_changed && !wave_changed && !new.paused
+            && new.wave_func == "thrust" && new.thrust.sharpness_speed > 0.0;
+        // The time warp depends on the waveform too
+        let waveform = wave_changed || (sharpness_changed && !ramp_sharpness);
+        let warp = waveform || dwell_changed || speed_ratio_changed;
+        // While running, the new shape fades in from the old output at the same phase instead of
+        // jumping to a phase that matches it. A reversal still needs the phase match.
+        let crossfade = warp && !reversal_changed && !new.paused && !old.paused && new.crossfade_time > 0.0;
+
+        // Unpausing and pausing win, then a new shape, which picks its phase at the new bpm itself
+        let resync = if !new.paused && old.paused {
+            Resync::Unpause
+        } else if new.paused {
+            Resync::Pause
+        } else if (warp || reversal_changed) && !crossfade {
+            Resync::MatchOutput { mirrored: reversal_changed }
+        } else if bpm_changed {
+            Resync::KeepPhase
+        } else {
+            Resync::None
+        };
+
+        // A new depth or escalation starts the escalation over from the depth
+        let escalation_reset = old.depth != new.depth
+            || old.escalation_step != new.escalation_step
+            || old.escalation_max != new.escalation_max;
+
+        Self { waveform, warp, ramp_sharpness, crossfade, escalation_reset, resync }
+    }
+}
+
+// When the cycle that is at `phase` by `now` started: phase = (t * bpm / 60) % 1, so t = phase * 60 / bpm.
+// A phase outside the cycle or an odd bpm can't panic Duration::from_secs_f32 or put t0 far off, and
+// shortly after boot t0 may not go back further than the clock does.
+fn cycle_start(now: time::Instant, phase: f32, bpm: f32) -> time::Instant {
+    let offset = phase * 60.0 / bpm;
+    let offset = if offset.is_finite() { offset.clamp(0.0, MAX_CYCLE_SECONDS) } else { 0.0 };
+    now.checked_sub(time::Duration::from_secs_f32(offset)).unwrap_or(now)
+}
+
+// ===== Demo =====
+// A slow, shallow sine while the machine sits paused and untouched, so a display unit isn't
+// dead still. It eases in from where the carriage is parked.
+
+struct Demo {
+    start: f32,     // Paused position it started from
+    center: f32,    // Middle of the demo stroke, far enough from the ends for the amplitude
+    elapsed: f32,
+}
+
+impl Demo {
+    fn new(start: f32) -> Self {
+        Self { start, center: start.clamp(DEMO_AMPLITUDE, 1.0 - DEMO_AMPLITUDE), elapsed: 0.0 }
+    }
+
+    // Shaped (y, dy/dt) after another dt seconds
+    fn advance(&mut self, dt: f32) -> (f32, f32) {
+        use std::f32::consts::PI;
+        self.elapsed += dt;
+        // Smoothstep, so the ease in starts and ends without a jump in speed
+        let s = (self.elapsed / DEMO_RAMP).min(1.0);
+        let ramp = s * s * (3.0 - 2.0 * s);
+        let ramp_speed = if s < 1.0 { 6.0 * s * (1.0 - s) / DEMO_RAMP } else { 0.0 };
+        let omega = 2.0 * PI * DEMO_BPM / 60.0;
+        let (sin, cos) = (omega * self.elapsed).sin_cos();
+        let offset = self.center - self.start + DEMO_AMPLITUDE * sin;
+        let y = self.start + ramp * offset;
+        let speed = ramp_speed * offset + ramp * DEMO_AMPLITUDE * omega * cos;
+        (y, speed)
+    }
+}
+
+// ===== Strokes =====
+// The one definition of a completed stroke: the cycle phase wrapping from 1 back to 0 while the
+// waveform runs. Paused time never counts, and wherever the phase is set anew (unpause, a
+// waveform switch matched to the position) the detector resyncs instead of counting the jump.
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct StrokeEvent {
+    pub stroke: u64,                // Count since the controller started, the first is 1
+    pub duration_ms: Option<u32>,   // Since the previous boundary, None for the first after a resync
+}
+
+struct StrokeDetector {
+    last_phase: Option<f32>,        // Phase in the previous running cycle(), None after a resync
+    last_boundary: Option<time::Instant>,
+    completed: u64,
+    log: VecDeque<StrokeEvent>,     // The latest STROKE_LOG_SIZE events
+}
+
+impl StrokeDetector {
+    fn new() -> Self {
+        Self { last_phase: None, last_boundary: None, completed: 0, log: VecDeque::with_capacity(STROKE_LOG_SIZE) }
+    }
+
+    // The phase jumped or stopped, start tracking from the next one
+    fn resync(&mut self) {
+        self.last_phase = None;
+        self.last_boundary = None;
+    }
+
+    // Takes the phase of each running cycle(), returns the event when a stroke completes
+    fn update(&mut self, u: f32, now: time::Instant) -> Option<StrokeEvent> {
+        let wrapped = self.last_phase.is_some_and(|last| u < last);
+        self.last_phase = Some(u);
+        if !wrapped {
+            return None;
+        }
+        self.completed += 1;
+        let event = StrokeEvent {
+            stroke: self.completed,
+            duration_ms: self.last_boundary.map(|t| now.duration_since(t).as_millis() as u32),
+        };
+        self.last_boundary = Some(now);
+        if self.log.len() == STROKE_LOG_SIZE {
+            self.log.pop_front();
+        }
+        self.log.push_back(event);
+        Some(event)
+    }
+}
+
 // ===== Layer 3: Position Generator =====
-// Maps y ∈ [0, 1] to motor position
+// Maps y ∈ [0, 1] to motor position, easing into the ends of travel within the deadzone
+
+pub const MAX_END_DEADZONE: f32 = 0.25;
+const END_SPEED_FACTOR: f32 = 0.25;     // Fraction of the speed left right at the ends
+const DEADZONE_SPEED: f32 = 0.05;       // Deadzone change per second, a jump would move the carriage
 
 pub struct PositionGenerator {
     pos_min: i32,
     pos_max: i32,
+    target_deadzone: f32,
+    deadzone: f32,          // Fraction of the stroke at each end, transitions smoothly to target
 }
 
 impl PositionGenerator {
-    pub fn new(pos_min: i32, pos_max: i32) -> Self {
-        Self { pos_min, pos_max }
+    pub fn new(pos_min: i32, pos_max: i32, deadzone: f32) -> Self {
+        Self { pos_min, pos_max, target_deadzone: deadzone, deadzone }
     }
-    
+
+    pub fn set_deadzone(&mut self, deadzone: f32) {
+        self.target_deadzone = deadzone;
+    }
+
+    pub fn update(&mut self, dt: f32) {
+        let step = DEADZONE_SPEED * dt;
+        let diff = self.target_deadzone - self.deadzone;
+        self.deadzone += diff.clamp(-step, step);
+    }
+
     pub fn generate(&self, y: f32, speed_y: f32) -> (i32, f32) {
+        let (y, slope) = self.cushion(y);
         let pos_range = (self.pos_max - self.pos_min) as f32;
         let position = (y * pos_range + self.pos_min as f32) as i32;
-        let speed = speed_y * pos_range;
+        let speed = speed_y * slope * pos_range;
         (position, speed)
     }
+
+    // Motor position back to y, the inverse of generate
+    pub fn normalize(&self, position: i32) -> f32 {
+        let cushioned = ((position - self.pos_min) as f32 / (self.pos_max - self.pos_min) as f32).clamp(0.0, 1.0);
+        // The cushion is monotonic, bisect for the y that maps onto the position
+        let (mut lo, mut hi) = (0.0f32, 1.0f32);
+        for _ in 0..20 {
+            let mid = (lo + hi) / 2.0;
+            if self.cushion(mid).0 < cushioned { lo = mid } else { hi = mid }
+        }
+        (lo + hi) / 2.0
+    }
+
+    // Within the deadzone y is bent along a cubic that keeps both ends and the slope at the
+    // zone's edge, so the range stays the same and only the approach to the end slows down.
+    // Returns the cushioned y and its slope.
+    fn cushion(&self, y: f32) -> (f32, f32) {
+        let zone = self.deadzone;
+        let distance = y.min(1.0 - y);
+        if zone <= 0.0 || distance >= zone {
+            return (y, 1.0);
+        }
+        let u = (distance / zone).max(0.0);
+        let k = END_SPEED_FACTOR;
+        let g = (k - 1.0) * u * u * u + (2.0 - 2.0 * k) * u * u + k * u;
+        let slope = (1.0 - k) * (4.0 * u - 3.0 * u * u) + k;
+        let cushioned = zone * g;
+        if y < 0.5 { (cushioned, slope) } else { (1.0 - cushioned, slope) }
+    }
+}
+
+// Per-device BPM limits, for machines that can't safely run the full range. Kept in NVS.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SpeedLimits {
+    pub bpm_min: f32,
+    pub bpm_max: f32,
+    pub depth_factor: f32,   // k in bpm_max * (1 - k * depth), 0 = same limit at any depth
+}
+
+impl Default for SpeedLimits {
+    fn default() -> Self {
+        Self {
+            bpm_min: 1.0,
+            bpm_max: 500.0,
+            depth_factor: 0.0,
+        }
+    }
+}
+
+impl SpeedLimits {
+    // Deeper strokes at the same BPM mean faster movement, so the ceiling drops with depth
+    pub fn max_bpm_at_depth(&self, depth: f32) -> f32 {
+        (self.bpm_max * (1.0 - self.depth_factor * depth)).max(self.bpm_min)
+    }
+
+    pub fn clamp_bpm(&self, bpm: f32, depth: f32) -> f32 {
+        bpm.clamp(self.bpm_min, self.max_bpm_at_depth(depth))
+    }
+}
+
+// How far and how fast POST /jog may move the paused position, and where it drifts back to once
+// the jogging stops. Kept in NVS.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JogLimits {
+    pub step: f32,       // One jog step, for requests that count steps
+    pub max_step: f32,   // Largest move one request can make
+    pub max_rate: f32,   // Stroke fractions per second, at most PAUSE_SPEED so the carriage keeps up
+    pub recenter_after: f32,  // Seconds without a jog before returning to center, 0 = stay put
+    pub center: f32,     // Neutral paused position, 0-1 of the full stroke
+}
+
+impl Default for JogLimits {
+    fn default() -> Self {
+        Self {
+            step: 0.01,
+            max_step: 0.1,
+            max_rate: PAUSE_SPEED,
+            recenter_after: 0.0,
+            center: 0.5,
+        }
+    }
+}
+
+// What happens to config values from the API that are outside their range
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RangePolicy {
+    #[default]
+    Clamp,      // Pull them into range and say so
+    Reject,     // Refuse the whole update
+}
+
+// A config value that was pulled into its range
+pub struct Clamped {
+    pub field: &'static str,
+    pub value: f32,
+    pub clamped: f32,
+}
+
+impl std::fmt::Display for Clamped {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {} -> {}", self.field, self.value, self.clamped)
+    }
+}
+
+// Ends of travel, for GET /range. The mm values need the counts_per_mm calibration.
+#[derive(Serialize)]
+pub struct RangeInfo {
+    pub pos_min: i32,
+    pub pos_max: i32,
+    pub counts_per_mm: Option<f32>,
+    pub stroke_mm: Option<f32>,     // Full homed stroke
+    pub depth_mm: Option<f32>,      // Part of it the current depth moves over
 }
 
 pub struct MotorController<'a> {
     motor: Box<dyn Motor + Send + 'a>,
     waveform: Box<dyn WaveformGenerator>,
+    time_warp: Box<dyn TimeWarp>,
     shaper: Shaper,
     position_gen: PositionGenerator,
     config: MotorControllerConfig,
     config_version: u32,
+    saved_version: u32,             // config_version last written to NVS, see mark_saved()
+    config_listeners: Vec<mpsc::Sender<u32>>,   // Told the new config_version on every change
+    speed_limits: SpeedLimits,
+    range_policy: RangePolicy,
+    counts_per_mm: Option<f32>,     // Mechanical calibration, None until set
     t0: time::Instant,
     last_cycle: time::Instant,
     
-    // Pause state
-    current_paused_y: f32,   // Current y when paused (for smooth transitions)
+    // Pause state. Parking happens in the physical stroke (shaped y), outside the depth window if need be
+    current_paused_pos: f32,   // Current shaped y while parked, tracks the output while running
+    moving_up: bool,           // Which way the output last moved, resyncs carry on that way
+    entering_window: bool,     // Resumed while parked outside the depth window, ramping into it first
+    idle_time: f32,            // Seconds paused without a config change
+    idle_parked: bool,         // Idle long enough, parking at idle_park_position
+    low_power: bool,           // Drive power turned down to IDLE_POWER
+    demo: Option<Demo>,        // Demo motion running while paused, see demo_mode
+    transaction: Option<Transaction>,
+    position_moved: Option<time::Instant>,  // Last move_paused_position() not yet counted in config_version
+    jog_limits: JogLimits,
+    jog_budget: f32,           // Distance jogs may still move, refills at jog_limits.max_rate
+    last_jog: Option<time::Instant>,
+    jogged: bool,              // Jogged since the last other change, recenters after recenter_after
+    interlock_engaged: bool,   // False while the interlock switch is open, the carriage stays put
+    end_stop: Option<(LimitEnd, f32)>,  // Pressed limit switch and where the carriage was, nothing goes past it
+
+    morph: Option<SplineMorph>,
+    crossfade: Option<Crossfade>,
+    tease: Tease,
+    escalated_depth: Option<f32>,   // Depth the escalation has reached, None before the first step
+    current_sharpness: f32,     // What the thrust waveform is built with, follows config.thrust.sharpness at sharpness_speed
+    pattern: Option<PatternRunner>,
+    patterns_finished: u32,     // Patterns that ran to their end, for event feedback
+    strokes: StrokeDetector,
+    recording: Option<Recording>,
+
+    // Output filter state, None until the first cycle after (re)initialization
+    filtered_position: Option<f32>,
 }
 
 impl<'a> MotorController<'a> {
     pub fn new(motor: Box<dyn Motor + Send + 'a>, config: MotorControllerConfig) -> Self {
-        let waveform: Box<dyn WaveformGenerator> = match config.wave_func.as_str() {
-            "sine" => Box::new(SineWaveform),
-            "thrust" => Box::new(ThrustWaveform::new(config.sharpness)),
-            "spline" => {
-                match SplineWaveform::from_points(&config.spline_points, SPLINE_RESOLUTION) {
-                    Ok(wf) => Box::new(wf),
-                    Err(e) => {
-                        eprintln!("Error creating spline waveform: {}. Falling back to sine wave.", e);
-                        Box::new(SineWaveform)
-                    }
-                }
-            },
-            _ => Box::new(SineWaveform),
-        };
+        let waveform = build_waveform(&config);
+        let time_warp = build_time_warp(&config, waveform.as_ref());
         
         let direction = if config.depth_top {
             DepthDirection::Top
@@ -514,36 +1289,83 @@ impl<'a> MotorController<'a> {
         };
         
         let shaper = Shaper::new(config.depth, direction, config.reversed);
-        let position_gen = PositionGenerator::new(0, 0); // Will be updated after homing
+        let position_gen = PositionGenerator::new(0, 0, config.end_deadzone); // Will be updated after homing
         
         let now = time::Instant::now();
         Self {
             motor,
             waveform,
+            time_warp,
             shaper,
             position_gen,
             config: config.clone(),
             config_version: 0,
+            saved_version: 0,
+            config_listeners: Vec::new(),
+            speed_limits: SpeedLimits::default(),
+            range_policy: RangePolicy::default(),
+            counts_per_mm: None,
             t0: now,
             last_cycle: now,
-            current_paused_y: config.paused_position,
+            current_paused_pos: config.paused_position,
+            moving_up: true,
+            entering_window: false,
+            idle_time: 0.0,
+            idle_parked: false,
+            low_power: false,
+            demo: None,
+            transaction: None,
+            position_moved: None,
+            jog_limits: JogLimits::default(),
+            jog_budget: 0.0,
+            last_jog: None,
+            jogged: false,
+            interlock_engaged: true,
+            end_stop: None,
+            morph: None,
+            crossfade: None,
+            tease: Tease { rng: Rng::new(TEASE_SEED), stroke: TeaseStroke::Normal },
+            escalated_depth: None,
+            current_sharpness: config.thrust.sharpness,
+            pattern: None,
+            patterns_finished: 0,
+            strokes: StrokeDetector::new(),
+            recording: None,
+            filtered_position: None,
         }
     }
 
-    pub fn init_motor(&mut self) -> Result<(), anyhow::Error> {
-        self.motor.homing()?;
+    // Homes the motor, or takes `known_range` as the ends of travel when given and the carriage
+    // is where that range says it can be
+    pub fn init_motor(&mut self, homing_progress: &mut dyn FnMut(HomingPhase), known_range: Option<(i32, i32)>) -> Result<(), anyhow::Error> {
+        let known_range = match known_range {
+            Some((pos_min, pos_max)) => {
+                let position = self.motor.read_position()?;
+                // Homing parks the range inside the ends, the carriage can rest out to them
+                let (margin_min, margin_max) = self.motor.homing_margins();
+                let lowest = pos_min.saturating_sub_unsigned(margin_min);
+                let highest = pos_max.saturating_add_unsigned(margin_max);
+                if (lowest..=highest).contains(&position) {
+                    Some((pos_min, pos_max))
+                } else {
+                    log::warn!("The motor reports position {}, outside the saved range [{}, {}], homing", position, pos_min, pos_max);
+                    None
+                }
+            }
+            None => None,
+        };
+        match known_range {
+            Some((pos_min, pos_max)) => self.motor.set_range(pos_min, pos_max)?,
+            None => self.motor.homing(homing_progress)?,
+        }
         
         // Update position generator with actual range
-        self.position_gen = PositionGenerator::new(self.motor.pos_min(), self.motor.pos_max());
+        self.position_gen = PositionGenerator::new(self.motor.pos_min(), self.motor.pos_max(), self.config.end_deadzone);
 
-        self.motor.set_max_power(350)?;
-        self.motor.set_acceleration(40000)?;
-        self.motor.set_position_ring_ratio(3000)?;
-        self.motor.set_speed_ring_ratio(3000)?;
+        self.apply_tuning()?;
 
         // Read current motor position and sync waveform generator
-        let position = self.motor.read_position()?;
-        let pos_normalized = (position - self.motor.pos_min()) as f32 / (self.motor.pos_max() - self.motor.pos_min()) as f32;
+        let pos_normalized = self.read_normalized_position()?;
         
         // Try to unshape the current position to get the waveform y
         match self.shaper.unshape(pos_normalized) {
@@ -552,14 +1374,10 @@ impl<'a> MotorController<'a> {
                 println!("Syncing waveform to current position (y={})", waveform_y);
                 
                 // Find phase that produces this y
-                let phase = self.waveform.find_x_for_y(waveform_y);
+                let phase = self.find_phase_for_y(waveform_y);
                 
                 // Set t0 so waveform starts at this phase
-                let time_offset = phase * 60.0 / self.config.bpm;
-                self.t0 = time::Instant::now() - time::Duration::from_secs_f32(time_offset);
-                
-                // Update paused position tracking
-                self.current_paused_y = waveform_y;
+                self.t0 = cycle_start(time::Instant::now(), phase, self.config.bpm);
             }
             None => {
                 // Position is outside current depth range, trigger transition
@@ -569,87 +1387,156 @@ impl<'a> MotorController<'a> {
                 self.shaper.transitioning = true;
                 
                 // Start waveform at a default phase (middle of cycle)
-                let time_offset = 0.25 * 60.0 / self.config.bpm;  // Start at 0.25 phase (near middle)
-                self.t0 = time::Instant::now() - time::Duration::from_secs_f32(time_offset);
-                
-                // Set paused position to middle as well
-                self.current_paused_y = 0.5;
+                self.t0 = cycle_start(time::Instant::now(), 0.25, self.config.bpm);  // Start at 0.25 phase (near middle)
             }
         }
 
+        // When paused, park from where the carriage actually is
+        self.current_paused_pos = pos_normalized;
+
         Ok(())
     }
 
-    pub fn set_config(&mut self, config: MotorControllerConfig) -> Result<(), anyhow::Error> {
-        let wave_changed = self.config.wave_func != config.wave_func || self.config.spline_points != config.spline_points;
-        let sharpness_changed = (self.config.sharpness - config.sharpness).abs() > 0.001;
-        let bpm_changed = (self.config.bpm - config.bpm).abs() > 0.001;
+    pub fn set_config(&mut self, mut config: MotorControllerConfig) -> Result<(), anyhow::Error> {
+        if !config.paused && self.recording.is_some() {
+            log::warn!("Stop the recording before unpausing");
+            config.paused = true;
+        }
+        if !self.interlock_engaged {
+            if !config.paused {
+                log::warn!("The interlock is open, close it before unpausing");
+            }
+            config.paused = true;
+            config.paused_position = self.current_paused_pos;
+        }
+        if self.end_stop.is_some() {
+            if !config.paused {
+                log::warn!("A limit switch is pressed, move off it before unpausing");
+            }
+            config.paused = true;
+            config.paused_position = self.clear_of_end_stop(config.paused_position);
+        }
 
-        // Grab current waveform output value before changing anything
-        let last_y_wave = if self.config.paused {
-            self.current_paused_y
-        } else {
-            let elapsed = time::Instant::now().duration_since(self.t0).as_secs_f32();
-            let (y, _) = self.waveform.evaluate(elapsed, self.config.bpm);
-            y
-        };
-        
-        // Update waveform if wave type or sharpness changed
-        if wave_changed || sharpness_changed {
-            self.waveform = match config.wave_func.as_str() {
-                "sine" => Box::new(SineWaveform),
-                "thrust" => Box::new(ThrustWaveform::new(config.sharpness)),
-                "spline" => match SplineWaveform::from_points(&config.spline_points, SPLINE_RESOLUTION) {
-                    Ok(wf) => Box::new(wf),
-                    Err(e) => {
-                        log::error!("Error creating spline waveform: {}. Falling back to sine wave.", e);
-                        Box::new(SineWaveform)
-                    }
-                },
-                _ => Box::new(SineWaveform),
-            };
+        self.end_idle()?;
+
+        for clamped in config.clamp_to_ranges(&self.speed_limits)? {
+            log::warn!("Out of range, using {}", clamped);
         }
-        
+        // The ranges only know the configured depth, an escalation that carries on may be deeper
+        if let Some(depth) = self.escalated_depth.filter(|_| !ConfigChange::between(&self.config, &config).escalation_reset) {
+            let bpm = self.speed_limits.clamp_bpm(config.bpm, depth);
+            if bpm != config.bpm {
+                log::warn!("{} BPM is out of range at the escalated depth {}, using {}", config.bpm, depth, bpm);
+                config.bpm = bpm;
+            }
+        }
+
+        if self.config.spline.points != config.spline.points {
+            check_spline_points(&config.spline.points)?;
+        }
+        let change = ConfigChange::between(&self.config, &config);
+        // First, so a drive that doesn't take it leaves the config as it was
+        if self.config.acceleration != config.acceleration {
+            self.motor.set_acceleration(config.acceleration)?;
+        }
+
+        // Everything the resync needs from before the change, taken at one instant
+        let now = time::Instant::now();
+        let old_phase = (now.duration_since(self.t0).as_secs_f32() * self.config.bpm / 60.0) % 1.0;
+        let (last_y_wave, last_speed_wave) = self.output_at_phase(old_phase, self.config.bpm, true);
+        let fade_from = change.crossfade.then(|| self.sample_output());
+
+        // Apply all of the new shape before anything is matched against it
+        self.position_gen.set_deadzone(config.end_deadzone);
+        if !change.ramp_sharpness {
+            self.current_sharpness = config.thrust.sharpness;
+        }
+        if change.waveform {
+            self.waveform = build_waveform(&config);
+            // An explicit waveform change wins over a morph in progress
+            self.morph = None;
+        }
+        if change.warp {
+            self.time_warp = build_time_warp(&config, self.waveform.as_ref());
+        }
+        if let Some(from) = fade_from {
+            self.crossfade = Some(Crossfade { from, start: now, duration: config.crossfade_time });
+        }
+        if change.escalation_reset {
+            self.escalated_depth = None;
+        }
+
         // Update shaper (this will trigger smooth transition if depth/direction changed)
         let direction = if config.depth_top {
             DepthDirection::Top
         } else {
             DepthDirection::Bottom
         };
-        self.shaper.set_params(config.depth, direction, config.reversed);
-        
-        // Handle waveform/timing changes
-        if (wave_changed || sharpness_changed) && !config.paused {
-            // Find phase in new waveform that matches last output of old waveform
-            let target_phase = self.waveform.find_x_for_y(last_y_wave);
-            let time_offset = target_phase * 60.0 / config.bpm;
-            self.t0 = time::Instant::now() - time::Duration::from_secs_f32(time_offset);
-        }
-        // Handle unpause: adjust t0 so waveform matches current_paused_y
-        else if !config.paused && self.config.paused {
-            // Find phase x that produces current_paused_y
-            let target_phase = self.waveform.find_x_for_y(self.current_paused_y);
-            
-            // Calculate time offset: phase = (t * bpm / 60) % 1
-            // t = phase * 60 / bpm
-            let time_offset = target_phase * 60.0 / config.bpm;
-            self.t0 = time::Instant::now() - time::Duration::from_secs_f32(time_offset);
-        }
-        // Handle BPM change: adjust t0 to maintain current phase
-        else if bpm_changed && !config.paused {
-            // Calculate current phase with old BPM
-            let now = time::Instant::now();
-            let elapsed = now.duration_since(self.t0).as_secs_f32();
-            let current_phase = (elapsed * self.config.bpm / 60.0) % 1.0;
-            
-            // Adjust t0 so same phase is maintained with new BPM
-            let new_elapsed = current_phase * 60.0 / config.bpm;
-            self.t0 = now - time::Duration::from_secs_f32(new_elapsed);
+        self.shaper.set_params(self.escalated_depth.unwrap_or(config.depth), direction, config.reversed);
+
+        // Then line t0 up with it, once
+        match change.resync {
+            // The parked position is kept in stroke space, not as a waveform value, so a waveform
+            // changed while paused has nothing to go stale. Unpausing takes its phase from the
+            // waveform as it is by then, built above when the change comes in the same call, and
+            // get_current_state() reports that phase while paused.
+            Resync::Unpause => {
+                if config.warmup_on_unpause {
+                    if let Err(e) = self.warmup() {
+                        log::warn!("Warmup before unpausing failed: {}", e);
+                    }
+                }
+                if config.resync_on_unpause {
+                    match self.read_normalized_position() {
+                        Ok(pos) => {
+                            self.current_paused_pos = pos;
+                            // Start the output filter from the real position too
+                            self.filtered_position = None;
+                        }
+                        Err(e) => log::warn!("Failed to re-read motor position on unpause: {}", e),
+                    }
+                }
+                let (lo, hi) = self.shaper.window();
+                if (lo..=hi).contains(&self.current_paused_pos) {
+                    self.sync_phase_to_position(self.current_paused_pos, config.bpm);
+                } else {
+                    self.entering_window = true;
+                }
+            }
+            Resync::Pause => {
+                self.entering_window = false;
+                self.crossfade = None;
+                self.tease.stroke = TeaseStroke::Normal;
+            }
+            Resync::MatchOutput { mirrored } => {
+                // Find phase in new waveform that matches last output of old waveform, moving the same
+                // way. After a reversal that is the mirrored value, moving the opposite way, so the
+                // carriage itself keeps its direction.
+                let (target_y, rising) = if mirrored {
+                    (1.0 - last_y_wave, last_speed_wave < 0.0)
+                } else {
+                    (last_y_wave, last_speed_wave > 0.0)
+                };
+                let target_phase = if last_speed_wave != 0.0 {
+                    self.find_phase_for_y_moving(target_y, rising)
+                } else {
+                    self.find_phase_for_y(target_y)
+                };
+                self.t0 = cycle_start(now, target_phase, config.bpm);
+                self.strokes.resync();
+                // The old output was matched at its own phase, fading from it would jump
+                self.crossfade = None;
+            }
+            Resync::KeepPhase => {
+                self.t0 = cycle_start(now, old_phase, config.bpm);
+            }
+            Resync::None => {}
         }
         
         // Update config
         self.config = config.clone();
-        self.config_version += 1;
+        self.position_moved = None;
+        self.bump_config_version();
         
         // Save config to file
         // if let Err(e) = config.save_to_file(CONFIG_FILE) {
@@ -659,18 +1546,775 @@ impl<'a> MotorController<'a> {
         Ok(())
     }
 
+    // Layers 1 and 1b: waveform output (y, dy/dt) at `elapsed` seconds since t0
+    fn evaluate_waveform(&self, elapsed: f32) -> (f32, f32) {
+        let u = (elapsed * self.config.bpm / 60.0) % 1.0;
+        self.output_at_phase(u, self.config.bpm, true)
+    }
+
+    // Warped waveform output at cycle phase u, blended with a crossfade in progress
+    fn output_at_phase(&self, u: f32, bpm: f32, fading: bool) -> (f32, f32) {
+        let freq = bpm / 60.0;
+        let (x, dx_du) = self.time_warp.warp(u);
+        let (y, speed) = self.waveform.evaluate(x / freq, bpm);
+        let output = (y, speed * dx_du);
+        match &self.crossfade {
+            Some(fade) => fade.blend(u, bpm, output, fading),
+            None => output,
+        }
+    }
+
+    // The current output over one cycle, frozen so it can fade out while a new waveform takes over.
+    // Includes the dwell and any crossfade still running.
+    fn sample_output(&self) -> SplineWaveform {
+        let resolution = SPLINE_RESOLUTION;
+        let scale = resolution as f32 - 1.0;
+        // At 60 BPM one cycle takes a second, so time is phase and the speed is dy/du
+        let (positions, speeds) = (0..resolution)
+            .map(|i| self.output_at_phase(i as f32 / scale, 60.0, false))
+            .unzip();
+        SplineWaveform { resolution, positions, speeds }
+    }
+
+    // Seeds the tease rolls, the same seed and settings tease the same strokes
+    pub fn seed_tease(&mut self, seed: u32) {
+        self.tease.rng = Rng::new(seed);
+    }
+
+    fn roll_tease(&mut self) -> TeaseStroke {
+        let config = &self.config;
+        if config.tease_probability <= 0.0 || self.tease.rng.next_f32() >= config.tease_probability {
+            return TeaseStroke::Normal;
+        }
+        if self.tease.rng.next_f32() < 0.5 {
+            return TeaseStroke::Short;
+        }
+        // Hold at the turning point on the end the depth is measured from
+        let (x_max, x_min) = self.waveform.extreme_phases();
+        let x = if config.depth_top != config.reversed { x_min } else { x_max };
+        TeaseStroke::Hold { phase: self.time_warp.unwarp(x), seconds: config.tease_intensity * 60.0 / config.bpm }
+    }
+
+    // Deepens by escalation_step each stroke up to escalation_max, then holds there or starts over
+    fn escalate(&mut self) {
+        let config = &self.config;
+        if config.escalation_step <= 0.0 || config.escalation_max <= config.depth {
+            return;
+        }
+        let depth = self.escalated_depth.unwrap_or(config.depth);
+        let next = if depth < config.escalation_max {
+            (depth + config.escalation_step).min(config.escalation_max)
+        } else if config.escalation_reset {
+            config.depth
+        } else {
+            return;
+        };
+        self.escalated_depth = Some(next);
+        // The shaper eases into it, so the stroke deepens gradually rather than in steps
+        self.shaper.set_depth(next);
+        if let Err(e) = self.enforce_bpm_ceiling() {
+            log::warn!("Failed to slow down for the escalated depth: {}", e);
+        }
+    }
+
+    // Tracks the cycle phase, starts each new stroke and runs the tease holds. Returns true while
+    // holding.
+    fn advance_stroke(&mut self, now: time::Instant, dt: f32) -> bool {
+        let freq = self.config.bpm / 60.0;
+        if let TeaseStroke::Holding { seconds } = self.tease.stroke {
+            // Moving t0 along with the clock keeps the phase where it is
+            self.t0 += time::Duration::from_secs_f32(dt);
+            self.tease.stroke = if seconds > dt { TeaseStroke::Holding { seconds: seconds - dt } } else { TeaseStroke::Normal };
+            return true;
+        }
+        let u = (now.duration_since(self.t0).as_secs_f32() * freq) % 1.0;
+        if self.strokes.update(u, now).is_some() {
+            self.tease.stroke = self.roll_tease();
+            self.escalate();
+        }
+        if let TeaseStroke::Hold { phase, seconds } = self.tease.stroke {
+            if u >= phase {
+                // Back to exactly the turning point, the carriage is at rest there
+                self.t0 += time::Duration::from_secs_f32((u - phase) / freq);
+                self.strokes.last_phase = Some(phase);
+                self.tease.stroke = TeaseStroke::Holding { seconds };
+                return true;
+            }
+        }
+        false
+    }
+
+    // Shortens a teased stroke towards the end the depth is measured from, like a brief depth
+    // change. The dip is zero with zero slope at the stroke boundaries, so speed stays continuous.
+    fn tease_shape(&self, shaped_y: f32, shaped_speed: f32) -> (f32, f32) {
+        if self.tease.stroke != TeaseStroke::Short {
+            return (shaped_y, shaped_speed);
+        }
+        let anchor = if self.config.depth_top { 0.0 } else { 1.0 };
+        let angle = std::f32::consts::PI * self.strokes.last_phase.unwrap_or(0.0);
+        let intensity = self.config.tease_intensity;
+        let scale = 1.0 - intensity * angle.sin().powi(2);
+        let scale_speed = -intensity * std::f32::consts::PI * (2.0 * angle).sin() * self.config.bpm / 60.0;
+        let offset = shaped_y - anchor;
+        (anchor + offset * scale, shaped_speed * scale + offset * scale_speed)
+    }
+
+    fn set_low_power(&mut self, low_power: bool) -> Result<(), anyhow::Error> {
+        self.motor.set_max_power(if low_power { IDLE_POWER } else { RUN_POWER })?;
+        self.low_power = low_power;
+        Ok(())
+    }
+
+    // Counts the time spent paused. After idle_park_timeout the carriage moves to
+    // idle_park_position. With that or power_save_on_pause, the drive power goes down once the
+    // carriage is where it rests.
+    fn advance_idle(&mut self, dt: f32) -> Result<(), anyhow::Error> {
+        if !self.interlock_engaged {
+            return Ok(());
+        }
+        self.idle_time += dt;
+        let arrived = self.current_paused_pos == self.paused_target();
+        if self.config.demo_mode {
+            // The demo takes the place of the idle park
+            if self.demo.is_none() && arrived && self.idle_time >= DEMO_DELAY && self.end_stop.is_none() {
+                log::info!("Paused for {:.0}s, starting the demo motion", DEMO_DELAY);
+                self.demo = Some(Demo::new(self.current_paused_pos));
+                if self.low_power {
+                    self.set_low_power(false)?;
+                }
+            }
+            if self.demo.is_some() {
+                return Ok(());
+            }
+        }
+        let recenter_after = self.jog_limits.recenter_after;
+        if self.jogged && recenter_after > 0.0 && self.idle_time >= recenter_after {
+            // Glides slower than a jog, like it is being let go of rather than moved
+            let center = self.jog_limits.center;
+            let step = RECENTER_SPEED * dt;
+            let position = self.config.paused_position;
+            if (center - position).abs() > step {
+                self.config.paused_position = position + step.copysign(center - position);
+            } else {
+                self.config.paused_position = center;
+                log::info!("No jog for {:.0}s, back at center", recenter_after);
+                self.jogged = false;
+            }
+        }
+        let timeout = self.config.idle_park_timeout;
+        if !self.idle_parked && timeout > 0.0 && self.idle_time >= timeout && !self.config.demo_mode {
+            log::info!("Paused for {:.0}s, parking", timeout);
+            self.idle_parked = true;
+        }
+        let resting = self.idle_parked || self.config.power_save_on_pause;
+        if resting && !self.low_power && arrived {
+            self.set_low_power(true)?;
+        }
+        Ok(())
+    }
+
+    // Any change counts as activity and ends an idle park or the demo
+    fn end_idle(&mut self) -> Result<(), anyhow::Error> {
+        self.idle_time = 0.0;
+        self.idle_parked = false;
+        self.demo = None;
+        self.jogged = false;
+        if self.low_power {
+            self.set_low_power(false)?;
+        }
+        Ok(())
+    }
+
+    // Where the carriage goes while paused
+    fn paused_target(&self) -> f32 {
+        let target = if self.idle_parked { self.config.idle_park_position } else { self.config.paused_position };
+        self.clear_of_end_stop(target)
+    }
+
+    // `position`, or where the pressed limit switch stopped the carriage if it is further toward that end
+    fn clear_of_end_stop(&self, position: f32) -> f32 {
+        match self.end_stop {
+            Some((LimitEnd::Min, stop)) => position.max(stop),
+            Some((LimitEnd::Max, stop)) => position.min(stop),
+            None => position,
+        }
+    }
+
+    fn apply_tuning(&mut self) -> Result<(), anyhow::Error> {
+        self.motor.set_max_power(RUN_POWER)?;
+        // The drive runs on with the acceleration it has, not worth failing the start over
+        if let Err(e) = self.motor.set_acceleration(self.config.acceleration) {
+            log::error!("Failed to set the acceleration: {}", e);
+        }
+        self.motor.set_position_ring_ratio(3000)?;
+        self.motor.set_speed_ring_ratio(3000)?;
+        Ok(())
+    }
+
+    // Re-asserts the tuning registers and nudges the carriage back and forth a little, so a
+    // drive that has been idle for a while doesn't stutter on the first stroke
+    fn warmup(&mut self) -> Result<(), anyhow::Error> {
+        const DITHER: f32 = 0.002;     // Fraction of the stroke
+        const SETTLE: time::Duration = time::Duration::from_millis(30);
+
+        self.apply_tuning()?;
+        let pos = self.current_paused_pos;
+        let nudge = if pos + DITHER <= 1.0 { pos + DITHER } else { pos - DITHER };
+        for y in [nudge, pos] {
+            let (position, _) = self.position_gen.generate(y, 0.0);
+            self.motor.write_position(position, 0.0)?;
+            std::thread::sleep(SETTLE);
+        }
+        Ok(())
+    }
+
+    // Further past an end than LOST_POSITION_MARGIN of the range than the carriage can get
+    fn outside_homed_range(&self, position: i32) -> bool {
+        let (pos_min, pos_max) = (self.motor.pos_min() as f32, self.motor.pos_max() as f32);
+        let margin = (pos_max - pos_min) * LOST_POSITION_MARGIN;
+        !(pos_min - margin..=pos_max + margin).contains(&(position as f32))
+    }
+
+    // Motor position as a fraction of the homed range
+    fn read_normalized_position(&mut self) -> Result<f32, anyhow::Error> {
+        let position = self.motor.read_position()?;
+        Ok(self.position_gen.normalize(position))
+    }
+
+    // Set t0 so the shaped waveform passes through `shaped_y` now, moving the way the output
+    // last moved. Resuming on the way down otherwise turns the carriage around.
+    fn sync_phase_to_position(&mut self, shaped_y: f32, bpm: f32) {
+        let y = self.shaper.unshape(shaped_y).unwrap_or(0.5);
+        let target_phase = self.resume_phase(y);
+        self.t0 = cycle_start(time::Instant::now(), target_phase, bpm);
+        self.strokes.resync();
+    }
+
+    // Cycle phase the waveform picks up from at waveform value y, see sync_phase_to_position()
+    fn resume_phase(&self, y: f32) -> f32 {
+        // A reversed shaper runs the waveform upside down
+        let rising = self.moving_up != self.shaper.reversed;
+        self.find_phase_for_y_moving(y, rising)
+    }
+
+    // Cycle phase u at which the (warped) waveform outputs y
+    fn find_phase_for_y(&self, y: f32) -> f32 {
+        self.time_warp.unwarp(self.waveform.find_x_for_y(y))
+    }
+
+    // Cycle phase u at which the (warped) waveform passes through y rising (or falling)
+    fn find_phase_for_y_moving(&self, y: f32, rising: bool) -> f32 {
+        self.time_warp.unwarp(self.waveform.find_x_for_y_moving(y, rising))
+    }
+
+    // Applies new limits, pulling the current BPM into range if needed
+    pub fn set_speed_limits(&mut self, limits: SpeedLimits) -> Result<(), anyhow::Error> {
+        self.speed_limits = limits;
+        self.enforce_bpm_ceiling()
+    }
+
+    // Depth the strokes actually run at
+    fn effective_depth(&self) -> f32 {
+        self.escalated_depth.unwrap_or(self.config.depth)
+    }
+
+    // Pulls the BPM under the ceiling at the effective depth. Goes around a transaction, a limit
+    // can't wait for the commit. Call it whenever the limits or the effective depth change.
+    fn enforce_bpm_ceiling(&mut self) -> Result<(), anyhow::Error> {
+        let bpm = self.speed_limits.clamp_bpm(self.config.bpm, self.effective_depth());
+        if bpm != self.config.bpm {
+            log::warn!("{} BPM is out of range at depth {}, using {}", self.config.bpm, self.effective_depth(), bpm);
+            let mut config = self.config.clone();
+            config.bpm = bpm;
+            self.set_config(config)?;
+        }
+        Ok(())
+    }
+
+    pub fn get_speed_limits(&self) -> SpeedLimits {
+        self.speed_limits.clone()
+    }
+
+    pub fn set_range_policy(&mut self, policy: RangePolicy) {
+        self.range_policy = policy;
+    }
+
+    // Applies the range policy to a config from the API before it goes to set_config. Returns
+    // what was clamped, or an error naming what is out of range when rejecting.
+    pub fn check_ranges(&self, config: &mut MotorControllerConfig) -> Result<Vec<Clamped>, OssmError> {
+        let mut checked = config.clone();
+        let clamped = checked.clamp_to_ranges(&self.speed_limits)?;
+        if self.range_policy == RangePolicy::Reject && !clamped.is_empty() {
+            let fields: Vec<String> = clamped.iter()
+                .map(|c| format!("{} {} (nearest allowed {})", c.field, c.value, c.clamped))
+                .collect();
+            return Err(OssmError::invalid(format!("Out of range: {}", fields.join(", "))));
+        }
+        *config = checked;
+        Ok(clamped)
+    }
+
+    // Changes the config, or only the pending one while a transaction is open
     pub fn update_config(&mut self, f: impl FnOnce(&mut MotorControllerConfig)) -> Result<(), anyhow::Error> {
+        if let Some(transaction) = &mut self.transaction {
+            f(&mut transaction.pending);
+            return Ok(());
+        }
         let mut config = self.config.clone();
         f(&mut config);
         self.set_config(config)
     }
 
+    // Moves the paused target alone, for jogging. Unlike set_config() it leaves transitions, the
+    // waveform and the phase be, and the version bump (with the NVS save it triggers) waits
+    // until the position has settled for POSITION_SETTLE seconds. The caller checks the range.
+    pub fn move_paused_position(&mut self, position: f32) -> Result<(), anyhow::Error> {
+        if !self.interlock_engaged {
+            anyhow::bail!("The interlock is open");
+        }
+        if self.transaction.is_some() {
+            return self.update_config(|config| config.paused_position = position);
+        }
+        self.end_idle()?;
+        self.config.paused_position = self.clear_of_end_stop(position);
+        self.position_moved = Some(time::Instant::now());
+        Ok(())
+    }
+
+    pub fn set_jog_limits(&mut self, limits: JogLimits) {
+        self.jog_limits = limits;
+    }
+
+    pub fn get_jog_limits(&self) -> JogLimits {
+        self.jog_limits.clone()
+    }
+
+    // Nudges the paused target by `delta`, clamped to the stroke, and returns where it ends up.
+    // Lighter still than move_paused_position(): no version bump at all, so a jog alone is
+    // never saved. Only for a paused motor, the caller checks.
+    pub fn jog(&mut self, delta: f32) -> Result<f32, anyhow::Error> {
+        if !delta.is_finite() {
+            anyhow::bail!("Jog delta is not a number");
+        }
+        if !self.interlock_engaged {
+            anyhow::bail!("The interlock is open");
+        }
+        self.end_idle()?;
+        // The target can't run more than a short burst ahead of max_rate, so held down buttons
+        // don't queue up moves the carriage is still working through after they are released
+        let limits = &self.jog_limits;
+        let now = time::Instant::now();
+        let refill = self.last_jog.map_or(JOG_BURST, |t| now.duration_since(t).as_secs_f32());
+        self.last_jog = Some(now);
+        self.jog_budget = (self.jog_budget + refill * limits.max_rate).min(JOG_BURST * limits.max_rate);
+        let delta = delta.clamp(-limits.max_step, limits.max_step).clamp(-self.jog_budget, self.jog_budget);
+        self.jog_budget -= delta.abs();
+        self.jogged = true;
+        self.config.paused_position = self.clear_of_end_stop((self.config.paused_position + delta).clamp(0.0, 1.0));
+        Ok(self.config.paused_position)
+    }
+
+    // Collects update_config() changes until commit_transaction(), which applies them as one
+    // set_config: one transition, one version bump, one NVS write
+    pub fn begin_transaction(&mut self) -> Result<(), anyhow::Error> {
+        if self.transaction.is_some() {
+            anyhow::bail!("A transaction is already open, commit or abort it first");
+        }
+        self.transaction = Some(Transaction {
+            base: self.config.clone(),
+            pending: self.config.clone(),
+            started: time::Instant::now(),
+        });
+        Ok(())
+    }
+
+    // Applies the fields the transaction changed, on top of whatever else set the config since
+    // it began. Returns how many fields changed.
+    pub fn commit_transaction(&mut self) -> Result<usize, anyhow::Error> {
+        let Some(transaction) = self.transaction.take() else {
+            anyhow::bail!("No transaction open");
+        };
+        let base = serde_json::to_value(&transaction.base)?;
+        let pending = serde_json::to_value(&transaction.pending)?;
+        let mut merged = serde_json::to_value(&self.config)?;
+        let mut changed = 0;
+        for (name, value) in pending.as_object().into_iter().flatten() {
+            if base.get(name) != Some(value) {
+                merged[name] = value.clone();
+                changed += 1;
+            }
+        }
+        if changed > 0 {
+            self.set_config(serde_json::from_value(merged)?)?;
+        }
+        Ok(changed)
+    }
+
+    // Drops the collected changes, returns false if there was no transaction
+    pub fn abort_transaction(&mut self) -> bool {
+        self.transaction.take().is_some()
+    }
+
+    // Cross-fades from the current waveform into a spline over `duration` seconds. The config
+    // switches to the new points once the morph completes.
+    pub fn morph_to_spline(&mut self, points: Vec<f32>, duration: f32) -> Result<(), anyhow::Error> {
+        check_spline_points(&points)?;
+        if duration <= 0.0 {
+            self.morph = None;
+            return self.update_config(|config| {
+                config.wave_func = "spline".to_string();
+                config.spline.points = points;
+            });
+        }
+        let to = SplineWaveform::from_points(&points, spline_resolution(points.len()))?;
+        let from = SplineWaveform::sample(self.waveform.as_ref(), to.resolution);
+        self.morph = Some(SplineMorph {
+            from,
+            to,
+            points,
+            start: time::Instant::now(),
+            duration,
+            alpha: 0.0,
+        });
+        Ok(())
+    }
+
+    // Steps the thrust sharpness toward the configured one. The phase stays where it is, the
+    // shape bends a little each cycle instead of switching at once.
+    fn advance_sharpness(&mut self, dt: f32) {
+        let diff = self.config.thrust.sharpness - self.current_sharpness;
+        if diff == 0.0 {
+            return;
+        }
+        let step = self.config.thrust.sharpness_speed * dt;
+        self.current_sharpness = if self.config.paused || step <= 0.0 || diff.abs() <= step {
+            self.config.thrust.sharpness
+        } else {
+            self.current_sharpness + step.copysign(diff)
+        };
+        if self.config.wave_func == "thrust" && self.morph.is_none() {
+            self.waveform = Box::new(ThrustWaveform::new(self.current_sharpness));
+            self.time_warp = build_time_warp(&self.config, self.waveform.as_ref());
+        }
+    }
+
+    fn advance_morph(&mut self, now: time::Instant) -> Result<(), anyhow::Error> {
+        let Some(morph) = self.morph.as_mut() else {
+            return Ok(());
+        };
+        let alpha = (now.duration_since(morph.start).as_secs_f32() / morph.duration).min(1.0);
+        if alpha >= 1.0 {
+            // The final blend is the target spline, so applying it through set_config keeps the phase
+            let points = std::mem::take(&mut morph.points);
+            self.morph = None;
+            return self.update_config(|config| {
+                config.wave_func = "spline".to_string();
+                config.spline.points = points;
+            });
+        }
+        if alpha - morph.alpha >= MORPH_STEP {
+            morph.alpha = alpha;
+            self.waveform = Box::new(morph.from.blend(&morph.to, alpha));
+        }
+        Ok(())
+    }
+
+    // Stops whatever is driving the motion and parks where the carriage is, for when the motor
+    // loop can't go on. Nothing moves once commands get through again.
+    pub fn halt(&mut self) -> Result<(), anyhow::Error> {
+        self.pattern = None;
+        self.morph = None;
+        self.recording = None;
+        if self.transaction.take().is_some() {
+            log::warn!("Halting, the open transaction is aborted");
+        }
+        self.force_pause(Some(self.current_paused_pos))
+    }
+
+    // Pauses right away, parking at `position` or else the configured paused position.
+    // update_config() would only add the pause to an open transaction, which stays open and
+    // commits its other changes on top of this.
+    pub fn force_pause(&mut self, position: Option<f32>) -> Result<(), anyhow::Error> {
+        let mut config = self.config.clone();
+        config.paused = true;
+        config.paused_position = position.unwrap_or(config.paused_position);
+        self.set_config(config)
+    }
+
+    // Follows the interlock switch. Opening it halts the motor, and while it is open nothing
+    // moves the carriage. Closing it leaves the motor paused until it is started again.
+    pub fn set_interlock(&mut self, engaged: bool) -> Result<(), anyhow::Error> {
+        if engaged == self.interlock_engaged {
+            return Ok(());
+        }
+        if engaged {
+            log::info!("Interlock closed, unpause to resume");
+            self.interlock_engaged = true;
+            Ok(())
+        } else {
+            log::warn!("Interlock open, halting the motor");
+            self.interlock_engaged = false;
+            self.demo = None;
+            self.halt()
+        }
+    }
+
+    pub fn interlock_engaged(&self) -> bool {
+        self.interlock_engaged
+    }
+
+    // Follows the limit switches. A press halts the motor, and for as long as the switch stays
+    // pressed the carriage can't be unpaused or moved any further toward that end. Moving the
+    // paused position back off it still works.
+    pub fn set_limit_switch(&mut self, pressed: Option<LimitEnd>) -> Result<(), anyhow::Error> {
+        if pressed == self.end_stop.map(|(end, _)| end) {
+            return Ok(());
+        }
+        let Some(end) = pressed else {
+            log::info!("Limit switch released");
+            self.end_stop = None;
+            return Ok(());
+        };
+        log::error!("{:?} limit switch pressed, halting the motor", end);
+        self.end_stop = Some((end, self.current_paused_pos));
+        self.halt()
+    }
+
+    // Rebuilds the link after the motor dropped off the bus (e.g. a power blip) and re-sends the
+    // tuning, which the drive forgets. Parks wherever the motor says it is.
+    pub fn reinitialize(&mut self) -> Result<(), anyhow::Error> {
+        self.motor.reconnect()?;
+        self.apply_tuning()?;
+        // normalize() clamps to the range, so the check has to look at the raw count
+        let position = self.motor.read_position()?;
+        if self.outside_homed_range(position) {
+            log::warn!("Motor reports position {}, outside the homed range [{}, {}], it may have lost its position. Reboot to home again.",
+                position, self.motor.pos_min(), self.motor.pos_max());
+        }
+        let pos = self.position_gen.normalize(position);
+        self.current_paused_pos = pos;
+        self.filtered_position = None;
+        self.update_config(|config| {
+            config.paused = true;
+            config.paused_position = pos;
+        })
+    }
+
+    // Back to the default settings, keeping whether it's paused and where it parks. Goes through
+    // set_config so depth and speed change as smoothly as with any other update.
+    pub fn reset_config(&mut self) -> Result<(), anyhow::Error> {
+        self.pattern = None;
+        self.morph = None;
+        let config = MotorControllerConfig {
+            paused: self.config.paused,
+            paused_position: self.config.paused_position,
+            ..MotorControllerConfig::default()
+        };
+        self.set_config(config)
+    }
+
+    // Applies the first step now, the rest follow as running time passes
+    pub fn run_pattern(&mut self, pattern: Pattern) -> Result<(), anyhow::Error> {
+        let runner = PatternRunner::new(pattern);
+        let step = runner.current_step().clone();
+        self.update_config(|config| step.apply(config))?;
+        self.pattern = Some(runner);
+        Ok(())
+    }
+
+    // Leaves the current settings as they are. Returns whether a pattern was running.
+    pub fn stop_pattern(&mut self) -> bool {
+        self.pattern.take().is_some()
+    }
+
+    // Index of the pattern step being run, if any
+    pub fn pattern_step(&self) -> Option<usize> {
+        self.pattern.as_ref().map(|runner| runner.current_index())
+    }
+
+    fn advance_pattern(&mut self, dt: f32) -> Result<(), anyhow::Error> {
+        let Some(runner) = self.pattern.as_mut() else {
+            return Ok(());
+        };
+        match runner.advance(dt) {
+            PatternProgress::Running => Ok(()),
+            PatternProgress::NextStep => {
+                let step = runner.current_step().clone();
+                log::info!("Pattern step {}", runner.current_index() + 1);
+                self.update_config(|config| step.apply(config))
+            }
+            PatternProgress::Finished => {
+                log::info!("Pattern finished");
+                self.pattern = None;
+                self.patterns_finished += 1;
+                Ok(())
+            }
+        }
+    }
+
+    // Starts capturing the carriage position while it's moved by hand. Only while paused, the drive
+    // power is lowered until the recording stops.
+    pub fn start_recording(&mut self, max_samples: usize) -> Result<(), anyhow::Error> {
+        if !self.config.paused {
+            return Err(anyhow::anyhow!("Pause before recording"));
+        }
+        if self.recording.is_some() {
+            return Err(anyhow::anyhow!("Already recording"));
+        }
+        let max_samples = max_samples.min((RECORD_MAX_SECONDS / RECORD_INTERVAL) as usize);
+        self.motor.set_max_power(RECORD_POWER)?;
+        // The hand decides where the carriage goes now
+        self.idle_time = 0.0;
+        self.idle_parked = false;
+        self.demo = None;
+        self.low_power = false;
+        self.recording = Some(Recording {
+            samples: Vec::with_capacity(max_samples),
+            max_samples,
+            last_sample: time::Instant::now(),
+        });
+        Ok(())
+    }
+
+    // Turns the recording into a spline played back at the recorded speed, one cycle per recording.
+    // Returns the spline points.
+    pub fn stop_recording(&mut self) -> Result<Vec<f32>, anyhow::Error> {
+        let recording = self.recording.take().ok_or_else(|| anyhow::anyhow!("Not recording"))?;
+        self.apply_tuning()?;
+        let (points, duration) = recording.to_spline_points()?;
+        let paused_position = self.current_paused_pos;
+        self.update_config(|config| {
+            config.wave_func = "spline".to_string();
+            config.spline.points = points.clone();
+            config.bpm = 60.0 / duration;
+            // Stay where the hand left the carriage
+            config.paused_position = paused_position;
+        })?;
+        Ok(points)
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+
+    // Lets the drive follow the hand and samples where it is
+    fn record_cycle(&mut self, now: time::Instant) -> Result<(), anyhow::Error> {
+        let position = self.motor.read_position()?;
+        self.motor.write_position(position, 0.0)?;
+        self.current_paused_pos = self.position_gen.normalize(position);
+        self.filtered_position = None;
+
+        if let Some(recording) = self.recording.as_mut() {
+            let due = now.duration_since(recording.last_sample).as_secs_f32() >= RECORD_INTERVAL;
+            if due && recording.samples.len() < recording.max_samples {
+                recording.samples.push(self.current_paused_pos);
+                recording.last_sample = now;
+                if recording.samples.len() == recording.max_samples {
+                    log::warn!("Recording is full, stop it to keep what was captured");
+                }
+            }
+        }
+        self.motor.cycle()?;
+        Ok(())
+    }
+
+    // One bus round-trip on the 57AIM30
+    pub fn read_telemetry(&mut self) -> crate::error::Result<MotorTelemetry> {
+        self.motor.read_telemetry()
+    }
+
+    #[cfg(feature = "modbus-debug")]
+    pub fn read_registers(&mut self, addr: u16, values: &mut [u16]) -> crate::error::Result<()> {
+        self.motor.read_registers(addr, values)
+    }
+
+    // Ends of travel in motor counts
+    pub fn range(&self) -> (i32, i32) {
+        (self.motor.pos_min(), self.motor.pos_max())
+    }
+
+    pub fn homing_repeatability(&self) -> Option<HomingRepeatability> {
+        self.motor.homing_repeatability()
+    }
+
+    pub fn set_counts_per_mm(&mut self, counts_per_mm: Option<f32>) {
+        self.counts_per_mm = counts_per_mm;
+    }
+
+    pub fn unit_converter(&self) -> UnitConverter {
+        let (pos_min, pos_max) = self.range();
+        UnitConverter::new(pos_min, pos_max, self.counts_per_mm)
+    }
+
+    // A distance in motor counts in mm, None without calibration
+    fn to_mm(&self, counts: f32) -> Option<f32> {
+        self.counts_per_mm.map(|counts_per_mm| counts / counts_per_mm)
+    }
+
+    pub fn range_info(&self) -> RangeInfo {
+        let (pos_min, pos_max) = self.range();
+        let stroke_mm = self.to_mm((pos_max - pos_min) as f32);
+        RangeInfo {
+            pos_min,
+            pos_max,
+            counts_per_mm: self.counts_per_mm,
+            stroke_mm,
+            depth_mm: stroke_mm.map(|mm| mm * self.config.depth),
+        }
+    }
+
     pub fn get_config(&self) -> MotorControllerConfig {
         self.config.clone()
     }
 
-    pub fn get_config_version(&self) -> u32 {
-        self.config_version
+    // Full in-and-out strokes since the controller started
+    pub fn stroke_count(&self) -> u64 {
+        self.strokes.completed
+    }
+
+    // Stroke events after stroke number `after`, as far back as the log goes. Subscribers keep
+    // the last number they saw; a count lower than that means a new controller started over.
+    pub fn strokes_since(&self, after: u64) -> Vec<StrokeEvent> {
+        self.strokes.log.iter().filter(|event| event.stroke > after).copied().collect()
+    }
+
+    pub fn round_trip_time(&self) -> Option<time::Duration> {
+        self.motor.round_trip_time()
+    }
+
+    pub fn patterns_finished(&self) -> u32 {
+        self.patterns_finished
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.config.paused
+    }
+
+    // The config and its version if it changed since the last mark_saved(). With `settle`, a
+    // paused position move that hasn't settled yet counts too, for a save that can't wait.
+    pub fn unsaved_config(&mut self, settle: bool) -> Option<(u32, MotorControllerConfig)> {
+        if settle && self.position_moved.take().is_some() {
+            self.bump_config_version();
+        }
+        (self.config_version != self.saved_version).then(|| (self.config_version, self.config.clone()))
+    }
+
+    pub fn mark_saved(&mut self, version: u32) {
+        self.saved_version = version;
+    }
+
+    // Receives the new config version whenever the config changes, instead of polling for it.
+    // Changes come in from HTTP, commands and patterns alike. Dropping the receiver unsubscribes.
+    pub fn subscribe_config_changes(&mut self) -> mpsc::Receiver<u32> {
+        let (sender, receiver) = mpsc::channel();
+        self.config_listeners.push(sender);
+        receiver
+    }
+
+    fn bump_config_version(&mut self) {
+        self.config_version += 1;
+        let version = self.config_version;
+        self.config_listeners.retain(|listener| listener.send(version).is_ok());
     }
 
     pub fn get_current_state(&self) -> StateResponse {
@@ -681,72 +2325,163 @@ impl<'a> MotorController<'a> {
         let cycles = elapsed * self.config.bpm / 60.0;
         let x = cycles % 1.0;
         
-        // Calculate waveform y
-        let (y_wave, speed_wave) = if self.config.paused {
-            (self.current_paused_y, 0.0)
+        // Calculate waveform y and shaped y, parked positions are already shaped. Parked inside
+        // the window, the phase is where unpausing picks up, on the waveform as it is now.
+        let (x, y_wave, shaped_y, shaped_speed) = if self.config.paused || self.entering_window {
+            let pos = self.current_paused_pos;
+            match self.shaper.unshape(pos) {
+                Some(y) => (self.resume_phase(y), y, pos, 0.0),
+                None => (x, pos, pos, 0.0),
+            }
         } else {
-            self.waveform.evaluate(elapsed, self.config.bpm)
-        };
-        
-        // Calculate shaped y
-        let (shaped_y, shaped_speed) = {
+            let (y_wave, speed_wave) = self.evaluate_waveform(elapsed);
             let mut temp_shaper = self.shaper.clone();
-            temp_shaper.shape(y_wave, speed_wave, 0.0)
+            let (shaped_y, shaped_speed) = temp_shaper.shape(y_wave, speed_wave, 0.0);
+            (x, y_wave, shaped_y, shaped_speed)
         };
         
         // Calculate position
         let (position, speed) = self.position_gen.generate(shaped_y, shaped_speed);
         
+        let (timestamp, time_synced) = crate::clock::timestamp();
         StateResponse {
             config: self.get_config(),
+            timestamp,
+            time_synced,
             t: elapsed,
             x,
             y: y_wave,
             shaped_y,
             position,
             speed,
+            position_mm: self.to_mm((position - self.motor.pos_min()) as f32),
+            stroke_mm: self.to_mm((self.motor.pos_max() - self.motor.pos_min()) as f32),
         }
     }
 
+    // First-order low-pass on the commanded position. While filtering, the speed
+    // feed-forward is the filtered position's rate of change so the two stay consistent.
+    fn filter_output(&mut self, position: i32, speed: f32, tau: f32, dt: f32) -> (i32, f32) {
+        let target = position as f32;
+        let previous = match self.filtered_position {
+            Some(previous) if tau > 0.0 && dt > 0.0 => previous,
+            _ => {
+                self.filtered_position = Some(target);
+                return (position, speed);
+            }
+        };
+
+        let alpha = dt / (tau + dt);
+        let filtered = previous + alpha * (target - previous);
+        self.filtered_position = Some(filtered);
+        (filtered.round() as i32, (filtered - previous) / dt)
+    }
+
+    // Extra smoothing time constant around the stroke ends: the full `reversal_smoothing`
+    // while the carriage is stopped, fading out as it approaches one stroke per cycle
+    fn reversal_time_constant(&self, shaped_speed: f32) -> f32 {
+        if self.config.reversal_smoothing <= 0.0 || self.config.paused || self.entering_window {
+            return 0.0;
+        }
+        let stroke_per_cycle = self.config.depth * self.config.bpm / 60.0;
+        if stroke_per_cycle <= 0.0 {
+            return 0.0;
+        }
+        let slowness = (1.0 - shaped_speed.abs() / stroke_per_cycle).max(0.0);
+        self.config.reversal_smoothing * slowness * slowness
+    }
+
     pub fn cycle(&mut self) -> Result<(), anyhow::Error> {
         let now = time::Instant::now();
-        let dt = now.duration_since(self.last_cycle).as_secs_f32();
+        // duration_since saturates at zero if the clock ever steps backwards
+        let dt = now.duration_since(self.last_cycle).as_secs_f32().min(MAX_CYCLE_DT);
         self.last_cycle = now;
+        if self.recording.is_some() {
+            return self.record_cycle(now);
+        }
+        if self.transaction.as_ref().is_some_and(|t| t.started.elapsed().as_secs_f32() > TRANSACTION_TIMEOUT) {
+            // Don't hold pattern steps and schedules back forever for a forgotten commit
+            log::warn!("Transaction open for over {}s, committing it", TRANSACTION_TIMEOUT);
+            self.commit_transaction()?;
+        }
+        if self.position_moved.is_some_and(|t| t.elapsed().as_secs_f32() >= POSITION_SETTLE) {
+            self.position_moved = None;
+            self.bump_config_version();
+        }
+        self.advance_morph(now)?;
+        self.advance_sharpness(dt);
+        if self.crossfade.as_ref().is_some_and(|fade| fade.alpha() >= 1.0) {
+            self.crossfade = None;
+        }
+        if self.config.paused {
+            self.advance_idle(dt)?;
+        } else {
+            self.advance_pattern(dt)?;
+        }
         
-        // Layer 1: Generate waveform or smooth to paused position
-        let (y_wave, speed_wave) = if self.config.paused {
-            // Smoothly transition to paused position
-            let target_y = self.config.paused_position;
-            let diff = target_y - self.current_paused_y;
+        let demo = self.demo.as_mut().map(|demo| demo.advance(dt));
+        let (shaped_y, shaped_speed) = if let Some((y, speed)) = demo {
+            self.shaper.update(dt);
+            self.strokes.resync();
+            self.current_paused_pos = y;
+            (y, speed)
+        } else if self.config.paused || self.entering_window {
+            // Smoothly move to the paused position (or into the depth window when resuming).
+            // This is already in physical stroke space, so it bypasses the shaper.
+            self.shaper.update(dt);
+            self.strokes.resync();
+            let target_pos = if self.config.paused {
+                self.paused_target()
+            } else {
+                let (lo, hi) = self.shaper.window();
+                self.current_paused_pos.clamp(lo, hi)
+            };
+            let diff = target_pos - self.current_paused_pos;
             
             let speed = if diff.abs() < TRANSITION_THRESHOLD {
-                self.current_paused_y = target_y;
+                self.current_paused_pos = target_pos;
+                if !self.config.paused {
+                    // Inside the window now, start the waveform from here
+                    self.entering_window = false;
+                    self.sync_phase_to_position(target_pos, self.config.bpm);
+                }
                 0.0
             } else {
                 let step = PAUSE_SPEED * dt;
                 if diff > 0.0 {
-                    self.current_paused_y = (self.current_paused_y + step).min(target_y);
+                    self.current_paused_pos = (self.current_paused_pos + step).min(target_pos);
                     PAUSE_SPEED
                 } else {
-                    self.current_paused_y = (self.current_paused_y - step).max(target_y);
+                    self.current_paused_pos = (self.current_paused_pos - step).max(target_pos);
                     -PAUSE_SPEED
                 }
             };
             
-            (self.current_paused_y, speed)
+            (self.current_paused_pos, speed)
         } else {
+            // Layer 1: Generate waveform
+            let holding = self.advance_stroke(now, dt);
             let elapsed = now.duration_since(self.t0).as_secs_f32();
-            let (y, speed) = self.waveform.evaluate(elapsed, self.config.bpm);
+            let (y_wave, speed_wave) = self.evaluate_waveform(elapsed);
+            let speed_wave = if holding { 0.0 } else { speed_wave };
+
+            // Layer 2: Apply shaping (with smooth transitions)
+            let (shaped_y, shaped_speed) = self.shaper.shape(y_wave, speed_wave, dt);
+            let (shaped_y, shaped_speed) = self.tease_shape(shaped_y, shaped_speed);
             // Track current position for smooth pause transition
-            self.current_paused_y = y;
-            (y, speed)
+            self.current_paused_pos = shaped_y;
+            (shaped_y, shaped_speed)
         };
         
-        // Layer 2: Apply shaping (with smooth transitions)
-        let (shaped_y, shaped_speed) = self.shaper.shape(y_wave, speed_wave, dt);
-        
+        if shaped_speed != 0.0 {
+            self.moving_up = shaped_speed > 0.0;
+        }
+
         // Layer 3: Convert to position and write
+        self.position_gen.update(dt);
         let (position, speed) = self.position_gen.generate(shaped_y, shaped_speed);
+        let tau = self.config.output_smoothing + self.reversal_time_constant(shaped_speed);
+        let (position, speed) = self.filter_output(position, speed, tau, dt);
         self.motor.write_position(position, speed)?;
 
         self.motor.cycle()?;
@@ -762,25 +2497,317 @@ pub struct MotorControllerConfig {
     pub depth_top: bool,     // true = top [0, depth], false = bottom [1-depth, 1]
     pub reversed: bool,      // reverse waveform direction
     pub wave_func: String,   // "sine", "thrust", or "spline"
-    pub sharpness: f32,      // For thrust waveform: rise duration (0.01-0.99), higher = longer rise
-    #[serde(default)]
-    pub spline_points: Vec<f32>,
+    // Each waveform's own parameters, kept while another one runs. Flattened, so the JSON keeps
+    // its sharpness, sharpness_speed and spline_points fields.
+    #[serde(flatten)]
+    pub thrust: ThrustParams,
+    #[serde(flatten)]
+    pub spline: SplineParams,
     pub paused: bool,
-    pub paused_position: f32,
+    pub paused_position: f32,   // Where to park when paused, 0-1 of the full stroke regardless of depth
+    #[serde(default)]
+    pub dwell_top: f32,      // Seconds to hold at the top of each stroke
+    #[serde(default)]
+    pub dwell_bottom: f32,   // Seconds to hold at the bottom of each stroke
+    #[serde(default = "default_speed_ratio")]
+    pub up_speed_ratio: f32,   // Relative speed of the rising half of the waveform
+    #[serde(default = "default_speed_ratio")]
+    pub down_speed_ratio: f32, // Relative speed of the falling half, only the ratio of the two matters
+    #[serde(default)]
+    pub tease_probability: f32, // Chance for each stroke to come up short or hold, 0 = off
+    #[serde(default = "default_tease_intensity")]
+    pub tease_intensity: f32,   // How much shorter a teased stroke gets, or how many cycles a hold lasts
+    #[serde(default)]
+    pub escalation_step: f32,   // Depth added each stroke, starting from depth, 0 = off
+    #[serde(default = "default_escalation_max")]
+    pub escalation_max: f32,    // Depth the escalation stops at
+    #[serde(default)]
+    pub escalation_reset: bool, // Start over from depth at escalation_max instead of holding there
+    #[serde(default)]
+    pub output_smoothing: f32,   // Low-pass time constant in seconds on the commanded position, 0 = off
+    #[serde(default)]
+    pub reversal_smoothing: f32, // Extra low-pass time constant in seconds near the stroke ends, 0 = off
+    #[serde(default)]
+    pub end_deadzone: f32,       // Fraction of the stroke at each end where the carriage eases in, 0 = off
+    #[serde(default = "default_crossfade_time")]
+    pub crossfade_time: f32,     // Seconds to fade from the old waveform into a new one while running, 0 = jump
+    #[serde(default)]
+    pub idle_park_timeout: f32,  // Seconds paused before parking at idle_park_position with the power turned down, 0 = off
+    #[serde(default)]
+    pub idle_park_position: f32, // 0-1 of the full stroke, like paused_position
+    #[serde(default)]
+    pub power_save_on_pause: bool, // Turn the drive power down while paused, once parked
+    #[serde(default)]
+    pub demo_mode: bool,         // Slow shallow motion after a while paused and untouched, instead of the idle park
+    #[serde(default = "default_true")]
+    pub resync_on_unpause: bool, // Re-read the motor position on unpause, in case the carriage was moved by hand
+    #[serde(default)]
+    pub warmup_on_unpause: bool, // Re-send drive tuning and dither briefly before resuming
+    #[serde(default = "default_acceleration")]
+    pub acceleration: u16,       // The drive's own acceleration register, applied as soon as it changes
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct ThrustParams {
+    pub sharpness: f32,       // Rise duration (0.01-0.99), higher = longer rise
+    pub sharpness_speed: f32, // Sharpness change per second while running, 0 = at once
+}
+
+// 0.1 is where the web UI has always started thrust, now without overwriting a saved sharpness
+impl Default for ThrustParams {
+    fn default() -> Self {
+        Self { sharpness: 0.1, sharpness_speed: 0.2 }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct SplineParams {
+    #[serde(rename = "spline_points")]
+    pub points: Vec<f32>,
+}
+
+impl Default for SplineParams {
+    fn default() -> Self {
+        Self { points: vec![0.0, 1.0] }   // A sawtooth wave
+    }
+}
+
+// The key of the last `"key":` before a serde error position (1-based line and column), the
+// field whose value the error is in
+fn field_before(text: &str, line: usize, column: usize) -> Option<&str> {
+    if line == 0 {
+        return None;    // No position, e.g. the input ended early
+    }
+    let line_start: usize = text.split_inclusive('\n').take(line - 1).map(str::len).sum();
+    let mut end = (line_start + column).min(text.len());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let before = &text[..end];
+    // Flattened fields are only checked once the whole object is read, their errors point at
+    // its closing brace and not at the field
+    if before.trim_end().ends_with('}') {
+        return None;
+    }
+    let key = before[..before.rfind(':')?].trim_end().strip_suffix('"')?;
+    Some(&key[key.rfind('"')? + 1..])
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_speed_ratio() -> f32 {
+    1.0
+}
+
+fn default_tease_intensity() -> f32 {
+    0.5
+}
+
+fn default_escalation_max() -> f32 {
+    1.0
+}
+
+fn default_acceleration() -> u16 {
+    40000
+}
+
+fn default_crossfade_time() -> f32 {
+    1.0
 }
 
 #[derive(Serialize)]
 pub struct StateResponse {
     pub config: MotorControllerConfig,
+    pub timestamp: f64,      // Unix time, or seconds since boot until the clock is synced
+    pub time_synced: bool,
     pub t: f32,              // Time offset in seconds
     pub x: f32,              // Phase [0, 1]
     pub y: f32,              // Waveform output [0, 1]
     pub shaped_y: f32,       // After shaping [0, 1]
     pub position: i32,       // Motor position
     pub speed: f32,          // Motor speed
+    pub position_mm: Option<f32>,   // From the homed minimum, needs the counts_per_mm calibration
+    pub stroke_mm: Option<f32>,
+}
+
+// Layout of StateResponse::to_binary(), all little-endian:
+//   0  u8   version, STATE_BINARY_VERSION
+//   1  u8   flags, bit 0 time_synced, bit 1 paused
+//   2  u16  reserved, 0
+//   4  f64  timestamp
+//   12 f32  t
+//   16 f32  x
+//   20 f32  y
+//   24 f32  shaped_y
+//   28 i32  position
+//   32 f32  speed
+//   36 f32  position_mm, NaN without the calibration
+//   40 f32  stroke_mm, NaN without the calibration
+pub const STATE_BINARY_VERSION: u8 = 1;
+pub const STATE_BINARY_SIZE: usize = 44;
+
+impl StateResponse {
+    // Fixed-layout encoding for clients polling fast, without the config (see GET /config)
+    pub fn to_binary(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(STATE_BINARY_SIZE);
+        out.push(STATE_BINARY_VERSION);
+        out.push(self.time_synced as u8 | (self.config.paused as u8) << 1);
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&self.timestamp.to_le_bytes());
+        for value in [self.t, self.x, self.y, self.shaped_y] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out.extend_from_slice(&self.position.to_le_bytes());
+        for value in [self.speed, self.position_mm.unwrap_or(f32::NAN), self.stroke_mm.unwrap_or(f32::NAN)] {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+        out
+    }
+
+    // One field as JSON, for GET /state/<field>
+    pub fn field(&self, name: &str) -> Option<serde_json::Value> {
+        let value = match name {
+            "timestamp" => serde_json::json!(self.timestamp),
+            "time_synced" => serde_json::json!(self.time_synced),
+            "t" => serde_json::json!(self.t),
+            "x" => serde_json::json!(self.x),
+            "y" => serde_json::json!(self.y),
+            "shaped_y" => serde_json::json!(self.shaped_y),
+            "position" => serde_json::json!(self.position),
+            "speed" => serde_json::json!(self.speed),
+            "position_mm" => serde_json::json!(self.position_mm),
+            "stroke_mm" => serde_json::json!(self.stroke_mm),
+            _ => return None,
+        };
+        Some(value)
+    }
 }
 
 impl MotorControllerConfig {
+    // Parses a config, often typed or pasted by hand. serde names the type it expected but
+    // rarely the field, so the error adds the field and the expected layout.
+    pub fn from_json(json: &[u8]) -> crate::error::Result<Self> {
+        serde_json::from_slice(json).map_err(|e| {
+            let text = String::from_utf8_lossy(json);
+            let mut message = format!("Invalid config: {}", e);
+            // A missing field is named already, and its position is the end of the object
+            if !message.contains("missing field") {
+                if let Some(field) = field_before(&text, e.line(), e.column()) {
+                    message += &format!(" (near \"{}\")", field);
+                }
+            }
+            OssmError::invalid(format!("{}\nExpected fields:\n{}", message, Self::schema()))
+        })
+    }
+
+    // One line per field with its type and default, e.g. "  bpm: number (default 36.0)"
+    pub fn schema() -> String {
+        let defaults = serde_json::to_value(Self::default()).unwrap();
+        // Numbers went through f64, show them as the f32 they are (0.3, not 0.30000001192092896)
+        let show = |value: &serde_json::Value| match value.as_f64() {
+            Some(number) => (number as f32).to_string(),
+            None => value.to_string(),
+        };
+        let fields = defaults.as_object().into_iter().flatten().map(|(name, value)| {
+            let (kind, default) = match value {
+                serde_json::Value::Bool(_) => ("true|false", show(value)),
+                serde_json::Value::Number(_) => ("number", show(value)),
+                serde_json::Value::String(_) => ("string", show(value)),
+                serde_json::Value::Array(items) => ("[numbers]", format!("[{}]", items.iter().map(show).collect::<Vec<_>>().join(", "))),
+                _ => ("null", show(value)),
+            };
+            format!("  {}: {} (default {})", name, kind, default)
+        });
+        fields.collect::<Vec<_>>().join("\n")
+    }
+
+    // Pulls every value into its range, returning what had to change. Everything that saves or
+    // applies a config goes through here, so the ranges are only defined once.
+    pub fn clamp_to_ranges(&mut self, limits: &SpeedLimits) -> Result<Vec<Clamped>, OssmError> {
+        let depth = self.depth.clamp(0.0, 1.0);
+        let fields: [(&'static str, &mut f32, f32, f32); 19] = [
+            ("depth", &mut self.depth, 0.0, 1.0),
+            ("bpm", &mut self.bpm, limits.bpm_min, limits.max_bpm_at_depth(depth)),
+            ("sharpness", &mut self.thrust.sharpness, 0.0, 1.0),
+            ("sharpness_speed", &mut self.thrust.sharpness_speed, 0.0, 10.0),
+            ("paused_position", &mut self.paused_position, 0.0, 1.0),
+            ("dwell_top", &mut self.dwell_top, 0.0, 10.0),
+            ("dwell_bottom", &mut self.dwell_bottom, 0.0, 10.0),
+            ("up_speed_ratio", &mut self.up_speed_ratio, 0.1, 10.0),
+            ("down_speed_ratio", &mut self.down_speed_ratio, 0.1, 10.0),
+            ("tease_probability", &mut self.tease_probability, 0.0, 1.0),
+            ("tease_intensity", &mut self.tease_intensity, 0.0, 1.0),
+            ("escalation_step", &mut self.escalation_step, 0.0, 0.5),
+            ("escalation_max", &mut self.escalation_max, 0.0, 1.0),
+            ("output_smoothing", &mut self.output_smoothing, 0.0, 1.0),
+            ("reversal_smoothing", &mut self.reversal_smoothing, 0.0, 1.0),
+            ("end_deadzone", &mut self.end_deadzone, 0.0, MAX_END_DEADZONE),
+            ("crossfade_time", &mut self.crossfade_time, 0.0, 10.0),
+            ("idle_park_timeout", &mut self.idle_park_timeout, 0.0, 86400.0),
+            ("idle_park_position", &mut self.idle_park_position, 0.0, 1.0),
+        ];
+        // There is nothing sensible to clamp NaN to
+        if let Some((field, _, _, _)) = fields.iter().find(|(_, value, _, _)| !value.is_finite()) {
+            return Err(OssmError::invalid(format!("{} is not a number", field)));
+        }
+        let mut clamped = Vec::new();
+        for (field, value, lo, hi) in fields {
+            let in_range = value.clamp(lo, hi);
+            if in_range != *value {
+                clamped.push(Clamped { field, value: *value, clamped: in_range });
+                *value = in_range;
+            }
+        }
+        let acceleration = self.acceleration.clamp(MIN_ACCELERATION, MAX_ACCELERATION);
+        if acceleration != self.acceleration {
+            clamped.push(Clamped { field: "acceleration", value: self.acceleration as f32, clamped: acceleration as f32 });
+            self.acceleration = acceleration;
+        }
+        Ok(clamped)
+    }
+
+    // One field as JSON, for GET /config/<field>
+    pub fn field(&self, name: &str) -> Option<serde_json::Value> {
+        let value = match name {
+            "bpm" => serde_json::json!(self.bpm),
+            "depth" => serde_json::json!(self.depth),
+            "depth_top" => serde_json::json!(self.depth_top),
+            "reversed" => serde_json::json!(self.reversed),
+            "wave_func" => serde_json::json!(self.wave_func),
+            "sharpness" => serde_json::json!(self.thrust.sharpness),
+            "sharpness_speed" => serde_json::json!(self.thrust.sharpness_speed),
+            "spline_points" => serde_json::json!(self.spline.points),
+            "paused" => serde_json::json!(self.paused),
+            "paused_position" => serde_json::json!(self.paused_position),
+            "dwell_top" => serde_json::json!(self.dwell_top),
+            "dwell_bottom" => serde_json::json!(self.dwell_bottom),
+            "up_speed_ratio" => serde_json::json!(self.up_speed_ratio),
+            "down_speed_ratio" => serde_json::json!(self.down_speed_ratio),
+            "tease_probability" => serde_json::json!(self.tease_probability),
+            "tease_intensity" => serde_json::json!(self.tease_intensity),
+            "escalation_step" => serde_json::json!(self.escalation_step),
+            "escalation_max" => serde_json::json!(self.escalation_max),
+            "escalation_reset" => serde_json::json!(self.escalation_reset),
+            "output_smoothing" => serde_json::json!(self.output_smoothing),
+            "reversal_smoothing" => serde_json::json!(self.reversal_smoothing),
+            "end_deadzone" => serde_json::json!(self.end_deadzone),
+            "crossfade_time" => serde_json::json!(self.crossfade_time),
+            "idle_park_timeout" => serde_json::json!(self.idle_park_timeout),
+            "idle_park_position" => serde_json::json!(self.idle_park_position),
+            "power_save_on_pause" => serde_json::json!(self.power_save_on_pause),
+            "demo_mode" => serde_json::json!(self.demo_mode),
+            "resync_on_unpause" => serde_json::json!(self.resync_on_unpause),
+            "warmup_on_unpause" => serde_json::json!(self.warmup_on_unpause),
+            "acceleration" => serde_json::json!(self.acceleration),
+            _ => return None,
+        };
+        Some(value)
+    }
+
     pub fn default() -> Self {
         Self {
             bpm: 36.0,
@@ -788,10 +2815,473 @@ impl MotorControllerConfig {
             depth_top: false,
             reversed: false,
             wave_func: "sine".to_string(),
-            sharpness: 0.3,
-            spline_points: vec![0.0, 1.0], // Default to a sawtooth wave
+            thrust: ThrustParams::default(),
+            spline: SplineParams::default(),
             paused: false,
             paused_position: 0.0,
+            dwell_top: 0.0,
+            dwell_bottom: 0.0,
+            up_speed_ratio: default_speed_ratio(),
+            down_speed_ratio: default_speed_ratio(),
+            tease_probability: 0.0,
+            tease_intensity: default_tease_intensity(),
+            escalation_step: 0.0,
+            escalation_max: default_escalation_max(),
+            escalation_reset: false,
+            output_smoothing: 0.0,
+            reversal_smoothing: 0.0,
+            end_deadzone: 0.0,
+            crossfade_time: default_crossfade_time(),
+            idle_park_timeout: 0.0,
+            idle_park_position: 0.0,
+            power_save_on_pause: false,
+            demo_mode: false,
+            resync_on_unpause: true,
+            warmup_on_unpause: false,
+            acceleration: default_acceleration(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Straight lines through `positions`, evenly spaced over one cycle
+    fn polyline(positions: &[f32]) -> SplineWaveform {
+        SplineWaveform { resolution: positions.len(), positions: positions.to_vec(), speeds: vec![0.0; positions.len()] }
+    }
+
+    // At 60 bpm the time offset is the phase
+    fn y_at(waveform: &dyn WaveformGenerator, x: f32) -> f32 {
+        waveform.evaluate(x, 60.0).0
+    }
+
+    #[test]
+    fn find_x_moving_leaves_a_flat_segment_in_the_requested_direction() {
+        // Flat at 0.5 from x = 0.25 to 0.5
+        let waveform = polyline(&[0.0, 0.5, 0.5, 1.0, 0.0]);
+
+        let x = waveform.find_x_for_y_moving(0.5, true);
+        assert!((x - 0.5).abs() < 0.01, "rising from the end of the flat, got {}", x);
+        assert!(y_at(&waveform, x + 0.02) > 0.5);
+
+        let x = waveform.find_x_for_y_moving(0.5, false);
+        assert!((x - 0.875).abs() < 0.01, "on the way down, got {}", x);
+        assert!(y_at(&waveform, x + 0.02) < 0.5);
+    }
+
+    #[test]
+    fn find_x_moving_out_of_range_falls_back_to_the_extremes() {
+        let waveform = polyline(&[0.0, 0.5, 0.5, 1.0, 0.0]);
+        let (x_max, x_min) = waveform.extreme_phases();
+        assert_eq!(waveform.find_x_for_y_moving(1.5, true), x_max);
+        assert_eq!(waveform.find_x_for_y_moving(1.5, false), x_max);
+        assert_eq!(waveform.find_x_for_y_moving(-0.5, true), x_min);
+        assert_eq!(waveform.find_x_for_y_moving(-0.5, false), x_min);
+    }
+
+    #[test]
+    fn find_x_moving_wraps_around_the_cycle() {
+        // Rising through 0.5 right at the wrap
+        let waveform = polyline(&[0.5, 1.0, 0.5, 0.0, 0.5]);
+        let x = waveform.find_x_for_y_moving(0.5, true);
+        assert!((0.0..1.0).contains(&x), "phase {} outside the cycle", x);
+        assert!(!(0.01..=0.99).contains(&x), "expected the wrap, got {}", x);
+        assert!(y_at(&waveform, x + 0.02) > 0.5);
+
+        // Just below the middle, the sine rises shortly before the wrap
+        let x = SineWaveform.find_x_for_y_moving(0.4, true);
+        assert!((0.9..1.0).contains(&x), "expected just before the wrap, got {}", x);
+        assert!((y_at(&SineWaveform, x) - 0.4).abs() < 0.001);
+        assert!(SineWaveform.evaluate(x, 60.0).1 > 0.0);
+    }
+
+    #[test]
+    fn thrust_is_continuous_at_the_peak() {
+        for sharpness in [0.05, 0.3, 0.5, 0.8, 0.99] {
+            let waveform = ThrustWaveform::new(sharpness);
+            let (y, speed) = waveform.evaluate(sharpness, 60.0);
+            assert!((y - 1.0).abs() < 1e-4 && speed.abs() < 1e-3, "sharpness {}: ({}, {})", sharpness, y, speed);
+            // A thousandth of the shorter phase to either side
+            let h = 1e-3 * sharpness.min(1.0 - sharpness);
+            let (y_rise, speed_rise) = waveform.evaluate(sharpness - h, 60.0);
+            let (y_fall, speed_fall) = waveform.evaluate(sharpness + h, 60.0);
+            assert!((y_rise - 1.0).abs() < 1e-3 && (y_fall - 1.0).abs() < 1e-3, "sharpness {}", sharpness);
+            assert!((0.0..0.01).contains(&speed_rise), "sharpness {}: rise ends at {}", sharpness, speed_rise);
+            assert!((-0.01..=0.0).contains(&speed_fall), "sharpness {}: fall starts at {}", sharpness, speed_fall);
+        }
+    }
+
+    #[test]
+    fn thrust_speed_is_the_derivative_of_y() {
+        const H: f32 = 1e-3;
+        for sharpness in [0.05, 0.3, 0.5, 0.8, 0.99] {
+            let waveform = ThrustWaveform::new(sharpness);
+            for i in 1..20 {
+                let x = i as f32 / 20.0;
+                if (x - sharpness).abs() < 2.0 * H {
+                    continue;
+                }
+                let (_, speed) = waveform.evaluate(x, 60.0);
+                let slope = (y_at(&waveform, x + H) - y_at(&waveform, x - H)) / (2.0 * H);
+                assert!((speed - slope).abs() < 0.02 * speed.abs().max(1.0), "sharpness {} at {}: {} vs {}", sharpness, x, speed, slope);
+            }
+        }
+    }
+
+    #[test]
+    fn find_x_moving_matches_the_value_and_the_direction() {
+        let spline = SplineWaveform::from_points(&[0.0, 1.0, 0.4], SPLINE_RESOLUTION).unwrap();
+        let waveforms: [(&str, &dyn WaveformGenerator); 4] = [
+            ("sine", &SineWaveform),
+            ("thrust", &ThrustWaveform::new(0.3)),
+            ("short thrust", &ThrustWaveform::new(0.05)),
+            ("spline", &spline),
+        ];
+        for (name, waveform) in waveforms {
+            for i in 1..10 {
+                let y = i as f32 / 10.0;
+                for rising in [true, false] {
+                    let x = waveform.find_x_for_y_moving(y, rising);
+                    let (found_y, speed) = waveform.evaluate(x, 60.0);
+                    assert!((found_y - y).abs() < 0.001, "{} {} rising {}: y {} at {}", name, y, rising, found_y, x);
+                    assert_eq!(speed > 0.0, rising, "{} {}: speed {} at {}", name, y, speed, x);
+                }
+            }
+        }
+    }
+
+    const TRAVEL: i32 = 10000;
+
+    // Takes every position written as where it is
+    struct FakeMotor {
+        position: i32,
+        pos_min: i32,
+        pos_max: i32,
+        margins: (u32, u32),
+    }
+
+    impl Motor for FakeMotor {
+        fn cycle(&mut self) -> crate::error::Result<()> {
+            Ok(())
+        }
+        fn homing(&mut self, _progress: &mut dyn FnMut(HomingPhase)) -> crate::error::Result<()> {
+            self.set_range(0, TRAVEL)
+        }
+        fn set_range(&mut self, pos_min: i32, pos_max: i32) -> crate::error::Result<()> {
+            self.pos_min = pos_min;
+            self.pos_max = pos_max;
+            Ok(())
+        }
+        fn read_position(&mut self) -> crate::error::Result<i32> {
+            Ok(self.position)
+        }
+        fn reconnect(&mut self) -> crate::error::Result<()> {
+            Ok(())
+        }
+        fn read_telemetry(&mut self) -> crate::error::Result<MotorTelemetry> {
+            Err(OssmError::invalid("No telemetry"))
+        }
+        fn write_position(&mut self, position: i32, _speed: f32) -> crate::error::Result<()> {
+            self.position = position;
+            Ok(())
+        }
+        fn pos_min(&self) -> i32 {
+            self.pos_min
+        }
+        fn pos_max(&self) -> i32 {
+            self.pos_max
+        }
+        fn homing_margins(&self) -> (u32, u32) {
+            self.margins
+        }
+        fn set_max_power(&mut self, _power: u16) -> crate::error::Result<()> {
+            Ok(())
+        }
+        fn set_acceleration(&mut self, _acceleration: u16) -> crate::error::Result<()> {
+            Ok(())
+        }
+        fn set_position_ring_ratio(&mut self, _ratio: u16) -> crate::error::Result<()> {
+            Ok(())
+        }
+        fn set_speed_ring_ratio(&mut self, _ratio: u16) -> crate::error::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn controller(config: MotorControllerConfig) -> MotorController<'static> {
+        let motor = FakeMotor { position: TRAVEL / 2, pos_min: 0, pos_max: 0, margins: (0, 0) };
+        let mut controller = MotorController::new(Box::new(motor), config);
+        controller.init_motor(&mut |_| {}, Some((0, TRAVEL))).unwrap();
+        controller
+    }
+
+    // Waveform (y, speed) the controller outputs right now
+    fn waveform_now(controller: &MotorController) -> (f32, f32) {
+        let elapsed = time::Instant::now().duration_since(controller.t0).as_secs_f32();
+        controller.evaluate_waveform(elapsed)
+    }
+
+    #[test]
+    fn unpausing_on_the_falling_half_keeps_going_down() {
+        for reversed in [false, true] {
+            let mut config = MotorControllerConfig::default();
+            config.wave_func = "thrust".to_string();
+            config.reversed = reversed;
+            let mut controller = controller(config.clone());
+            // Partway down the fall
+            controller.t0 = cycle_start(time::Instant::now(), 0.7, config.bpm);
+            controller.cycle().unwrap();
+
+            config.paused = true;
+            config.paused_position = controller.current_paused_pos;
+            controller.set_config(config.clone()).unwrap();
+            config.paused = false;
+            controller.set_config(config).unwrap();
+
+            let (_, speed) = waveform_now(&controller);
+            assert!(speed < 0.0, "reversed {}: resumed with speed {}", reversed, speed);
+        }
+    }
+
+    #[test]
+    fn switching_waveforms_keeps_the_direction() {
+        for reverse in [false, true] {
+            let mut config = MotorControllerConfig::default();
+            let mut controller = controller(config.clone());
+            // On the way down
+            controller.t0 = cycle_start(time::Instant::now(), 0.6, config.bpm);
+
+            config.wave_func = "thrust".to_string();
+            config.reversed = reverse;
+            controller.set_config(config).unwrap();
+
+            // Reversing mirrors the waveform, so the carriage keeps going down when it rises
+            let (_, speed) = waveform_now(&controller);
+            assert_eq!(speed > 0.0, reverse, "reversed {}: switched to speed {}", reverse, speed);
+        }
+    }
+
+    #[test]
+    fn bpm_ceiling_drops_with_depth() {
+        let flat = SpeedLimits { bpm_min: 10.0, bpm_max: 200.0, depth_factor: 0.0 };
+        let sloped = SpeedLimits { depth_factor: 0.5, ..flat.clone() };
+        let steep = SpeedLimits { depth_factor: 1.0, ..flat.clone() };
+        for (depth, flat_max, sloped_max, steep_max) in [(0.0, 200.0, 200.0, 200.0), (0.5, 200.0, 150.0, 100.0), (1.0, 200.0, 100.0, 10.0)] {
+            assert_eq!(flat.max_bpm_at_depth(depth), flat_max);
+            assert_eq!(sloped.max_bpm_at_depth(depth), sloped_max);
+            // Never under the minimum, however steep
+            assert_eq!(steep.max_bpm_at_depth(depth), steep_max);
+        }
+        assert_eq!(sloped.clamp_bpm(180.0, 1.0), 100.0);
+        assert_eq!(sloped.clamp_bpm(80.0, 1.0), 80.0);
+        assert_eq!(sloped.clamp_bpm(5.0, 1.0), 10.0);
+    }
+
+    #[test]
+    fn config_bpm_is_clamped_at_its_depth() {
+        let limits = SpeedLimits { bpm_min: 10.0, bpm_max: 200.0, depth_factor: 0.5 };
+        let mut config = MotorControllerConfig { bpm: 180.0, depth: 1.0, ..MotorControllerConfig::default() };
+        let clamped = config.clamp_to_ranges(&limits).unwrap();
+        assert_eq!(config.bpm, 100.0);
+        assert_eq!(clamped.len(), 1);
+        assert_eq!((clamped[0].field, clamped[0].value, clamped[0].clamped), ("bpm", 180.0, 100.0));
+
+        // Shallow enough for the same BPM
+        let mut config = MotorControllerConfig { bpm: 180.0, depth: 0.2, ..MotorControllerConfig::default() };
+        assert!(config.clamp_to_ranges(&limits).unwrap().is_empty());
+    }
+
+    #[test]
+    fn new_speed_limits_slow_a_running_stroke() {
+        let mut controller = controller(MotorControllerConfig { bpm: 180.0, ..MotorControllerConfig::default() });
+        // Not held back until a commit
+        controller.begin_transaction().unwrap();
+        controller.set_speed_limits(SpeedLimits { bpm_min: 10.0, bpm_max: 200.0, depth_factor: 0.5 }).unwrap();
+        assert_eq!(controller.config.bpm, 100.0);
+    }
+
+    #[test]
+    fn escalating_slows_to_the_ceiling_at_the_new_depth() {
+        let mut controller = controller(MotorControllerConfig::default());
+        controller.set_speed_limits(SpeedLimits { bpm_min: 10.0, bpm_max: 200.0, depth_factor: 0.5 }).unwrap();
+        let config = MotorControllerConfig { bpm: 150.0, depth: 0.5, escalation_step: 0.25, escalation_max: 1.0, ..MotorControllerConfig::default() };
+        controller.set_config(config.clone()).unwrap();
+        assert_eq!(controller.config.bpm, 150.0);
+
+        controller.escalate();
+        assert_eq!(controller.config.bpm, 125.0);
+        controller.escalate();
+        assert_eq!(controller.config.bpm, 100.0);
+
+        // Still escalated, asking for the old speed again doesn't get it back
+        controller.set_config(config).unwrap();
+        assert_eq!(controller.config.bpm, 100.0);
+    }
+
+    #[test]
+    fn forced_pause_does_not_wait_for_the_transaction() {
+        let mut controller = controller(MotorControllerConfig { paused_position: 0.3, ..MotorControllerConfig::default() });
+        controller.begin_transaction().unwrap();
+        controller.update_config(|config| config.bpm = 60.0).unwrap();
+
+        controller.force_pause(None).unwrap();
+        assert!(controller.is_paused());
+        assert_eq!(controller.config.paused_position, 0.3);
+
+        // The transaction is still open and only brings its own changes
+        assert_eq!(controller.commit_transaction().unwrap(), 1);
+        assert!(controller.is_paused());
+        assert_eq!(controller.config.bpm, 60.0);
+    }
+
+    #[test]
+    fn interlock_halts_during_a_transaction() {
+        let mut controller = controller(MotorControllerConfig::default());
+        controller.cycle().unwrap();
+        let position = controller.current_paused_pos;
+        controller.begin_transaction().unwrap();
+
+        controller.set_interlock(false).unwrap();
+        assert!(controller.is_paused());
+        assert_eq!(controller.config.paused_position, position);
+    }
+
+    #[test]
+    fn pressed_limit_switch_keeps_the_carriage_from_going_further() {
+        let config = MotorControllerConfig { paused: false, ..MotorControllerConfig::default() };
+        let mut controller = controller(config);
+        controller.cycle().unwrap();
+
+        controller.set_limit_switch(Some(LimitEnd::Max)).unwrap();
+        assert!(controller.is_paused());
+        let stop = controller.current_paused_pos;
+
+        // Toward the switch, every way of moving the paused position stops at it
+        assert_eq!(controller.jog(0.05).unwrap(), stop);
+        controller.move_paused_position(1.0).unwrap();
+        assert_eq!(controller.config.paused_position, stop);
+        controller.update_config(|config| config.paused_position = 1.0).unwrap();
+        assert_eq!(controller.config.paused_position, stop);
+        controller.update_config(|config| config.idle_park_position = 1.0).unwrap();
+        controller.idle_parked = true;
+        assert_eq!(controller.paused_target(), stop);
+        controller.update_config(|config| config.paused = false).unwrap();
+        assert!(controller.is_paused());
+
+        // Away from it is fine, and once released it runs again
+        assert!(controller.jog(-0.05).unwrap() < stop);
+        controller.set_limit_switch(None).unwrap();
+        controller.update_config(|config| config.paused = false).unwrap();
+        assert!(!controller.is_paused());
+    }
+
+    #[test]
+    fn known_range_allows_resting_within_the_homing_margins() {
+        // Homing finds the ends at 0 and TRAVEL, the known range is inside them
+        let init = |position: i32, margins: (u32, u32)| {
+            let motor = FakeMotor { position, pos_min: 0, pos_max: 0, margins };
+            let mut controller = MotorController::new(Box::new(motor), MotorControllerConfig::default());
+            controller.init_motor(&mut |_| {}, Some((1000, 9000))).unwrap();
+            controller.range()
+        };
+        assert_eq!(init(500, (600, 600)), (1000, 9000));
+        assert_eq!(init(9500, (0, 500)), (1000, 9000));
+        // Further out than the margins, so something moved it, homing again
+        assert_eq!(init(500, (400, 400)), (0, TRAVEL));
+        assert_eq!(init(9001, (600, 0)), (0, TRAVEL));
+    }
+
+    #[test]
+    fn reinitialize_flags_a_position_outside_the_homed_range() {
+        let controller = controller(MotorControllerConfig::default());
+        for position in [0, TRAVEL, -TRAVEL / 25, TRAVEL + TRAVEL / 25] {
+            assert!(!controller.outside_homed_range(position), "{}", position);
+        }
+        for position in [-TRAVEL / 10, TRAVEL + TRAVEL / 10] {
+            assert!(controller.outside_homed_range(position), "{}", position);
+        }
+
+        // Reconnected after a power blip far past the end, parks at the end of the range
+        let motor = FakeMotor { position: 2 * TRAVEL, pos_min: 0, pos_max: 0, margins: (0, 0) };
+        let mut controller = MotorController::new(Box::new(motor), MotorControllerConfig::default());
+        controller.init_motor(&mut |_| {}, None).unwrap();
+        let position = controller.motor.read_position().unwrap();
+        assert!(controller.outside_homed_range(position));
+        controller.reinitialize().unwrap();
+        assert!(controller.is_paused());
+        assert!(controller.config.paused_position > 0.999, "parked at {}", controller.config.paused_position);
+    }
+
+    #[test]
+    fn simultaneous_bpm_wave_and_depth_change_is_continuous() {
+        // Matched to the new waveform's phase, then faded into it at the old phase
+        for crossfade_time in [0.0, 1.0] {
+            let mut config = MotorControllerConfig { crossfade_time, ..MotorControllerConfig::default() };
+            let mut controller = controller(config.clone());
+            controller.t0 = cycle_start(time::Instant::now(), 0.4, config.bpm);
+            controller.cycle().unwrap();
+            let before = controller.get_current_state();
+            let (_, speed_before) = waveform_now(&controller);
+
+            config.bpm = 60.0;
+            config.wave_func = "thrust".to_string();
+            config.depth = 0.5;
+            controller.set_config(config).unwrap();
+
+            let after = controller.get_current_state();
+            let (_, speed_after) = waveform_now(&controller);
+            assert!((after.y - before.y).abs() < 0.01, "crossfade {}: y {} -> {}", crossfade_time, before.y, after.y);
+            assert!((after.shaped_y - before.shaped_y).abs() < 0.01, "crossfade {}: shaped {} -> {}", crossfade_time, before.shaped_y, after.shaped_y);
+            assert_eq!(speed_after > 0.0, speed_before > 0.0, "crossfade {}: turned around", crossfade_time);
+            if crossfade_time > 0.0 {
+                // The new bpm carries on from the same phase
+                assert!((after.x - before.x).abs() < 0.01, "phase {} -> {}", before.x, after.x);
+            }
+        }
+    }
+
+    #[test]
+    fn paused_wave_change_resumes_from_the_reported_phase() {
+        for wave_func in ["thrust", "spline"] {
+            let mut config = MotorControllerConfig::default();
+            config.spline.points = vec![0.0, 0.2, 1.0, 0.6];
+            let mut controller = controller(config.clone());
+            controller.t0 = cycle_start(time::Instant::now(), 0.6, config.bpm);
+            controller.cycle().unwrap();
+
+            config.paused = true;
+            config.paused_position = controller.current_paused_pos;
+            controller.set_config(config.clone()).unwrap();
+            controller.cycle().unwrap();
+            let parked = controller.get_current_state();
+
+            config.wave_func = wave_func.to_string();
+            controller.set_config(config.clone()).unwrap();
+
+            // Still parked, on the new waveform
+            let changed = controller.get_current_state();
+            assert_eq!(changed.shaped_y, parked.shaped_y);
+            assert!((y_at(controller.waveform.as_ref(), changed.x) - changed.y).abs() < 0.001, "{}: ({}, {}) is off the waveform", wave_func, changed.x, changed.y);
+
+            config.paused = false;
+            controller.set_config(config).unwrap();
+            controller.cycle().unwrap();
+
+            let resumed = controller.get_current_state();
+            assert!((resumed.x - changed.x).abs() < 0.01, "{}: phase {} while paused, resumed at {}", wave_func, changed.x, resumed.x);
+            assert!((resumed.shaped_y - parked.shaped_y).abs() < 0.01, "{}: parked at {}, resumed at {}", wave_func, parked.shaped_y, resumed.shaped_y);
+            assert!((resumed.position - parked.position).abs() < TRAVEL / 100, "{}: {} -> {}", wave_func, parked.position, resumed.position);
+        }
+    }
+
+    #[test]
+    fn acceleration_is_clamped_to_the_drive_range() {
+        for (acceleration, expected) in [(0, MIN_ACCELERATION), (40000, 40000), (u16::MAX, MAX_ACCELERATION)] {
+            let mut config = MotorControllerConfig { acceleration, ..MotorControllerConfig::default() };
+            config.clamp_to_ranges(&SpeedLimits::default()).unwrap();
+            assert_eq!(config.acceleration, expected);
         }
     }
 }
@@ -1,9 +1,52 @@
-use anyhow::Result;
+use std::time::Duration;
+
+use crate::error::Result;
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HomingPhase {
+    FindingMin,
+    FindingMax,
+    Verifying,  // Finding both ends again to compare, see HomingConfiguration::verify
+    Centering,
+}
+
+// Drive readings in the drive's own units
+#[derive(Clone, Debug, Serialize)]
+pub struct MotorTelemetry {
+    pub position: i32,
+    pub speed: i16,
+    pub current: u16,
+    pub voltage: u16,
+    pub temperature: u16,
+    pub alarm_code: u16,    // 0 = no alarm
+}
+
+// What the drive says it is, for matching it against the register map in use
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct MotorDeviceInfo {
+    pub model: u16,
+    pub firmware_version: u16,
+}
+
+// How far apart the ends found by two homing passes are, in counts
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HomingRepeatability {
+    pub min_diff: u32,
+    pub max_diff: u32,
+}
 
 pub trait Motor: Send {
     fn cycle(&mut self) -> Result<()>;
-    fn homing(&mut self) -> Result<()>;
+    // `progress` is called whenever homing enters a new phase
+    fn homing(&mut self, progress: &mut dyn FnMut(HomingPhase)) -> Result<()>;
+    // Takes the ends of travel as known instead of homing. Only right for absolute encoders.
+    fn set_range(&mut self, pos_min: i32, pos_max: i32) -> Result<()>;
     fn read_position(&mut self) -> Result<i32>;
+    // Re-establishes communication after the motor dropped off the bus
+    fn reconnect(&mut self) -> Result<()>;
+    fn read_telemetry(&mut self) -> Result<MotorTelemetry>;
     fn write_position(&mut self, position: i32, speed: f32) -> Result<()>;
     fn pos_min(&self) -> i32;
     fn pos_max(&self) -> i32;
@@ -11,4 +54,25 @@ pub trait Motor: Send {
     fn set_acceleration(&mut self, acceleration: u16) -> Result<()>;
     fn set_position_ring_ratio(&mut self, ratio: u16) -> Result<()>;
     fn set_speed_ring_ratio(&mut self, ratio: u16) -> Result<()>;
+    // Time the last request took to get its answer, for links that have one
+    fn round_trip_time(&self) -> Option<Duration> {
+        None
+    }
+    // Counts past pos_min and pos_max the carriage can rest, for a homing that keeps the range
+    // clear of the ends it found
+    fn homing_margins(&self) -> (u32, u32) {
+        (0, 0)
+    }
+    // Set by a homing that found the ends twice
+    fn homing_repeatability(&self) -> Option<HomingRepeatability> {
+        None
+    }
+    // None for drives that don't identify themselves
+    fn read_device_info(&mut self) -> Result<Option<MotorDeviceInfo>> {
+        Ok(None)
+    }
+    // Raw holding register read for reverse engineering, values.len() registers from addr
+    fn read_registers(&mut self, _addr: u16, _values: &mut [u16]) -> Result<()> {
+        Err(crate::error::OssmError::invalid("This motor has no registers to read"))
+    }
 }
@@ -1,22 +1,36 @@
 use std::time;
 
-use crate::motor::Motor;
+use crate::motor::{HomingPhase, HomingRepeatability, Motor, MotorDeviceInfo, MotorTelemetry};
 use esp_idf_svc::hal::delay::{Ets, FreeRtos, TickType_t};
 use esp_idf_svc::hal::gpio::{self, AnyOutputPin};
 use esp_idf_svc::hal::uart;
 use esp_idf_svc::hal::delay::TICK_RATE_HZ;
+use esp_idf_svc::sys::{self, esp};
 
 use fixedvec::FixedVec;
 use rmodbus::{client::ModbusRequest, guess_response_frame_len, ModbusProto};
-use anyhow::Result;
-
+use crate::error::{OssmError, Result};
+use crate::pins::PinLease;
+use crate::storage::{HomingConfiguration, WordOrder};
+
+const MODBUS_BAUD_RATES: [u32; 4] = [115200, 9600, 19200, 38400];
+// Model code and firmware version, read-only. Not every firmware has them.
+const DEVICE_INFO_ADDR: u16 = 0x1A;
+// Most registers one read can return, 2 bytes each within the 256 byte RTU frame
+pub const MAX_READ_REGISTERS: u16 = 125;
+const HOMING_TRAVEL: i32 = 1000000;     // Counts homing moves towards an end before giving up on finding it
+const HOMING_TICK_MS: u32 = 20;         // Between target steps while seeking an end
+const STALL_LAG: u32 = 1000;            // Counts the target may run ahead of a still carriage before it counts as blocked
 
 pub struct ModbusRTUMaster<'a> {
     uart: uart::UartDriver<'a>,
     ctrl_pin_driver: Option<gpio::PinDriver<'a, AnyOutputPin, gpio::Output>>,
+    de_re_active_low: bool,     // Transceiver sends while DE/RE is low
     device_id: u8,
     read_timeout: TickType_t,
     write_timeout: TickType_t,
+    last_round_trip: Option<time::Duration>,
+    _pin_lease: Option<PinLease>,   // Declared after uart so the pins are handed back once the driver is gone
 }
 
 impl<'a> ModbusRTUMaster<'a> {
@@ -34,20 +48,46 @@ impl<'a> ModbusRTUMaster<'a> {
         let result = Self {
             uart,
             ctrl_pin_driver,
+            de_re_active_low: false,
             device_id,
             read_timeout: timeout,
             write_timeout: timeout,
+            last_round_trip: None,
+            _pin_lease: None,
         };
         result
     }
 
+    // For transceivers wired with DE/RE inverted. Inverts the RTS line the UART drives in RS485
+    // mode, and the level send_frame() puts on a manual control pin.
+    pub fn with_de_re_active_low(mut self, active_low: bool) -> Result<Self> {
+        let mask = if active_low { sys::uart_signal_inv_t_UART_SIGNAL_RTS_INV } else { 0 };
+        esp!(unsafe { sys::uart_set_line_inverse(self.uart.port(), mask) }).map_err(OssmError::Uart)?;
+        self.de_re_active_low = active_low;
+        self.set_transmitting(false);
+        Ok(self)
+    }
+
+    fn set_transmitting(&mut self, transmitting: bool) {
+        if let Some(ref mut ctrl_pin_driver) = self.ctrl_pin_driver {
+            let high = transmitting != self.de_re_active_low;
+            ctrl_pin_driver.set_level(high.into()).unwrap();
+        }
+    }
+
+    // Keeps the pins the UART was built from until the master is dropped
+    pub fn with_pin_lease(mut self, pin_lease: PinLease) -> Self {
+        self._pin_lease = Some(pin_lease);
+        self
+    }
+
     fn get_operation_timeout(baudrate: u32) -> Result<TickType_t> {
         match baudrate {
             9600 => Ok(TICK_RATE_HZ / 10),
             19200 => Ok(TICK_RATE_HZ / 20),
             38400 => Ok(TICK_RATE_HZ / 40),
             115200 | 115201 => Ok(TICK_RATE_HZ / 200),
-            _ => Err(anyhow::anyhow!("Invalid baud rate: {}", baudrate)),
+            _ => Err(OssmError::invalid(format!("Invalid baud rate: {}", baudrate))),
         }
     }
 
@@ -56,7 +96,11 @@ impl<'a> ModbusRTUMaster<'a> {
         while total_bytes_read < buf.len() {
             let bytes_read = self
                 .uart
-                .read(&mut buf[total_bytes_read..], self.read_timeout)?;
+                .read(&mut buf[total_bytes_read..], self.read_timeout)
+                .map_err(OssmError::uart)?;
+            if bytes_read == 0 {
+                return Err(OssmError::ModbusTimeout);
+            }
             total_bytes_read += bytes_read;
         }
         Ok(())
@@ -65,33 +109,52 @@ impl<'a> ModbusRTUMaster<'a> {
     fn uart_write_all(&mut self, buf: &[u8]) -> Result<()> {
         let mut total_bytes_written = 0;
         while total_bytes_written < buf.len() {
-            let bytes_written = self.uart.write(&buf[total_bytes_written..])?;
+            let bytes_written = self.uart.write(&buf[total_bytes_written..]).map_err(OssmError::uart)?;
             total_bytes_written += bytes_written;
         }
-        self.uart.wait_tx_done(self.write_timeout)?;
+        self.uart.wait_tx_done(self.write_timeout).map_err(OssmError::uart)?;
         Ok(())
     }
 
-    fn modbus_request(&mut self, req: &[u8], resp: &mut [u8]) -> Result<usize> {
-        assert!(resp.len() >= 256);
+    // Reads whatever arrives until the line goes quiet, without expecting a valid frame
+    fn uart_read_available(&mut self, buf: &mut [u8]) -> usize {
+        let mut total_bytes_read = 0;
+        while total_bytes_read < buf.len() {
+            match self.uart.read(&mut buf[total_bytes_read..], self.read_timeout) {
+                Ok(bytes_read) if bytes_read > 0 => total_bytes_read += bytes_read,
+                _ => break,
+            }
+        }
+        total_bytes_read
+    }
 
-        if let Some(ref mut ctrl_pin_driver) = self.ctrl_pin_driver {
-            ctrl_pin_driver.set_high().unwrap();
+    fn send_frame(&mut self, req: &[u8]) -> Result<()> {
+        if self.ctrl_pin_driver.is_some() {
+            self.set_transmitting(true);
             Ets::delay_us(10);
         }
 
         self.uart_write_all(req)?;
 
-        if let Some(ref mut ctrl_pin_driver) = self.ctrl_pin_driver {
-            ctrl_pin_driver.set_low().unwrap();
+        if self.ctrl_pin_driver.is_some() {
+            self.set_transmitting(false);
             Ets::delay_us(10);
         }
+        Ok(())
+    }
+
+    fn modbus_request(&mut self, req: &[u8], resp: &mut [u8]) -> Result<usize> {
+        assert!(resp.len() >= 256);
+
+        let started = time::Instant::now();
+        self.send_frame(req)?;
         
         self.uart_read_exactly(&mut resp[..6])?;
         let len = guess_response_frame_len(&resp[..6], ModbusProto::Rtu)? as usize;
         if len > 6 {
             self.uart_read_exactly(&mut resp[6..len])?;
         }
+        self.last_round_trip = Some(started.elapsed());
         Ok(len)
     }
 
@@ -151,8 +214,57 @@ impl<'a> ModbusRTUMaster<'a> {
         Ok(())
     }
 
+    // Sends a holding register read and reports what came back, valid or not. Tells
+    // "nothing on the line" apart from "something answers, but not in a way we understand".
+    pub fn probe(&mut self, addr: u16) -> Result<LineResponse> {
+        let mut request = ModbusRequest::new(self.device_id, ModbusProto::Rtu);
+        let mut request_buf = fixedvec::alloc_stack!([u8; 256]);
+        let mut response_buf = [0; 256];
+
+        let mut frame_buf = FixedVec::new(&mut request_buf);
+
+        request.generate_get_holdings(addr, 1, &mut frame_buf)?;
+        self.uart.clear_rx().map_err(OssmError::uart)?;
+        self.send_frame(frame_buf.as_slice())?;
+        let len = self.uart_read_available(&mut response_buf);
+        if len == 0 {
+            return Ok(LineResponse::Silent);
+        }
+
+        let mut result = [0u16; 1];
+        let mut result_vec = FixedVec::new(&mut result);
+        match request.parse_u16(&response_buf[..len], &mut result_vec) {
+            Ok(()) => Ok(LineResponse::Valid),
+            Err(_) => Ok(LineResponse::Garbled(len)),
+        }
+    }
+
+    // Writes a test pattern and expects to read it straight back. Only passes with TX wired
+    // to RX (or A/B looped back behind the transceiver), a motor on the bus won't echo it.
+    pub fn loopback_test(&mut self) -> Result<LineResponse> {
+        const PATTERN: [u8; 8] = [0x55, 0xAA, 0x00, 0xFF, 0x0F, 0xF0, 0x12, 0x34];
+        let mut buf = [0u8; 16];
+
+        self.uart.clear_rx().map_err(OssmError::uart)?;
+        self.send_frame(&PATTERN)?;
+        let len = self.uart_read_available(&mut buf);
+        Ok(match len {
+            0 => LineResponse::Silent,
+            _ if buf[..len] == PATTERN => LineResponse::Valid,
+            _ => LineResponse::Garbled(len),
+        })
+    }
+
+    pub fn last_round_trip(&self) -> Option<time::Duration> {
+        self.last_round_trip
+    }
+
+    pub fn baudrate(&self) -> Result<u32> {
+        Ok(self.uart.baudrate().map_err(OssmError::uart)?.into())
+    }
+
     pub fn set_baudrate(&mut self, baudrate: u32) -> Result<()> {
-        self.uart.change_baudrate(baudrate)?;
+        self.uart.change_baudrate(baudrate).map_err(OssmError::uart)?;
         let timeout = Self::get_operation_timeout(baudrate)?;
         self.read_timeout = timeout;
         self.write_timeout = timeout;
@@ -164,6 +276,10 @@ pub struct Modbus57AIM30Motor<'a> {
     client: ModbusRTUMaster<'a>,
     pos_min: i32,
     pos_max: i32,
+    homing: HomingConfiguration,
+    repeatability: Option<HomingRepeatability>,
+    word_order: WordOrder,
+    signed: bool,
 }
 
 impl<'a> Modbus57AIM30Motor<'a> {
@@ -172,38 +288,113 @@ impl<'a> Modbus57AIM30Motor<'a> {
             client: modbus_client,
             pos_min: 0,
             pos_max: 0,
+            homing: HomingConfiguration::default(),
+            repeatability: None,
+            word_order: WordOrder::LowFirst,
+            signed: true,
         }
     }
 
-    fn write_position_raw(&mut self, position: i32) -> Result<(), anyhow::Error> {
-        let data = [position as u16, (position >> 16) as u16];
+    // How the motor lays out the 32-bit position in its two registers
+    pub fn with_position_format(mut self, word_order: WordOrder, signed: bool) -> Self {
+        self.word_order = word_order;
+        self.signed = signed;
+        self
+    }
+
+    pub fn with_homing(mut self, homing: HomingConfiguration) -> Self {
+        self.homing = homing;
+        self
+    }
+
+    fn write_position_raw(&mut self, position: i32) -> Result<()> {
+        let data = encode_position(position, self.word_order, self.signed);
         self.client.write_holding_registers(0x16, &data)?;
         Ok(())
     }
 
-    fn wait_stable_position(&mut self, timeout_ms: u32) -> Result<i32, anyhow::Error> {
-        let start_time = time::SystemTime::now();
+    fn wait_stable_position(&mut self, timeout_ms: u32) -> Result<i32> {
+        let start_time = time::Instant::now();
         let timeout = time::Duration::from_millis(timeout_ms as u64);
         let mut position = self.read_position()?;
-        while start_time.elapsed()? < timeout {
+        // A single quiet pair can be vibration or encoder noise settling by chance
+        let mut stable_reads = 0;
+        while start_time.elapsed() < timeout {
             let new_position = self.read_position()?;
-            if (new_position - position).abs() < 10 {
-                return Ok(new_position);
+            if new_position.abs_diff(position) < self.homing.stable_threshold {
+                stable_reads += 1;
+                if stable_reads >= self.homing.stable_reads {
+                    return Ok(new_position);
+                }
+            } else {
+                stable_reads = 0;
             }
             position = new_position;
             FreeRtos::delay_ms(100);
         }
-        Err(anyhow::anyhow!("Timeout waiting for stable position"))
+        Err(OssmError::Homing("timeout waiting for stable position".to_string()))
     }
 
-    fn reset_position(&mut self) -> Result<(), anyhow::Error> {
+    // Moves the target towards `direction` (-1 or 1) at `speed` counts per second until the carriage
+    // stops following it, i.e. it is against the end, and holds it there. Returns where it stopped.
+    fn seek_end(&mut self, direction: i32, speed: u32) -> Result<i32> {
+        let step = (speed * HOMING_TICK_MS / 1000).max(1) as i32;
+        let start = self.read_position()?;
+        let mut target = start;
+        let mut position = start;
+        let mut stable_reads = 0;
+        while target.abs_diff(start) < HOMING_TRAVEL as u32 {
+            target += direction * step;
+            self.write_position(target, 0.0)?;
+            FreeRtos::delay_ms(HOMING_TICK_MS);
+            let new_position = self.read_position()?;
+            if new_position.abs_diff(position) < self.homing.stable_threshold && target.abs_diff(new_position) > STALL_LAG {
+                stable_reads += 1;
+                if stable_reads >= self.homing.stable_reads {
+                    // Stop pushing against it
+                    self.write_position(new_position, 0.0)?;
+                    return Ok(new_position);
+                }
+            } else {
+                stable_reads = 0;
+            }
+            position = new_position;
+        }
+        Err(OssmError::Homing("no end of travel found".to_string()))
+    }
+
+    // Where the end in `direction` is. Approaches it fast, backs off and touches it again slowly,
+    // so the reference comes from a gentle touch rather than the hit.
+    fn find_end(&mut self, direction: i32) -> Result<i32> {
+        if self.homing.approach_speed == 0 {
+            // A push the power limit stalls at the stop
+            self.write_position(direction * HOMING_TRAVEL, 0.0)?;
+            FreeRtos::delay_ms(5000);
+            return self.wait_stable_position(5000);
+        }
+        let end = self.seek_end(direction, self.homing.approach_speed)?;
+        self.write_position(end - direction * self.homing.backoff as i32, 0.0)?;
+        self.wait_stable_position(5000)?;
+        self.seek_end(direction, self.homing.touch_speed)
+    }
+
+    // The range within the ends found, keeping the margins clear of them
+    fn apply_margins(&self, end_min: i32, end_max: i32) -> Result<(i32, i32)> {
+        let (margin_min, margin_max) = (self.homing.margin_min as i32, self.homing.margin_max as i32);
+        if end_max - end_min <= margin_min + margin_max {
+            return Err(OssmError::Homing(format!("the margins ({} and {} counts) leave nothing of the {} counts of travel",
+                margin_min, margin_max, end_max - end_min)));
+        }
+        Ok((end_min + margin_min, end_max - margin_max))
+    }
+
+    fn reset_position(&mut self) -> Result<()> {
         self.write_position_raw(0)?;
         Ok(())
     }
 
     pub fn modbus_scan(&mut self) -> Result<ModbusScanResult> {
-        let baud_rates: [u32; _] = [115200, 9600, 19200, 38400];
-        for baud_rate in baud_rates {
+        for baud_rate in MODBUS_BAUD_RATES {
             self.client.set_baudrate(baud_rate)?;
             for device_id in 1..=247 {
                 self.client.device_id = device_id;
@@ -218,17 +409,59 @@ impl<'a> Modbus57AIM30Motor<'a> {
                 }
             }
         }
-        Err(anyhow::anyhow!("no response"))
+        Err(OssmError::ModbusTimeout)
     }
 
-    pub fn modbus_set_baud_rate(&mut self, baud_rate: u32) -> Result<(), anyhow::Error> {
+    // Probes the configured device id at every baud rate. Runs before a scan so a failed
+    // scan comes with a hint about whether anything is connected at all.
+    pub fn probe_line(&mut self) -> Result<Vec<(u32, LineResponse)>> {
+        let original_baud_rate = self.client.baudrate()?;
+        let mut responses = Vec::new();
+        for baud_rate in MODBUS_BAUD_RATES {
+            self.client.set_baudrate(baud_rate)?;
+            responses.push((baud_rate, self.client.probe(0x00)?));
+        }
+        self.client.set_baudrate(original_baud_rate)?;
+        Ok(responses)
+    }
+
+    // Whether anything answers the configured device id at any baud rate. Takes a fraction of a
+    // second, unlike a scan.
+    pub fn answers(&mut self) -> Result<bool> {
+        Ok(self.probe_line()?.iter().any(|(_, response)| *response != LineResponse::Silent))
+    }
+
+    // Wiring check for new builds, logs a report of what the line looks like
+    pub fn diagnose_modbus(&mut self) -> Result<()> {
+        log::info!("=== Modbus diagnosis ===");
+        log::info!("Probing device id {} at each baud rate:", self.client.device_id);
+        let responses = self.probe_line()?;
+        for (baud_rate, response) in &responses {
+            log::info!("  {:>6} baud: {}", baud_rate, response);
+        }
+        log::info!("{}", line_hint(&responses));
+
+        log::info!("Loopback test (TX wired to RX, motor disconnected):");
+        match self.client.loopback_test()? {
+            LineResponse::Valid => log::info!("  Passed: the UART sends and receives correctly"),
+            LineResponse::Garbled(len) => log::info!("  Got {} bytes back that don't match, check for noise or a wrong baud rate", len),
+            LineResponse::Silent => log::info!("  Nothing came back. Expected if the motor is connected, otherwise check the TX/RX pins"),
+        }
+        log::info!("=== End of Modbus diagnosis ===");
+        Ok(())
+    }
+
+    pub fn modbus_set_baud_rate(&mut self, baud_rate: u32) -> Result<()> {
         let baud_rate_code = match baud_rate {
             9600 => 800,
             19200 => 801,
             38400 => 802,
             115200 => 803,
-            _ => return Err(anyhow::anyhow!("Invalid baud rate")),
+            _ => return Err(OssmError::invalid(format!("Invalid baud rate: {}", baud_rate))),
         };
+        // 0x03 takes the baud code in this sequence only, otherwise it is the acceleration.
+        // Runs while connecting, before anything can change the acceleration, and the drive
+        // needs a power cycle after it.
         self.client.write_holding_register(0x00, 1)?;
         self.client.write_holding_register(0x03, baud_rate_code)?;
         self.client.write_holding_register(0x04, 129)?;
@@ -236,23 +469,57 @@ impl<'a> Modbus57AIM30Motor<'a> {
         Ok(())
     }
 
-    pub fn enable_modbus_communication(&mut self) -> Result<(), anyhow::Error> {
+    pub fn modbus_set_device_id(&mut self, device_id: u8) -> Result<()> {
+        if !(1..=247).contains(&device_id) {
+            return Err(OssmError::invalid(format!("Invalid device id: {}", device_id)));
+        }
+        let old_device_id = self.client.device_id;
+        // 0x15 holds the slave address, writing 1 to 0x14 stores the parameters
+        self.client.write_holding_register(0x15, device_id as u16)?;
+        // Depending on firmware the new address applies right away or after a power cycle
+        self.client.device_id = device_id;
+        if self.client.write_holding_register(0x14, 1).is_err() {
+            self.client.device_id = old_device_id;
+            self.client.write_holding_register(0x14, 1)?;
+            self.client.device_id = device_id;
+        }
+        Ok(())
+    }
+
+    pub fn enable_modbus_communication(&mut self) -> Result<()> {
         self.client.write_holding_register(0x00, 0x01)?;
         Ok(())
     }
 }
 
 impl<'a> Motor for Modbus57AIM30Motor<'a> {
-    fn read_position(&mut self) -> Result<i32, anyhow::Error> {
+    fn read_position(&mut self) -> Result<i32> {
         let mut rsp = [0u16; 2];
         self.client.read_holding_registers(0x16, 2, &mut rsp)?;
-        let low = rsp[0];
-        let high = rsp[1];
-        let position = (high as i32) << 16 | low as i32;
-        Ok(position)
+        Ok(decode_position(rsp, self.word_order, self.signed))
+    }
+
+    fn reconnect(&mut self) -> Result<()> {
+        self.enable_modbus_communication()
+    }
+
+    fn read_telemetry(&mut self) -> Result<MotorTelemetry> {
+        // Status registers are contiguous, so one request covers them all:
+        // 0x0E alarm code, 0x0F current, 0x10 speed, 0x11 voltage, 0x12 temperature,
+        // 0x13 PWM output, 0x14 save flag, 0x15 device address, 0x16/0x17 absolute position
+        let mut rsp = [0u16; 10];
+        self.client.read_holding_registers(0x0E, 10, &mut rsp)?;
+        Ok(MotorTelemetry {
+            position: decode_position([rsp[8], rsp[9]], self.word_order, self.signed),
+            speed: rsp[2] as i16,
+            current: rsp[1],
+            voltage: rsp[3],
+            temperature: rsp[4],
+            alarm_code: rsp[0],
+        })
     }
 
-    fn write_position(&mut self, position: i32, _speed: f32) -> Result<(), anyhow::Error> {
+    fn write_position(&mut self, position: i32, _speed: f32) -> Result<()> {
         if position == 0 {
             self.write_position_raw(1)
         } else {
@@ -260,27 +527,32 @@ impl<'a> Motor for Modbus57AIM30Motor<'a> {
         }
     }
 
-    fn set_max_power(&mut self, power: u16) -> Result<(), anyhow::Error> {
+    fn set_max_power(&mut self, power: u16) -> Result<()> {
         self.client.write_holding_register(0x18, power)?;
         Ok(())
     }
 
-    fn set_acceleration(&mut self, acceleration: u16) -> Result<(), anyhow::Error> {
+    fn set_acceleration(&mut self, acceleration: u16) -> Result<()> {
         self.client.write_holding_register(0x03, acceleration)?;
+        // Out of range values are accepted without an exception, only reading back shows them
+        let applied = self.client.read_holding_register(0x03)?;
+        if applied != acceleration {
+            return Err(OssmError::invalid(format!("The drive took acceleration {} as {}", acceleration, applied)));
+        }
         Ok(())
     }
 
-    fn set_position_ring_ratio(&mut self, ratio: u16) -> Result<(), anyhow::Error> {
+    fn set_position_ring_ratio(&mut self, ratio: u16) -> Result<()> {
         self.client.write_holding_register(0x07, ratio)?;
         Ok(())
     }
 
-    fn set_speed_ring_ratio(&mut self, ratio: u16) -> Result<(), anyhow::Error> {
+    fn set_speed_ring_ratio(&mut self, ratio: u16) -> Result<()> {
         self.client.write_holding_register(0x05, ratio)?;
         Ok(())
     }
 
-    fn homing(&mut self) -> Result<(), anyhow::Error> {
+    fn homing(&mut self, progress: &mut dyn FnMut(HomingPhase)) -> Result<()> {
         assert!(
             self.pos_min == 0 && self.pos_max == 0,
             "Motor already homed"
@@ -289,14 +561,36 @@ impl<'a> Motor for Modbus57AIM30Motor<'a> {
         self.set_max_power(60)?;
         self.set_acceleration(10000)?;
         self.reset_position()?;
-        self.write_position(-1000000, 0.0)?;
-        FreeRtos::delay_ms(5000);
-        self.pos_min = self.wait_stable_position(5000)? + 3000;
-
-        self.write_position(1000000, 0.0)?;
-        FreeRtos::delay_ms(5000);
-        self.pos_max = self.wait_stable_position(5000)? - 3000;
+        progress(HomingPhase::FindingMin);
+        let end_min = self.find_end(-1)?;
+
+        progress(HomingPhase::FindingMax);
+        let end_max = self.find_end(1)?;
+        (self.pos_min, self.pos_max) = self.apply_margins(end_min, end_max)?;
+
+        if self.homing.verify {
+            // Slipping belts or stops that give show up as ends that move between passes. The
+            // second pass is the range used, it's the one the carriage has just touched.
+            progress(HomingPhase::Verifying);
+            let end_min = self.find_end(-1)?;
+            let end_max = self.find_end(1)?;
+            let (pos_min, pos_max) = self.apply_margins(end_min, end_max)?;
+            let repeatability = HomingRepeatability {
+                min_diff: pos_min.abs_diff(self.pos_min),
+                max_diff: pos_max.abs_diff(self.pos_max),
+            };
+            if repeatability.min_diff.max(repeatability.max_diff) > self.homing.repeat_tolerance {
+                log::warn!("Homing found the ends {} and {} counts apart between passes, more than the {} allowed. \
+                    Check for a slipping belt or pulley and for stops that give.", repeatability.min_diff, repeatability.max_diff, self.homing.repeat_tolerance);
+            } else {
+                log::info!("Homing repeatability: min within {} counts, max within {}", repeatability.min_diff, repeatability.max_diff);
+            }
+            self.pos_min = pos_min;
+            self.pos_max = pos_max;
+            self.repeatability = Some(repeatability);
+        }
 
+        progress(HomingPhase::Centering);
         self.write_position((self.pos_min + self.pos_max) / 2, 0.0)?;
         FreeRtos::delay_ms(5000);
         self.wait_stable_position(5000)?;
@@ -304,6 +598,46 @@ impl<'a> Motor for Modbus57AIM30Motor<'a> {
         Ok(())
     }
 
+    fn set_range(&mut self, pos_min: i32, pos_max: i32) -> Result<()> {
+        assert!(
+            self.pos_min == 0 && self.pos_max == 0,
+            "Motor already homed"
+        );
+        self.pos_min = pos_min;
+        self.pos_max = pos_max;
+        Ok(())
+    }
+
+    fn round_trip_time(&self) -> Option<time::Duration> {
+        self.client.last_round_trip()
+    }
+
+    fn homing_margins(&self) -> (u32, u32) {
+        (self.homing.margin_min, self.homing.margin_max)
+    }
+
+    fn homing_repeatability(&self) -> Option<HomingRepeatability> {
+        self.repeatability.clone()
+    }
+
+    fn read_device_info(&mut self) -> Result<Option<MotorDeviceInfo>> {
+        let mut rsp = [0u16; 2];
+        match self.client.read_holding_registers(DEVICE_INFO_ADDR, 2, &mut rsp) {
+            Ok(()) => Ok(Some(MotorDeviceInfo { model: rsp[0], firmware_version: rsp[1] })),
+            // Drives without them answer with an illegal address exception
+            Err(OssmError::Modbus(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn read_registers(&mut self, addr: u16, values: &mut [u16]) -> Result<()> {
+        for (i, chunk) in values.chunks_mut(MAX_READ_REGISTERS as usize).enumerate() {
+            let start = addr + i as u16 * MAX_READ_REGISTERS;
+            self.client.read_holding_registers(start, chunk.len() as u16, chunk)?;
+        }
+        Ok(())
+    }
+
     fn pos_min(&self) -> i32 {
         self.pos_min
     }
@@ -317,8 +651,111 @@ impl<'a> Motor for Modbus57AIM30Motor<'a> {
     }
 }
 
+// Unsigned positions above i32::MAX saturate, they are far beyond any stroke
+fn decode_position(words: [u16; 2], word_order: WordOrder, signed: bool) -> i32 {
+    let (low, high) = match word_order {
+        WordOrder::LowFirst => (words[0], words[1]),
+        WordOrder::HighFirst => (words[1], words[0]),
+    };
+    let raw = (high as u32) << 16 | low as u32;
+    if signed {
+        raw as i32
+    } else {
+        raw.min(i32::MAX as u32) as i32
+    }
+}
+
+// Negative positions clamp to 0 on unsigned motors, homing then stops at the bottom of their range
+fn encode_position(position: i32, word_order: WordOrder, signed: bool) -> [u16; 2] {
+    let raw = if signed { position as u32 } else { position.max(0) as u32 };
+    let (low, high) = (raw as u16, (raw >> 16) as u16);
+    match word_order {
+        WordOrder::LowFirst => [low, high],
+        WordOrder::HighFirst => [high, low],
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineResponse {
+    Valid,              // A well-formed reply
+    Garbled(usize),     // Some bytes came back, but not a valid reply
+    Silent,             // Nothing came back
+}
+
+impl std::fmt::Display for LineResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LineResponse::Valid => write!(f, "valid reply"),
+            LineResponse::Garbled(len) => write!(f, "{} garbled bytes", len),
+            LineResponse::Silent => write!(f, "no reply"),
+        }
+    }
+}
+
+// One-line explanation of a probe_line result, meant for people wiring up a new build
+pub fn line_hint(responses: &[(u32, LineResponse)]) -> String {
+    if let Some((baud_rate, _)) = responses.iter().find(|(_, r)| *r == LineResponse::Valid) {
+        format!("The motor answers at {} baud, wiring looks fine", baud_rate)
+    } else if responses.iter().any(|(_, r)| matches!(r, LineResponse::Garbled(_))) {
+        "Something is on the line but its replies are garbled: check the baud rate, parity/stop bits, and that A/B are not swapped".to_string()
+    } else {
+        "Nothing answers: check that the motor is powered, that the TX/RX/DE-RE pins match the wiring (get_pin_configuration), \
+        and the transceiver has power. A motor with another device id stays silent too, a scan will find it".to_string()
+    }
+}
+
 #[derive(Debug)]
 pub struct ModbusScanResult {
     pub baud_rate: u32,
     pub device_id: u8,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORDERS: [WordOrder; 2] = [WordOrder::LowFirst, WordOrder::HighFirst];
+
+    #[test]
+    fn signed_positions_round_trip() {
+        for word_order in ORDERS {
+            for position in [0, 1, -1, 12345, -12345, 65535, 65536, -65536, i32::MAX, i32::MIN] {
+                let words = encode_position(position, word_order, true);
+                assert_eq!(decode_position(words, word_order, true), position, "{:?}", word_order);
+            }
+        }
+    }
+
+    #[test]
+    fn signed_positions_sign_extend() {
+        assert_eq!(decode_position([0xFFFF, 0xFFFF], WordOrder::LowFirst, true), -1);
+        assert_eq!(decode_position([0xFFFE, 0xFFFF], WordOrder::LowFirst, true), -2);
+        assert_eq!(decode_position([0xFFFF, 0xFFFE], WordOrder::HighFirst, true), -2);
+        assert_eq!(decode_position([0x0000, 0x8000], WordOrder::LowFirst, true), i32::MIN);
+        assert_eq!(decode_position([0xFFFF, 0x7FFF], WordOrder::LowFirst, true), i32::MAX);
+        // A negative low word alone is still a positive position
+        assert_eq!(decode_position([0xFFFF, 0x0000], WordOrder::LowFirst, true), 65535);
+    }
+
+    #[test]
+    fn word_order_swaps_the_words() {
+        assert_eq!(encode_position(0x0001_0002, WordOrder::LowFirst, true), [0x0002, 0x0001]);
+        assert_eq!(encode_position(0x0001_0002, WordOrder::HighFirst, true), [0x0001, 0x0002]);
+        assert_eq!(encode_position(-2, WordOrder::HighFirst, true), [0xFFFF, 0xFFFE]);
+    }
+
+    #[test]
+    fn unsigned_positions_clamp_at_the_i32_range() {
+        for word_order in ORDERS {
+            for position in [0, 1, 65536, i32::MAX] {
+                let words = encode_position(position, word_order, false);
+                assert_eq!(decode_position(words, word_order, false), position, "{:?}", word_order);
+            }
+            // Nothing below 0 to send, nothing above i32::MAX to report
+            assert_eq!(encode_position(-1, word_order, false), [0, 0]);
+            assert_eq!(encode_position(i32::MIN, word_order, false), [0, 0]);
+            assert_eq!(decode_position([0xFFFF, 0xFFFF], word_order, false), i32::MAX);
+            assert_eq!(decode_position(encode_position(i32::MIN, word_order, true), word_order, false), i32::MAX);
+        }
+    }
+}
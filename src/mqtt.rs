@@ -0,0 +1,166 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use esp_idf_svc::mqtt::client::{EspMqttClient, EventPayload, MqttClientConfiguration, QoS};
+
+use crate::command;
+use crate::context::AppContext;
+use crate::status::HealthReport;
+use crate::storage::MqttConfiguration;
+
+const MQTT_TASK_STACK_SIZE: usize = 8192;
+// What the command topic may run: the motion, not the network, pin or motor setup
+const ALLOWED_COMMANDS: &[&str] = &[
+    "pause", "start", "set_bpm", "set_wave", "list_waves", "set_depth", "set_depth_top",
+    "set_paused_position", "set_sharpness", "set_sharpness_speed", "set_dwell_top", "set_dwell_bottom",
+    "set_speed_ratio", "set_tease", "set_escalation", "set_spline_points", "morph_spline",
+    "load_spline", "list_splines", "run_pattern", "stop_pattern", "begin", "commit", "abort",
+];
+
+// Publishes the motion state and health to a broker and runs the command lines sent to
+// <prefix>/command. Topics:
+//   <prefix>/state     StateResponse JSON, every interval while the motor is initialized and
+//                      right away when the config changes
+//   <prefix>/health    same body as GET /health, every interval
+//   <prefix>/stroke    one StrokeEvent JSON per completed stroke, sent each interval
+//   <prefix>/command   subscribed, one serial command per message, only ALLOWED_COMMANDS
+pub fn start(app_context: AppContext, config: &MqttConfiguration) -> anyhow::Result<()> {
+    if config.url.is_empty() {
+        return Ok(());
+    }
+    let client_config = MqttClientConfiguration {
+        client_id: Some(&config.client_id),
+        username: (!config.username.is_empty()).then_some(config.username.as_str()),
+        password: (!config.password.is_empty()).then_some(config.password.as_str()),
+        ..Default::default()
+    };
+
+    // The callback runs on the MQTT task, hand commands over instead of running them there
+    let (command_tx, command_rx) = mpsc::channel::<String>();
+    let connected = Arc::new(AtomicBool::new(false));
+    let subscribe = Arc::new(AtomicBool::new(false));
+    let command_topic = format!("{}/command", config.topic_prefix);
+    let client = {
+        let connected = connected.clone();
+        let subscribe = subscribe.clone();
+        let command_topic = command_topic.clone();
+        EspMqttClient::new_cb(&config.url, &client_config, move |event| match event.payload() {
+            EventPayload::Connected(_) => {
+                log::info!("MQTT connected");
+                connected.store(true, Ordering::Relaxed);
+                subscribe.store(true, Ordering::Relaxed);
+            }
+            EventPayload::Disconnected => {
+                log::warn!("MQTT disconnected");
+                connected.store(false, Ordering::Relaxed);
+            }
+            EventPayload::Received { topic, data, .. } if topic == Some(command_topic.as_str()) => {
+                match std::str::from_utf8(data) {
+                    Ok(cmdline) => {
+                        let _ = command_tx.send(cmdline.trim().to_string());
+                    }
+                    Err(_) => log::error!("MQTT command is not UTF-8"),
+                }
+            }
+            EventPayload::Error(e) => log::error!("MQTT error: {:?}", e),
+            _ => {}
+        })?
+    };
+    let client = Arc::new(Mutex::new(client));
+    log::info!("MQTT broker {}, topic prefix {}", config.url, config.topic_prefix);
+
+    {
+        let app_context = app_context.clone();
+        std::thread::Builder::new()
+            .stack_size(MQTT_TASK_STACK_SIZE)
+            .spawn(move || {
+                for cmdline in command_rx {
+                    let command = cmdline.split(' ').next().unwrap_or_default();
+                    if ALLOWED_COMMANDS.contains(&command) {
+                        command::run_command(&app_context, &cmdline);
+                    } else {
+                        log::warn!("MQTT command {} is not allowed, only motion commands are", command);
+                    }
+                }
+            })?;
+    }
+
+    let state_topic = format!("{}/state", config.topic_prefix);
+    let health_topic = format!("{}/health", config.topic_prefix);
+    let stroke_topic = format!("{}/stroke", config.topic_prefix);
+    let interval = Duration::from_millis(config.interval_ms as u64);
+    let mut last_stroke = 0;
+    // From the current controller, a new one after a motor task restart needs a new subscription
+    let mut config_changes: Option<mpsc::Receiver<u32>> = None;
+    let mut next_publish = Instant::now() + interval;
+    std::thread::Builder::new()
+        .stack_size(MQTT_TASK_STACK_SIZE)
+        .spawn(move || loop {
+            let timeout = next_publish.saturating_duration_since(Instant::now());
+            let changed = match config_changes.as_ref().map(|changes| changes.recv_timeout(timeout)) {
+                Some(Ok(_)) => true,
+                Some(Err(RecvTimeoutError::Timeout)) => false,
+                Some(Err(RecvTimeoutError::Disconnected)) => {
+                    config_changes = None;
+                    false
+                }
+                None => {
+                    std::thread::sleep(timeout);
+                    false
+                }
+            };
+            if config_changes.is_none() {
+                config_changes = app_context.motor_controller.lock().unwrap().as_mut().map(|mc| mc.subscribe_config_changes());
+            }
+            if let Some(changes) = &config_changes {
+                // One publish for a burst of changes, e.g. a slider being dragged
+                while changes.try_recv().is_ok() {}
+            }
+            let periodic = Instant::now() >= next_publish;
+            if periodic {
+                next_publish = Instant::now() + interval;
+            }
+            if !connected.load(Ordering::Relaxed) || !(changed || periodic) {
+                continue;
+            }
+            let mut client = client.lock().unwrap();
+            // Subscriptions don't survive a reconnect to a broker without a persistent session
+            if subscribe.swap(false, Ordering::Relaxed) {
+                if let Err(e) = client.subscribe(&command_topic, QoS::AtLeastOnce) {
+                    log::error!("Failed to subscribe to {}: {}", command_topic, e);
+                    subscribe.store(true, Ordering::Relaxed);
+                }
+            }
+
+            let state = app_context.motor_controller.lock().unwrap().as_ref().map(|mc| {
+                // A new controller counts from zero again
+                if mc.stroke_count() < last_stroke {
+                    last_stroke = 0;
+                }
+                (mc.get_current_state(), mc.strokes_since(last_stroke))
+            });
+            if let Some((state, strokes)) = state {
+                let json = serde_json::to_string(&state).unwrap();
+                if let Err(e) = client.enqueue(&state_topic, QoS::AtMostOnce, false, json.as_bytes()) {
+                    log::error!("Failed to publish to {}: {}", state_topic, e);
+                }
+                for stroke in strokes {
+                    last_stroke = stroke.stroke;
+                    let json = serde_json::to_string(&stroke).unwrap();
+                    if let Err(e) = client.enqueue(&stroke_topic, QoS::AtMostOnce, false, json.as_bytes()) {
+                        log::error!("Failed to publish to {}: {}", stroke_topic, e);
+                    }
+                }
+            }
+            if periodic {
+                let health = HealthReport::new(app_context.status.lock().unwrap().clone());
+                let json = serde_json::to_string(&health).unwrap();
+                if let Err(e) = client.enqueue(&health_topic, QoS::AtMostOnce, false, json.as_bytes()) {
+                    log::error!("Failed to publish to {}: {}", health_topic, e);
+                }
+            }
+        })?;
+    Ok(())
+}
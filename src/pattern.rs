@@ -0,0 +1,223 @@
+use anyhow::{anyhow, Result};
+
+use crate::motion::{self, MotorControllerConfig};
+
+// ===== Pattern language =====
+// A session is a list of steps separated by ';', e.g.
+//   sine 40bpm 30s; thrust 0.2 60bpm 60s; spline [0,1,0.3,1] 45bpm loop
+// Each step changes only the fields it mentions:
+//   sine | thrust | spline   waveform
+//   0.2                      thrust sharpness
+//   [0, 1, 0.3, 1]           spline points
+//   45bpm                    speed
+//   80%                      depth
+//   30s | 2min               how long the step runs, without one (or with "loop") it holds until stopped
+// A final "repeat" step starts over after the last step.
+
+#[derive(Clone, Debug, Default)]
+pub struct PatternStep {
+    pub wave_func: Option<String>,
+    pub sharpness: Option<f32>,
+    pub spline_points: Option<Vec<f32>>,
+    pub bpm: Option<f32>,
+    pub depth: Option<f32>,
+    pub duration: Option<f32>,      // Seconds, None = hold
+}
+
+impl PatternStep {
+    pub fn apply(&self, config: &mut MotorControllerConfig) {
+        if let Some(wave_func) = &self.wave_func {
+            config.wave_func = wave_func.clone();
+        }
+        if let Some(sharpness) = self.sharpness {
+            config.thrust.sharpness = sharpness;
+        }
+        if let Some(points) = &self.spline_points {
+            config.spline.points = points.clone();
+        }
+        if let Some(bpm) = self.bpm {
+            config.bpm = bpm;
+        }
+        if let Some(depth) = self.depth {
+            config.depth = depth;
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Pattern {
+    pub steps: Vec<PatternStep>,
+    pub repeat: bool,
+}
+
+pub fn parse(text: &str) -> Result<Pattern> {
+    let mut steps: Vec<PatternStep> = Vec::new();
+    let mut repeat = false;
+
+    for (i, step_text) in text.split(';').map(str::trim).filter(|s| !s.is_empty()).enumerate() {
+        if repeat {
+            return Err(anyhow!("\"repeat\" must be the last step"));
+        }
+        if step_text.eq_ignore_ascii_case("repeat") {
+            repeat = true;
+            continue;
+        }
+        let step = parse_step(step_text).map_err(|e| anyhow!("Step {} ({}): {}", i + 1, step_text, e))?;
+        if let Some(last) = steps.last() {
+            if last.duration.is_none() {
+                return Err(anyhow!("Step {} holds until stopped, so step {} would never run", steps.len(), i + 1));
+            }
+        }
+        steps.push(step);
+    }
+
+    if steps.is_empty() {
+        return Err(anyhow!("Pattern has no steps"));
+    }
+    if repeat && steps.iter().any(|s| s.duration.is_none()) {
+        return Err(anyhow!("A repeating pattern needs a duration on every step"));
+    }
+    Ok(Pattern { steps, repeat })
+}
+
+fn parse_step(text: &str) -> Result<PatternStep> {
+    let mut step = PatternStep::default();
+    for token in tokenize(text)? {
+        let lower = token.to_ascii_lowercase();
+        if motion::is_known_waveform(&lower) {
+            step.wave_func = Some(lower);
+        } else if let Some(list) = lower.strip_prefix('[') {
+            let list = list.strip_suffix(']').ok_or_else(|| anyhow!("missing ']'"))?;
+            let points = list
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .map(|s| parse_number(s, "spline point"))
+                .collect::<Result<Vec<f32>>>()?;
+            if points.is_empty() || points.iter().any(|p| !(0.0..=1.0).contains(p)) {
+                return Err(anyhow!("spline points must be between 0 and 1"));
+            }
+            step.spline_points = Some(points);
+        } else if let Some(bpm) = lower.strip_suffix("bpm") {
+            let bpm = parse_number(bpm, "bpm")?;
+            if bpm <= 0.0 {
+                return Err(anyhow!("bpm must be positive"));
+            }
+            step.bpm = Some(bpm);
+        } else if let Some(depth) = lower.strip_suffix('%') {
+            let depth = parse_number(depth, "depth")?;
+            if !(0.0..=100.0).contains(&depth) {
+                return Err(anyhow!("depth must be between 0% and 100%"));
+            }
+            step.depth = Some(depth / 100.0);
+        } else if let Some(minutes) = lower.strip_suffix("min").filter(|m| m.parse::<f32>().is_ok()) {
+            step.duration = Some(parse_duration(minutes)? * 60.0);
+        } else if let Some(seconds) = lower.strip_suffix('s').filter(|s| s.parse::<f32>().is_ok()) {
+            step.duration = Some(parse_duration(seconds)?);
+        } else if lower == "loop" {
+            step.duration = None;
+        } else if let Ok(sharpness) = lower.parse::<f32>() {
+            if !(0.01..=0.99).contains(&sharpness) {
+                return Err(anyhow!("sharpness must be between 0.01 and 0.99"));
+            }
+            step.sharpness = Some(sharpness);
+        } else {
+            return Err(anyhow!("unknown token \"{}\"", token));
+        }
+    }
+    Ok(step)
+}
+
+// Splits on whitespace, keeping a bracketed point list together
+fn tokenize(text: &str) -> Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_list = false;
+    for c in text.chars() {
+        match c {
+            '[' if !in_list => {
+                in_list = true;
+                current.push(c);
+            }
+            ']' if in_list => {
+                in_list = false;
+                current.push(c);
+            }
+            c if c.is_whitespace() && !in_list => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            c => current.push(c),
+        }
+    }
+    if in_list {
+        return Err(anyhow!("missing ']'"));
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    Ok(tokens)
+}
+
+fn parse_number(text: &str, what: &str) -> Result<f32> {
+    text.trim().parse::<f32>().map_err(|_| anyhow!("invalid {} \"{}\"", what, text))
+}
+
+fn parse_duration(text: &str) -> Result<f32> {
+    let duration = parse_number(text, "duration")?;
+    if duration <= 0.0 {
+        return Err(anyhow!("duration must be positive"));
+    }
+    Ok(duration)
+}
+
+// ===== Sequence engine =====
+// Steps through a pattern as running time passes. Time spent paused doesn't count.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PatternProgress {
+    Running,    // Still on the same step
+    NextStep,   // A new step started, apply current_step()
+    Finished,
+}
+
+pub struct PatternRunner {
+    pattern: Pattern,
+    index: usize,
+    step_elapsed: f32,
+}
+
+impl PatternRunner {
+    pub fn new(pattern: Pattern) -> Self {
+        Self { pattern, index: 0, step_elapsed: 0.0 }
+    }
+
+    pub fn current_step(&self) -> &PatternStep {
+        &self.pattern.steps[self.index]
+    }
+
+    pub fn current_index(&self) -> usize {
+        self.index
+    }
+
+    // Advances by `dt` seconds of running time
+    pub fn advance(&mut self, dt: f32) -> PatternProgress {
+        let Some(duration) = self.current_step().duration else {
+            return PatternProgress::Running;
+        };
+        self.step_elapsed += dt;
+        if self.step_elapsed < duration {
+            return PatternProgress::Running;
+        }
+
+        self.step_elapsed -= duration;
+        self.index += 1;
+        if self.index == self.pattern.steps.len() {
+            if !self.pattern.repeat {
+                return PatternProgress::Finished;
+            }
+            self.index = 0;
+        }
+        PatternProgress::NextStep
+    }
+}
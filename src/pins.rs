@@ -0,0 +1,189 @@
+use std::sync::{Arc, Mutex};
+
+use esp_idf_svc::hal::gpio::AnyIOPin;
+use esp_idf_svc::sys;
+use serde::Serialize;
+
+use crate::error::{OssmError, Result};
+use crate::storage::{LedKind, StorageManager};
+
+// GPIOs not in use, indexed by pin number
+pub type PinPool = Arc<Mutex<Vec<Option<AnyIOPin>>>>;
+
+// Never in the pool, they carry the USB serial console
+pub const USB_SERIAL_PINS: [usize; 2] = [12, 13];
+
+// GPIOs that have no output driver on this chip
+#[cfg(esp32)]
+pub const INPUT_ONLY_PINS: &[usize] = &[34, 35, 36, 37, 38, 39];
+#[cfg(esp32s2)]
+pub const INPUT_ONLY_PINS: &[usize] = &[46];
+#[cfg(not(any(esp32, esp32s2)))]
+pub const INPUT_ONLY_PINS: &[usize] = &[];
+
+// Read at reset to pick the boot mode. Whatever is wired to them can keep the chip from booting
+// normally, so they are a poor choice for outputs into external circuits.
+#[cfg(esp32)]
+pub const STRAPPING_PINS: &[usize] = &[0, 2, 5, 12, 15];
+#[cfg(esp32s2)]
+pub const STRAPPING_PINS: &[usize] = &[0, 45, 46];
+#[cfg(esp32s3)]
+pub const STRAPPING_PINS: &[usize] = &[0, 3, 45, 46];
+#[cfg(esp32c3)]
+pub const STRAPPING_PINS: &[usize] = &[2, 8, 9];
+#[cfg(esp32c6)]
+pub const STRAPPING_PINS: &[usize] = &[4, 5, 8, 9, 15];
+#[cfg(esp32h2)]
+pub const STRAPPING_PINS: &[usize] = &[8, 9, 25];
+#[cfg(not(any(esp32, esp32s2, esp32s3, esp32c3, esp32c6, esp32h2)))]
+pub const STRAPPING_PINS: &[usize] = &[];
+
+// Refuses a pin that can't drive the output `role` needs
+pub fn check_output_pin(pin: u32, role: &str) -> Result<()> {
+    if INPUT_ONLY_PINS.contains(&(pin as usize)) {
+        return Err(OssmError::invalid(format!("GPIO {} is input-only on this chip, it can't be the {}", pin, role)));
+    }
+    Ok(())
+}
+
+// Strapping pins work as outputs, but can upset booting depending on what they drive
+pub fn strapping_warning(pin: u32) -> Option<String> {
+    STRAPPING_PINS.contains(&(pin as usize)).then(|| format!(
+        "GPIO {} is a strapping pin, if the circuit on it pulls it at reset the chip may not boot normally", pin))
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PinState {
+    Available,
+    InUse,          // Taken by the driver named in used_by
+    UsbSerial,
+    Unavailable,    // Not broken out, or used by the chip itself (e.g. flash)
+}
+
+#[derive(Serialize)]
+pub struct PinInfo {
+    pub pin: usize,
+    pub state: PinState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub used_by: Option<&'static str>,
+    pub input_only: bool,
+    pub strapping: bool,
+}
+
+#[derive(Serialize)]
+pub struct PinReport {
+    pub chip: String,
+    pub pins: Vec<PinInfo>,
+    pub note: String,
+}
+
+// Which pins are free for configuration, and what the others are taken by. A pin out of the
+// pool is put down to whatever is configured to use it.
+pub fn pin_report(pool: &PinPool, storage: &StorageManager) -> PinReport {
+    let mut uses: Vec<(u32, &'static str)> = Vec::new();
+    if let Ok(config) = storage.get_pin_configuration() {
+        uses.extend([(config.modbus_tx, "modbus_tx"), (config.modbus_rx, "modbus_rx"), (config.modbus_de_re, "modbus_de_re")]);
+    }
+    if let Ok(config) = storage.get_led_configuration() {
+        if config.kind != LedKind::None {
+            uses.push((config.pin, "status_led"));
+        }
+    }
+    if let Ok(config) = storage.get_buzzer_configuration() {
+        if config.enabled {
+            uses.push((config.pin, "buzzer"));
+        }
+    }
+    if let Ok(config) = storage.get_interlock_configuration() {
+        if config.enabled {
+            uses.push((config.pin, "interlock"));
+        }
+    }
+    if let Ok(config) = storage.get_limit_switch_configuration() {
+        uses.extend(config.min_pin.map(|pin| (pin, "limit_min")));
+        uses.extend(config.max_pin.map(|pin| (pin, "limit_max")));
+    }
+
+    let all_pins = pool.lock().unwrap();
+    let pins = (0..all_pins.len())
+        .map(|pin| {
+            let used_by = uses.iter().find(|(p, _)| *p as usize == pin).map(|&(_, name)| name);
+            let state = if all_pins[pin].is_some() {
+                PinState::Available
+            } else if USB_SERIAL_PINS.contains(&pin) {
+                PinState::UsbSerial
+            } else if used_by.is_some() {
+                PinState::InUse
+            } else {
+                PinState::Unavailable
+            };
+            PinInfo {
+                pin,
+                state,
+                used_by: used_by.filter(|_| state == PinState::InUse),
+                input_only: INPUT_ONLY_PINS.contains(&pin),
+                strapping: STRAPPING_PINS.contains(&pin),
+            }
+        })
+        .collect();
+
+    let mut note = if INPUT_ONLY_PINS.is_empty() {
+        "Every GPIO on this chip can be an output.".to_string()
+    } else {
+        format!("GPIOs {:?} are input-only, use them for Modbus RX only.", INPUT_ONLY_PINS)
+    };
+    if !STRAPPING_PINS.is_empty() {
+        note += &format!(" GPIOs {:?} are strapping pins, better avoided for outputs.", STRAPPING_PINS);
+    }
+    PinReport { chip: chip_name(), pins, note }
+}
+
+fn chip_name() -> String {
+    String::from_utf8_lossy(sys::CONFIG_IDF_TARGET).trim_end_matches('\0').to_string()
+}
+
+// Pins taken out of the pool, put back when the lease is dropped. Keep the lease alive for as
+// long as the driver built from the pins, and drop it after the driver.
+pub struct PinLease {
+    pool: PinPool,
+    pins: Vec<usize>,
+}
+
+impl PinLease {
+    // Takes all of the given pins, or none of them if any is in use or doesn't exist
+    pub fn take<const N: usize>(pool: &PinPool, pins: [usize; N]) -> Option<(Self, [AnyIOPin; N])> {
+        let mut all_pins = pool.lock().unwrap();
+        let available = pins.iter().enumerate().all(|(i, &pin)| {
+            !pins[..i].contains(&pin) && all_pins.get(pin).is_some_and(|p| p.is_some())
+        });
+        if !available {
+            return None;
+        }
+        let taken = std::array::from_fn(|i| all_pins[pins[i]].take().unwrap());
+        Some((Self { pool: pool.clone(), pins: pins.to_vec() }, taken))
+    }
+
+    // Takes the lowest numbered free pins
+    pub fn take_any<const N: usize>(pool: &PinPool) -> Option<(Self, [AnyIOPin; N])> {
+        let free: Vec<usize> = {
+            let all_pins = pool.lock().unwrap();
+            all_pins.iter().enumerate().filter(|(_, p)| p.is_some()).map(|(i, _)| i).take(N).collect()
+        };
+        Self::take(pool, free.try_into().ok()?)
+    }
+
+    pub fn pins(&self) -> &[usize] {
+        &self.pins
+    }
+}
+
+impl Drop for PinLease {
+    fn drop(&mut self) {
+        let mut all_pins = self.pool.lock().unwrap();
+        for &pin in &self.pins {
+            // The driver that used the pin is gone, nothing else refers to it
+            all_pins[pin] = Some(unsafe { AnyIOPin::new(pin as i32) });
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{anyhow, Result};
+use esp_idf_svc::sys;
+use serde::{Deserialize, Serialize};
+
+use crate::clock;
+
+const POLL_INTERVAL_SECS: f64 = 1.0;
+
+// When the machine should run on its own
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Schedule {
+    // Every day between two local times, in minutes after midnight. May wrap past midnight.
+    Daily { start_minute: u16, stop_minute: u16 },
+    // Once, between two timestamps from clock::timestamp()
+    Countdown { start_at: f64, stop_at: f64, wall_clock: bool },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScheduleAction {
+    Start,
+    Stop,
+    Finished,   // Stop, and the one-off schedule is over
+}
+
+impl Schedule {
+    // Parses "daily <HH:MM> <HH:MM>" or "[in <delay>] for <duration>", e.g. "in 10min for 30min"
+    pub fn parse(text: &str) -> Result<Self> {
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        match parts.as_slice() {
+            ["daily", start, stop] => {
+                let start_minute = parse_time_of_day(start)?;
+                let stop_minute = parse_time_of_day(stop)?;
+                if start_minute == stop_minute {
+                    return Err(anyhow!("start and stop time must differ"));
+                }
+                if clock::wall_clock().is_none() {
+                    log::warn!("The clock is not synced yet, the daily schedule waits for NTP");
+                }
+                Ok(Schedule::Daily { start_minute, stop_minute })
+            }
+            ["for", duration] => Self::countdown(0.0, parse_duration(duration)?),
+            ["in", delay, "for", duration] => Self::countdown(parse_duration(delay)?, parse_duration(duration)?),
+            _ => Err(anyhow!("use daily <HH:MM> <HH:MM>, for <duration> or in <delay> for <duration>")),
+        }
+    }
+
+    fn countdown(delay: f64, duration: f64) -> Result<Self> {
+        let (now, wall_clock) = clock::timestamp();
+        Ok(Schedule::Countdown { start_at: now + delay, stop_at: now + delay + duration, wall_clock })
+    }
+
+    // Whether the machine should be running now, None while that can't be told
+    fn active(&self) -> Option<bool> {
+        match *self {
+            Schedule::Daily { start_minute, stop_minute } => {
+                let minute = local_minute_of_day(clock::wall_clock()?);
+                Some(if start_minute < stop_minute {
+                    (start_minute..stop_minute).contains(&minute)
+                } else {
+                    minute >= start_minute || minute < stop_minute
+                })
+            }
+            Schedule::Countdown { start_at, stop_at, wall_clock } => {
+                let now = countdown_clock(wall_clock)?;
+                Some(now >= start_at && now < stop_at)
+            }
+        }
+    }
+
+    fn is_over(&self) -> bool {
+        match *self {
+            Schedule::Daily { .. } => false,
+            Schedule::Countdown { stop_at, wall_clock, .. } => countdown_clock(wall_clock).is_some_and(|now| now >= stop_at),
+        }
+    }
+}
+
+// Turns the schedule into start and stop actions on its edges, so the machine can still be
+// paused or started by hand in between
+pub struct Scheduler {
+    schedule: Option<Schedule>,
+    last_active: Option<bool>,
+    last_poll: f64,
+    stop_now: bool,
+}
+
+impl Scheduler {
+    pub fn restore(schedule: Option<Schedule>) -> Self {
+        // A countdown timed by uptime can't be picked up after a reboot, stop to be safe
+        let stale = matches!(schedule, Some(Schedule::Countdown { wall_clock: false, .. }));
+        if stale {
+            log::warn!("Dropping a countdown schedule set before the reboot without a synced clock");
+        }
+        Self {
+            schedule: if stale { None } else { schedule },
+            last_active: None,
+            last_poll: f64::NEG_INFINITY,
+            stop_now: stale,
+        }
+    }
+
+    // A new countdown starts on its own, a daily schedule waits for its next start time
+    pub fn set(&mut self, schedule: Option<Schedule>) {
+        self.last_active = matches!(schedule, Some(Schedule::Countdown { .. })).then_some(false);
+        self.schedule = schedule;
+        self.last_poll = f64::NEG_INFINITY;
+        self.stop_now = false;
+    }
+
+    // Called from the motor loop every cycle, looks at the clock about once a second
+    pub fn poll(&mut self) -> Option<ScheduleAction> {
+        if std::mem::take(&mut self.stop_now) {
+            return Some(ScheduleAction::Finished);
+        }
+        let now = clock::uptime_secs();
+        if now - self.last_poll < POLL_INTERVAL_SECS {
+            return None;
+        }
+        self.last_poll = now;
+
+        let schedule = self.schedule.as_ref()?;
+        if schedule.is_over() {
+            self.schedule = None;
+            self.last_active = None;
+            return Some(ScheduleAction::Finished);
+        }
+        let active = schedule.active()?;
+        match (self.last_active.replace(active), active) {
+            (Some(false), true) => Some(ScheduleAction::Start),
+            (Some(true), false) => Some(ScheduleAction::Stop),
+            // Never start on the first check, only make sure nothing runs outside the schedule
+            (None, false) => Some(ScheduleAction::Stop),
+            _ => None,
+        }
+    }
+}
+
+// Stays on the clock the countdown was set with, even once NTP syncs
+fn countdown_clock(wall_clock: bool) -> Option<f64> {
+    if wall_clock { clock::wall_clock() } else { Some(clock::uptime_secs()) }
+}
+
+fn parse_time_of_day(text: &str) -> Result<u16> {
+    let (hours, minutes) = text.split_once(':').ok_or_else(|| anyhow!("invalid time \"{}\", use HH:MM", text))?;
+    match (hours.parse::<u16>(), minutes.parse::<u16>()) {
+        (Ok(h), Ok(m)) if h < 24 && m < 60 => Ok(h * 60 + m),
+        _ => Err(anyhow!("invalid time \"{}\", use HH:MM", text)),
+    }
+}
+
+// "90s", "10min" or "2h"
+fn parse_duration(text: &str) -> Result<f64> {
+    let (number, unit) = if let Some(n) = text.strip_suffix("min") {
+        (n, 60.0)
+    } else if let Some(n) = text.strip_suffix('h') {
+        (n, 3600.0)
+    } else if let Some(n) = text.strip_suffix('s') {
+        (n, 1.0)
+    } else {
+        return Err(anyhow!("invalid duration \"{}\", use e.g. 90s, 10min or 2h", text));
+    };
+    match number.parse::<f64>() {
+        Ok(n) if n >= 0.0 => Ok(n * unit),
+        _ => Err(anyhow!("invalid duration \"{}\", use e.g. 90s, 10min or 2h", text)),
+    }
+}
+
+fn local_minute_of_day(unix_secs: f64) -> u16 {
+    let t = unix_secs as sys::time_t;
+    let mut tm: sys::tm = unsafe { std::mem::zeroed() };
+    unsafe { sys::localtime_r(&t, &mut tm) };
+    (tm.tm_hour * 60 + tm.tm_min) as u16
+}
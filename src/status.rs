@@ -0,0 +1,130 @@
+use std::time;
+
+use serde::Serialize;
+
+use crate::boot::ResetReason;
+use crate::clock;
+use crate::limit_switch::LimitEnd;
+use crate::motor::{HomingPhase, HomingRepeatability, MotorDeviceInfo};
+use crate::storage::HomedRange;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InitState {
+    Booting,    // setting up the Modbus link to the motor
+    NoMotor,    // nothing answers on the bus, probing again every second
+    Homing,     // searching for the ends of travel, takes ~15s
+    Ready,      // motor controller is available
+    Failed,     // initialization gave up, see init_error
+    SafeMode,   // the motor is not started, see safe_mode_reason
+    Stopped,    // the motor loop was stopped on request
+}
+
+// Device-wide status shared between the motor task and the interfaces
+#[derive(Clone, Debug, Serialize)]
+pub struct DeviceStatus {
+    pub init_state: InitState,
+    pub init_error: Option<String>,
+    pub homing_phase: Option<HomingPhase>,
+    pub homed_range: Option<HomedRange>,   // Saved by the last homing, known before this one finishes
+    pub homing_repeatability: Option<HomingRepeatability>,   // From the last homing, if it was verified
+    pub motor_info: Option<MotorDeviceInfo>,   // Read once the Modbus link is up, None if the drive doesn't say
+    pub motor_fault: Option<String>,    // Set while the motor loop has stopped driving the motor
+    pub interlock_engaged: Option<bool>,    // None without an interlock
+    pub limit_switch: Option<LimitEnd>,     // Limit switch pressed right now
+    pub motor_task_restarts: u32,       // Times the motor task exited and was started again
+    pub boot_count: u32,
+    pub reset_reason: ResetReason,
+    pub last_boot_crashed: bool,        // The previous run ended in a panic or watchdog reset
+    pub safe_mode_reason: Option<String>,
+    #[serde(skip)]
+    pub homing_started: Option<time::Instant>,
+    #[serde(skip)]
+    pub motor_retry_at: Option<time::Instant>,  // When the motor task that exited starts again
+    #[serde(skip)]
+    pub metrics: MotorMetrics,
+}
+
+// Counters and gauges from the motor loop, for GET /metrics
+#[derive(Clone, Debug, Default)]
+pub struct MotorMetrics {
+    pub cycles: u64,
+    pub cycle_errors: u64,
+    pub reinitializations: u64,
+    pub loop_rate_hz: f32,                          // Measured over the last minute
+    pub round_trip: Option<time::Duration>,         // Last Modbus request to answer
+    pub strokes: u64,                               // Since the current motor controller started
+}
+
+impl Default for DeviceStatus {
+    fn default() -> Self {
+        Self {
+            init_state: InitState::Booting,
+            init_error: None,
+            homing_phase: None,
+            homed_range: None,
+            homing_repeatability: None,
+            motor_info: None,
+            motor_fault: None,
+            interlock_engaged: None,
+            limit_switch: None,
+            motor_task_restarts: 0,
+            boot_count: 0,
+            reset_reason: ResetReason::Unknown,
+            last_boot_crashed: false,
+            safe_mode_reason: None,
+            homing_started: None,
+            motor_retry_at: None,
+            metrics: MotorMetrics::default(),
+        }
+    }
+}
+
+impl DeviceStatus {
+    pub fn set_homing_phase(&mut self, phase: HomingPhase) {
+        self.homing_phase = Some(phase);
+        self.homing_started.get_or_insert_with(time::Instant::now);
+    }
+
+    pub fn finish_homing(&mut self) {
+        self.homing_phase = None;
+        self.homing_started = None;
+    }
+
+    // Seconds spent homing so far, None when not homing
+    pub fn homing_elapsed(&self) -> Option<f32> {
+        self.homing_phase?;
+        self.homing_started.map(|t| t.elapsed().as_secs_f32())
+    }
+
+    // Seconds until the motor task is started again, None unless it is waiting to
+    pub fn motor_retry_in(&self) -> Option<f32> {
+        self.motor_retry_at.map(|t| t.saturating_duration_since(time::Instant::now()).as_secs_f32())
+    }
+}
+
+// Body of GET /health, also published over MQTT
+#[derive(Serialize)]
+pub struct HealthReport {
+    pub uptime: f64,            // Seconds since boot
+    pub timestamp: f64,         // Unix time, or seconds since boot until the clock is synced
+    pub time_synced: bool,
+    pub homing_elapsed: Option<f32>,
+    pub motor_retry_in: Option<f32>,
+    #[serde(flatten)]
+    pub status: DeviceStatus,
+}
+
+impl HealthReport {
+    pub fn new(status: DeviceStatus) -> Self {
+        let (timestamp, time_synced) = clock::timestamp();
+        Self {
+            timestamp,
+            time_synced,
+            uptime: clock::uptime_secs(),
+            homing_elapsed: status.homing_elapsed(),
+            motor_retry_in: status.motor_retry_in(),
+            status,
+        }
+    }
+}
@@ -1,8 +1,11 @@
 use esp_idf_svc::nvs::{EspDefaultNvsPartition, EspNvs, NvsDefault};
 
+use std::collections::BTreeMap;
 use serde::{Deserialize, Serialize, de::DeserializeOwned};
-use anyhow::Result;
-use crate::motion::MotorControllerConfig;
+use crate::error::{OssmError, Result};
+use crate::motion::{self, JogLimits, MotorControllerConfig, RangePolicy, SpeedLimits};
+use crate::pins;
+use crate::schedule::Schedule;
 
 pub struct StorageManager {
     nvs: EspNvs<NvsDefault>,
@@ -25,6 +28,281 @@ impl Default for PinConfiguration {
     }
 }
 
+// Ends of travel found by the last homing, in motor counts
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HomedRange {
+    pub pos_min: i32,
+    pub pos_max: i32,
+}
+
+const MIN_HOMED_SPAN: i64 = 10000;     // Far shorter than any real rail, less means homing went wrong
+
+impl HomedRange {
+    pub fn is_plausible(&self) -> bool {
+        self.pos_max as i64 - self.pos_min as i64 >= MIN_HOMED_SPAN
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpConfiguration {
+    pub port: u16,
+    pub allowed_origin: String,     // value of Access-Control-Allow-Origin
+}
+
+impl Default for HttpConfiguration {
+    fn default() -> Self {
+        Self {
+            port: 80,
+            allowed_origin: "*".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LedKind {
+    None,
+    Gpio,       // Plain LED on a GPIO
+    Ws2812,     // Addressable RGB LED, like the one on the ESP32-C6 DevKit (GPIO8)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LedConfiguration {
+    pub kind: LedKind,
+    pub pin: u32,
+    pub active_low: bool,   // Plain LED wired to 3.3V, lit when the pin is low
+}
+
+impl Default for LedConfiguration {
+    fn default() -> Self {
+        Self {
+            kind: LedKind::None,
+            pin: 8,
+            active_low: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BuzzerConfiguration {
+    pub enabled: bool,
+    pub pin: u32,
+}
+
+impl Default for BuzzerConfiguration {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pin: 10,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MqttConfiguration {
+    pub url: String,            // e.g. mqtt://192.168.1.10:1883, empty = MQTT off
+    pub username: String,
+    pub password: String,
+    pub client_id: String,
+    pub topic_prefix: String,
+    pub interval_ms: u32,       // How often state and health are published
+}
+
+impl Default for MqttConfiguration {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            username: String::new(),
+            password: String::new(),
+            client_id: "ossm".to_string(),
+            topic_prefix: "ossm".to_string(),
+            interval_ms: 1000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeConfiguration {
+    pub ntp_server: String,     // Empty = no time sync
+    pub timezone: String,       // POSIX TZ string, e.g. CET-1CEST,M3.5.0,M10.5.0/3
+}
+
+impl Default for TimeConfiguration {
+    fn default() -> Self {
+        Self {
+            ntp_server: "pool.ntp.org".to_string(),
+            timezone: "UTC0".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+// What switches the RS485 transceiver between sending and receiving
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeReControl {
+    Rts,    // The UART's RS485 mode, on the RTS line
+    Gpio,   // Set by hand around each frame, for transceivers the RTS timing doesn't suit
+}
+
+// Which of the two registers holding a 32-bit value comes first
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WordOrder {
+    LowFirst,
+    HighFirst,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ModbusConfiguration {
+    pub parity: Parity,
+    pub stop_bits: u8,      // 1 or 2
+    pub device_id: u8,      // Slave address the motor is expected to answer on
+    pub position_word_order: WordOrder,
+    pub position_signed: bool,  // Two's complement, otherwise the motor only knows positions from 0 up
+    pub de_re_active_low: bool, // Transceiver wired to send while DE/RE is low
+    pub de_re_control: DeReControl,
+    pub rx_buffer_size: usize,  // UART driver ring buffers in bytes, taken from the heap
+    pub tx_buffer_size: usize,  // 0 = none, writes wait for the hardware FIFO
+}
+
+impl Default for ModbusConfiguration {
+    fn default() -> Self {
+        Self {
+            parity: Parity::None,
+            stop_bits: 1,
+            device_id: 1,
+            position_word_order: WordOrder::LowFirst,
+            position_signed: true,
+            de_re_active_low: false,
+            de_re_control: DeReControl::Rts,
+            // A few frames' worth, so back-to-back replies aren't dropped while the motor task
+            // is held up. The driver's own default is 256.
+            rx_buffer_size: 1024,
+            tx_buffer_size: 512,
+        }
+    }
+}
+
+impl ModbusConfiguration {
+    // Short form like "8N1", always 8 data bits
+    pub fn framing(&self) -> String {
+        let parity = match self.parity {
+            Parity::None => 'N',
+            Parity::Even => 'E',
+            Parity::Odd => 'O',
+        };
+        format!("8{}{}", parity, self.stop_bits)
+    }
+}
+
+pub const MIN_UART_BUFFER: usize = 256;
+pub const MAX_UART_BUFFER: usize = 8192;
+
+// Keeps the motor task below the WiFi and lwIP tasks, starving those drops the connection
+pub const MAX_TASK_PRIORITY: u8 = 17;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MotorLoopConfiguration {
+    pub control_rate_hz: f32,
+    pub pause_after_brownout: bool,     // Start paused when the last reset was a brownout
+    pub task_priority: u8,              // FreeRTOS priority of the motor task
+    pub task_core: u8,                  // Core the motor task is pinned to, ignored on single-core chips
+    pub skip_homing: bool,              // Use the saved homed range instead of homing, for absolute encoders
+}
+
+impl Default for MotorLoopConfiguration {
+    fn default() -> Self {
+        Self {
+            control_rate_hz: 100.0,
+            pause_after_brownout: true,
+            task_priority: 10,  // Above the HTTP server (5), below lwIP (18) and WiFi (23)
+            task_core: 1,       // WiFi runs on core 0 of dual-core chips
+            skip_homing: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InterlockConfiguration {
+    pub enabled: bool,
+    pub pin: u32,
+    pub active_low: bool,   // Switch to GND, closed when the pin is low
+}
+
+impl Default for InterlockConfiguration {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            pin: 11,
+            active_low: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitSwitchConfiguration {
+    pub min_pin: Option<u32>,   // Switch at the end homing finds first, None = no switch there
+    pub max_pin: Option<u32>,
+    pub active_low: bool,       // Normally open switches to GND, triggered when the pin is low
+}
+
+impl Default for LimitSwitchConfiguration {
+    fn default() -> Self {
+        Self {
+            min_pin: None,
+            max_pin: None,
+            active_low: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HomingConfiguration {
+    pub stable_threshold: u32,  // Counts two reads 100 ms apart may differ by while the carriage counts as still
+    pub stable_reads: u32,      // Such reads in a row before an end counts as reached
+    pub approach_speed: u32,    // Counts per second towards each end, 0 = one push under the power limit
+    pub touch_speed: u32,       // Counts per second touching the end again after backing off
+    pub backoff: u32,           // Counts to back off before the slow touch
+    pub verify: bool,           // Find both ends a second time and compare, doubles the homing time
+    pub repeat_tolerance: u32,  // Counts the two passes may differ by before it warns
+    pub margin_min: u32,        // Counts kept clear of the min end, the homed range starts this far in
+    pub margin_max: u32,        // Likewise at the max end
+}
+
+impl Default for HomingConfiguration {
+    fn default() -> Self {
+        Self {
+            stable_threshold: 10,
+            stable_reads: 1,
+            approach_speed: 10000,
+            touch_speed: 2000,
+            backoff: 2000,
+            verify: false,
+            repeat_tolerance: 500,
+            margin_min: 3000,
+            margin_max: 3000,
+        }
+    }
+}
+
 impl StorageManager {
     pub fn new(nvs_partition: EspDefaultNvsPartition) -> Self {
         let nvs = EspNvs::new(nvs_partition, "ossm", true).unwrap();
@@ -32,64 +310,73 @@ impl StorageManager {
     }
 
     fn get_string(&self, key: &str) -> Result<String> {
-        let mut buf = vec![0u8; 1024];
-        let str_value = self.nvs.get_str(key, &mut buf).map_err(|e| anyhow::anyhow!("Failed to get string by key {}: {}", key, e))?;
+        let len = self.nvs.str_len(key).map_err(OssmError::Storage)?;
+        let mut buf = vec![0u8; len.unwrap_or(1)];
+        let str_value = self.nvs.get_str(key, &mut buf).map_err(OssmError::Storage)?;
         match str_value {
             Some(s) => {
                 Ok(s.to_string())
             }
             None => {
-                Err(anyhow::anyhow!("String value not found by key: {}", key))
+                Err(OssmError::NotFound(key.to_string()))
             }
         }
     }
 
+    // JSON is kept in a blob, NVS strings are capped at 4000 bytes which a config with a big
+    // spline can exceed. Values written by older firmware are strings and are still read.
     fn set_json<T: Serialize>(&mut self, key: &str, value: &T) -> Result<()> {
         let json = serde_json::to_string(value)?;
-        self.nvs.set_str(key, &json)?;
+        if let Ok(Some(_)) = self.nvs.str_len(key) {
+            // Drop a string saved by older firmware so it doesn't linger next to the blob
+            self.nvs.remove(key).map_err(OssmError::Storage)?;
+        }
+        self.nvs.set_blob(key, json.as_bytes()).map_err(OssmError::Storage)?;
         Ok(())
     }
 
     fn get_json<T: DeserializeOwned>(&self, key: &str) -> Result<T> {
-        let string = self.get_string(key)?;
-        serde_json::from_str(&string).map_err(|e| anyhow::anyhow!("Failed to get JSON by key {}: {}", key, e))
+        let string = match self.nvs.blob_len(key) {
+            Ok(Some(len)) => {
+                let mut buf = vec![0u8; len];
+                let bytes = self.nvs.get_blob(key, &mut buf).map_err(OssmError::Storage)?
+                    .ok_or_else(|| OssmError::NotFound(key.to_string()))?;
+                String::from_utf8(bytes.to_vec()).map_err(|_| OssmError::invalid(format!("Stored value for {} is not UTF-8", key)))?
+            }
+            _ => self.get_string(key)?,
+        };
+        Ok(serde_json::from_str(&string)?)
     }
 
     pub fn set_ssid(&mut self, ssid: &str) -> Result<()> {
-        self.nvs.set_str("ssid", ssid)?;
+        self.nvs.set_str("ssid", ssid).map_err(OssmError::Storage)?;
         Ok(())
     }
 
     pub fn get_ssid(&self) -> Result<String> {
         let mut buf = [0u8; 32];
-        self.nvs.get_str("ssid", &mut buf)?;
+        self.nvs.get_str("ssid", &mut buf).map_err(OssmError::Storage)?;
         let end = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
-        let ssid = core::str::from_utf8(&buf[..end]).map_err(|e| anyhow::anyhow!("Failed to get SSID: {}", e))?;
+        let ssid = core::str::from_utf8(&buf[..end]).map_err(|e| OssmError::invalid(format!("Failed to get SSID: {}", e)))?;
         Ok(ssid.to_string())
     }
 
     pub fn set_password(&mut self, password: &str) -> Result<()> {
-        self.nvs.set_str("password", password)?;
+        self.nvs.set_str("password", password).map_err(OssmError::Storage)?;
         Ok(())
     }
 
     pub fn get_password(&self) -> Result<String> {
         let mut buf = [0u8; 64];
-        self.nvs.get_str("password", &mut buf)?;
+        self.nvs.get_str("password", &mut buf).map_err(OssmError::Storage)?;
         let end = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
-        let password = core::str::from_utf8(&buf[..end]).map_err(|e| anyhow::anyhow!("Failed to get Password: {}", e))?;
+        let password = core::str::from_utf8(&buf[..end]).map_err(|e| OssmError::invalid(format!("Failed to get Password: {}", e)))?;
         Ok(password.to_string())
     }
 
     pub fn set_motor_config(&mut self, config: &MotorControllerConfig) -> Result<()> {
-        let config = {
-            let mut config = config.clone();
-            config.depth = config.depth.clamp(0.0, 1.0);
-            config.bpm = config.bpm.clamp(1.0, 500.0);
-            config.sharpness = config.sharpness.clamp(0.0, 1.0);
-            config.paused_position = config.paused_position.clamp(0.0, 1.0);
-            config
-        };
+        let mut config = config.clone();
+        config.clamp_to_ranges(&SpeedLimits::default())?;
 
         self.set_json("motor_config", &config)?;
         Ok(())
@@ -99,7 +386,263 @@ impl StorageManager {
         self.get_json("motor_config")
     }
 
+    pub fn set_speed_limits(&mut self, limits: &SpeedLimits) -> Result<()> {
+        if !(1.0..=500.0).contains(&limits.bpm_min) || !(1.0..=500.0).contains(&limits.bpm_max) {
+            return Err(OssmError::invalid("BPM limits must be between 1 and 500"));
+        }
+        if !(0.0..=1.0).contains(&limits.depth_factor) {
+            return Err(OssmError::invalid("Depth factor must be between 0 and 1"));
+        }
+        if limits.bpm_min > limits.bpm_max {
+            return Err(OssmError::invalid(format!("bpm_min ({}) is greater than bpm_max ({})", limits.bpm_min, limits.bpm_max)));
+        }
+        self.set_json("speed_limits", limits)?;
+        Ok(())
+    }
+
+    pub fn get_speed_limits(&self) -> Result<SpeedLimits> {
+        self.get_json("speed_limits")
+    }
+
+    pub fn set_jog_limits(&mut self, limits: &JogLimits) -> Result<()> {
+        if !(limits.step > 0.0 && limits.step <= 0.5) {
+            return Err(OssmError::invalid("The jog step must be above 0 and at most 0.5"));
+        }
+        if !(limits.max_step > 0.0 && limits.max_step <= 1.0) {
+            return Err(OssmError::invalid("The largest jog must be above 0 and at most 1"));
+        }
+        if !(limits.max_rate > 0.0 && limits.max_rate <= motion::PAUSE_SPEED) {
+            return Err(OssmError::invalid(format!("The jog rate must be above 0 and at most {} per second, the speed the carriage follows at", motion::PAUSE_SPEED)));
+        }
+        if !(limits.recenter_after >= 0.0 && limits.recenter_after <= 86400.0) {
+            return Err(OssmError::invalid("The recenter time must be 0-86400 seconds"));
+        }
+        if !(0.0..=1.0).contains(&limits.center) {
+            return Err(OssmError::invalid("The center position must be 0-1"));
+        }
+        self.set_json("jog_limits", limits)?;
+        Ok(())
+    }
+
+    pub fn get_jog_limits(&self) -> Result<JogLimits> {
+        self.get_json("jog_limits")
+    }
+
+    pub fn set_range_policy(&mut self, policy: RangePolicy) -> Result<()> {
+        self.set_json("range_policy", &policy)?;
+        Ok(())
+    }
+
+    pub fn get_range_policy(&self) -> Result<RangePolicy> {
+        self.get_json("range_policy")
+    }
+
+    // Flag to run the Modbus diagnosis on the next boot, the UART is only free before the motor starts
+    pub fn request_modbus_diagnosis(&mut self) -> Result<()> {
+        self.nvs.set_u8("diag_modbus", 1).map_err(OssmError::Storage)?;
+        Ok(())
+    }
+
+    // Returns whether a diagnosis was requested, clearing the request
+    pub fn take_modbus_diagnosis_request(&mut self) -> bool {
+        match self.nvs.get_u8("diag_modbus") {
+            Ok(Some(1)) => {
+                if let Err(e) = self.nvs.set_u8("diag_modbus", 0) {
+                    log::error!("Failed to clear Modbus diagnosis request: {}", e);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn set_modbus_configuration(&mut self, config: &ModbusConfiguration) -> Result<()> {
+        // Modbus RTU characters are 11 bits: parity and one stop bit, or two stop bits without
+        // parity. 8N1 isn't in the spec but is what most motors ship with.
+        match (config.parity, config.stop_bits) {
+            (_, 1) | (Parity::None, 2) => {}
+            _ => return Err(OssmError::invalid(format!("Unsupported Modbus framing: {}", config.framing()))),
+        }
+        if !(1..=247).contains(&config.device_id) {
+            return Err(OssmError::invalid(format!("Invalid Modbus device id: {}", config.device_id)));
+        }
+        // The ring buffers must be larger than the 128 byte hardware FIFO
+        if !(MIN_UART_BUFFER..=MAX_UART_BUFFER).contains(&config.rx_buffer_size) {
+            return Err(OssmError::invalid(format!("UART RX buffer must be {}-{} bytes", MIN_UART_BUFFER, MAX_UART_BUFFER)));
+        }
+        if config.tx_buffer_size != 0 && !(MIN_UART_BUFFER..=MAX_UART_BUFFER).contains(&config.tx_buffer_size) {
+            return Err(OssmError::invalid(format!("UART TX buffer must be 0 or {}-{} bytes", MIN_UART_BUFFER, MAX_UART_BUFFER)));
+        }
+        self.set_json("modbus_config", &config)?;
+        Ok(())
+    }
+
+    pub fn get_modbus_configuration(&self) -> Result<ModbusConfiguration> {
+        self.get_json("modbus_config")
+    }
+
+    // Device id to program into the motor on the next boot
+    pub fn request_device_id_change(&mut self, device_id: u8) -> Result<()> {
+        if !(1..=247).contains(&device_id) {
+            return Err(OssmError::invalid(format!("Invalid Modbus device id: {}", device_id)));
+        }
+        self.nvs.set_u8("new_device_id", device_id).map_err(OssmError::Storage)?;
+        Ok(())
+    }
+
+    // Returns the requested device id change, if any, clearing the request
+    pub fn take_device_id_change(&mut self) -> Option<u8> {
+        match self.nvs.get_u8("new_device_id") {
+            Ok(Some(device_id)) if device_id != 0 => {
+                if let Err(e) = self.nvs.set_u8("new_device_id", 0) {
+                    log::error!("Failed to clear device id change request: {}", e);
+                }
+                Some(device_id)
+            }
+            _ => None,
+        }
+    }
+
+    // Flag to start in safe mode on the next boot only
+    pub fn request_safe_mode(&mut self) -> Result<()> {
+        self.nvs.set_u8("safe_mode", 1).map_err(OssmError::Storage)?;
+        Ok(())
+    }
+
+    // Returns whether safe mode was requested, clearing the request
+    pub fn take_safe_mode_request(&mut self) -> bool {
+        match self.nvs.get_u8("safe_mode") {
+            Ok(Some(1)) => {
+                if let Err(e) = self.nvs.set_u8("safe_mode", 0) {
+                    log::error!("Failed to clear safe mode request: {}", e);
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Counts this boot, and the crash in a row if the last reset was one. A clean start clears the
+    // crash count. Returns (boot count, crashes in a row).
+    pub fn record_boot(&mut self, crashed: bool) -> Result<(u32, u8)> {
+        let boot_count = self.nvs.get_u32("boot_count").map_err(OssmError::Storage)?.unwrap_or(0).wrapping_add(1);
+        self.nvs.set_u32("boot_count", boot_count).map_err(OssmError::Storage)?;
+        let crash_count = match crashed {
+            true => self.nvs.get_u8("crash_count").map_err(OssmError::Storage)?.unwrap_or(0).saturating_add(1),
+            false => 0,
+        };
+        self.nvs.set_u8("crash_count", crash_count).map_err(OssmError::Storage)?;
+        Ok((boot_count, crash_count))
+    }
+
+    pub fn clear_crash_count(&mut self) -> Result<()> {
+        self.nvs.set_u8("crash_count", 0).map_err(OssmError::Storage)?;
+        Ok(())
+    }
+
+    pub fn set_motor_loop_configuration(&mut self, config: &MotorLoopConfiguration) -> Result<()> {
+        if !(1.0..=1000.0).contains(&config.control_rate_hz) {
+            return Err(OssmError::invalid("Control rate must be between 1 and 1000 Hz"));
+        }
+        if !(1..=MAX_TASK_PRIORITY).contains(&config.task_priority) {
+            return Err(OssmError::invalid(format!("Task priority must be between 1 and {}", MAX_TASK_PRIORITY)));
+        }
+        if config.task_core > 1 {
+            return Err(OssmError::invalid("Task core must be 0 or 1"));
+        }
+        self.set_json("motor_loop_cfg", &config)?;
+        Ok(())
+    }
+
+    pub fn get_motor_loop_configuration(&self) -> Result<MotorLoopConfiguration> {
+        self.get_json("motor_loop_cfg")
+    }
+
+    pub fn set_interlock_configuration(&mut self, config: &InterlockConfiguration) -> Result<()> {
+        self.set_json("interlock_cfg", &config)?;
+        Ok(())
+    }
+
+    pub fn get_interlock_configuration(&self) -> Result<InterlockConfiguration> {
+        self.get_json("interlock_cfg")
+    }
+
+    pub fn set_limit_switch_configuration(&mut self, config: &LimitSwitchConfiguration) -> Result<()> {
+        if config.min_pin.is_some() && config.min_pin == config.max_pin {
+            return Err(OssmError::invalid("The limit switches need a pin each"));
+        }
+        self.set_json("limit_sw_cfg", &config)?;
+        Ok(())
+    }
+
+    pub fn get_limit_switch_configuration(&self) -> Result<LimitSwitchConfiguration> {
+        self.get_json("limit_sw_cfg")
+    }
+
+    pub fn set_homing_configuration(&mut self, config: &HomingConfiguration) -> Result<()> {
+        if !(1..=10000).contains(&config.stable_threshold) {
+            return Err(OssmError::invalid("Stability threshold must be between 1 and 10000 counts"));
+        }
+        if !(1..=20).contains(&config.stable_reads) {
+            return Err(OssmError::invalid("Stable reads must be between 1 and 20"));
+        }
+        if config.approach_speed > 100000 {
+            return Err(OssmError::invalid("Approach speed must be at most 100000 counts per second"));
+        }
+        if config.approach_speed > 0 && !(1..=config.approach_speed).contains(&config.touch_speed) {
+            return Err(OssmError::invalid("Touch speed must be between 1 and the approach speed"));
+        }
+        if !(1..=50000).contains(&config.backoff) {
+            return Err(OssmError::invalid("Backoff must be between 1 and 50000 counts"));
+        }
+        if !(1..=100000).contains(&config.repeat_tolerance) {
+            return Err(OssmError::invalid("Repeat tolerance must be between 1 and 100000 counts"));
+        }
+        // Whether they fit the travel is only known once homing has found it
+        if config.margin_min > 100000 || config.margin_max > 100000 {
+            return Err(OssmError::invalid("Margins must be at most 100000 counts"));
+        }
+        self.set_json("homing_config", &config)?;
+        Ok(())
+    }
+
+    pub fn get_homing_configuration(&self) -> Result<HomingConfiguration> {
+        self.get_json("homing_config")
+    }
+
+    // Named spline patterns, kept apart from the motor config
+    pub fn get_splines(&self) -> Result<BTreeMap<String, Vec<f32>>> {
+        match self.nvs.contains("splines").map_err(OssmError::Storage)? {
+            true => self.get_json("splines"),
+            false => Ok(BTreeMap::new()),
+        }
+    }
+
+    pub fn save_spline(&mut self, name: &str, points: &[f32]) -> Result<()> {
+        if name.is_empty() || name.len() > 32 {
+            return Err(OssmError::invalid("Spline name must be 1 to 32 characters"));
+        }
+        if points.iter().any(|p| !(0.0..=1.0).contains(p)) {
+            return Err(OssmError::invalid("Spline points must be between 0 and 1"));
+        }
+        let mut splines = self.get_splines()?;
+        splines.insert(name.to_string(), points.to_vec());
+        self.set_json("splines", &splines)
+    }
+
+    // Returns whether a spline with that name existed
+    pub fn delete_spline(&mut self, name: &str) -> Result<bool> {
+        let mut splines = self.get_splines()?;
+        let existed = splines.remove(name).is_some();
+        if existed {
+            self.set_json("splines", &splines)?;
+        }
+        Ok(existed)
+    }
+
     pub fn set_pin_configuration(&mut self, config: &PinConfiguration) -> Result<()> {
+        pins::check_output_pin(config.modbus_tx, "Modbus TX")?;
+        pins::check_output_pin(config.modbus_de_re, "Modbus DE/RE")?;
         self.set_json("pin_configuration", &config)?;
         Ok(())
     }
@@ -107,4 +650,116 @@ impl StorageManager {
     pub fn get_pin_configuration(&self) -> Result<PinConfiguration> {
         self.get_json("pin_configuration")
     }
-}
\ No newline at end of file
+
+    pub fn set_homed_range(&mut self, range: &HomedRange) -> Result<()> {
+        if !range.is_plausible() {
+            return Err(OssmError::invalid(format!("Homed range [{}, {}] is too short", range.pos_min, range.pos_max)));
+        }
+        self.set_json("homed_range", range)?;
+        Ok(())
+    }
+
+    // A stored range that doesn't make sense is dropped, so the next start homes
+    pub fn get_homed_range(&mut self) -> Result<HomedRange> {
+        let range: HomedRange = self.get_json("homed_range")?;
+        if !range.is_plausible() {
+            log::warn!("Dropping the saved homed range [{}, {}], it is too short", range.pos_min, range.pos_max);
+            self.nvs.remove("homed_range").map_err(OssmError::Storage)?;
+            return Err(OssmError::NotFound("homed_range".to_string()));
+        }
+        Ok(range)
+    }
+
+    // Motor counts per mm of carriage travel, None clears it
+    pub fn set_counts_per_mm(&mut self, counts_per_mm: Option<f32>) -> Result<()> {
+        if let Some(counts_per_mm) = counts_per_mm {
+            if !counts_per_mm.is_finite() || counts_per_mm <= 0.0 {
+                return Err(OssmError::invalid(format!("Invalid counts per mm: {}", counts_per_mm)));
+            }
+        }
+        self.set_json("counts_per_mm", &counts_per_mm)?;
+        Ok(())
+    }
+
+    pub fn get_counts_per_mm(&self) -> Result<Option<f32>> {
+        self.get_json("counts_per_mm")
+    }
+
+    pub fn set_http_configuration(&mut self, config: &HttpConfiguration) -> Result<()> {
+        if config.port == 0 {
+            return Err(OssmError::invalid(format!("Invalid HTTP port: {}", config.port)));
+        }
+        if config.allowed_origin.is_empty() {
+            return Err(OssmError::invalid("Allowed origin cannot be empty"));
+        }
+        self.set_json("http_config", &config)?;
+        Ok(())
+    }
+
+    pub fn get_http_configuration(&self) -> Result<HttpConfiguration> {
+        self.get_json("http_config")
+    }
+
+    pub fn set_led_configuration(&mut self, config: &LedConfiguration) -> Result<()> {
+        if config.kind != LedKind::None {
+            pins::check_output_pin(config.pin, "status LED")?;
+        }
+        self.set_json("led_config", &config)?;
+        Ok(())
+    }
+
+    pub fn get_led_configuration(&self) -> Result<LedConfiguration> {
+        self.get_json("led_config")
+    }
+
+    pub fn set_buzzer_configuration(&mut self, config: &BuzzerConfiguration) -> Result<()> {
+        if config.enabled {
+            pins::check_output_pin(config.pin, "buzzer")?;
+        }
+        self.set_json("buzzer_config", &config)?;
+        Ok(())
+    }
+
+    pub fn get_buzzer_configuration(&self) -> Result<BuzzerConfiguration> {
+        self.get_json("buzzer_config")
+    }
+
+    pub fn set_mqtt_configuration(&mut self, config: &MqttConfiguration) -> Result<()> {
+        if !config.url.is_empty() && !(config.url.starts_with("mqtt://") || config.url.starts_with("mqtts://")) {
+            return Err(OssmError::invalid("MQTT URL must start with mqtt:// or mqtts://"));
+        }
+        if config.topic_prefix.is_empty() || config.topic_prefix.contains(['#', '+']) {
+            return Err(OssmError::invalid("Topic prefix must not be empty or contain wildcards"));
+        }
+        if !(100..=3_600_000).contains(&config.interval_ms) {
+            return Err(OssmError::invalid("Publish interval must be between 100 ms and 1 hour"));
+        }
+        self.set_json("mqtt_config", &config)?;
+        Ok(())
+    }
+
+    pub fn get_mqtt_configuration(&self) -> Result<MqttConfiguration> {
+        self.get_json("mqtt_config")
+    }
+
+    pub fn set_time_configuration(&mut self, config: &TimeConfiguration) -> Result<()> {
+        if config.timezone.is_empty() || config.timezone.contains(char::is_whitespace) {
+            return Err(OssmError::invalid("Timezone must be a POSIX TZ string without spaces"));
+        }
+        self.set_json("time_config", &config)?;
+        Ok(())
+    }
+
+    pub fn get_time_configuration(&self) -> Result<TimeConfiguration> {
+        self.get_json("time_config")
+    }
+
+    pub fn set_schedule(&mut self, schedule: Option<&Schedule>) -> Result<()> {
+        self.set_json("schedule", &schedule)?;
+        Ok(())
+    }
+
+    pub fn get_schedule(&self) -> Result<Option<Schedule>> {
+        self.get_json("schedule")
+    }
+}
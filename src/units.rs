@@ -0,0 +1,70 @@
+use crate::error::{OssmError, Result};
+
+// How positions are given and reported at the API boundary. The controller itself works in
+// fractions of the homed stroke.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Units {
+    #[default]
+    Fraction,   // 0 to 1 of the homed stroke
+    Counts,     // Motor position
+    Mm,         // From the homed minimum, needs the counts_per_mm calibration
+}
+
+impl Units {
+    pub fn parse(text: &str) -> Result<Self> {
+        match text {
+            "fraction" => Ok(Units::Fraction),
+            "counts" => Ok(Units::Counts),
+            "mm" => Ok(Units::Mm),
+            _ => Err(OssmError::invalid(format!("Unknown units \"{}\", use fraction, counts or mm", text))),
+        }
+    }
+}
+
+// Converts between units over one homed range, so every endpoint does it the same way
+pub struct UnitConverter {
+    pos_min: i32,
+    pos_max: i32,
+    counts_per_mm: Option<f32>,
+}
+
+impl UnitConverter {
+    pub fn new(pos_min: i32, pos_max: i32, counts_per_mm: Option<f32>) -> Self {
+        Self { pos_min, pos_max, counts_per_mm }
+    }
+
+    fn counts_per_unit(&self, units: Units) -> Result<f32> {
+        match units {
+            Units::Fraction => Ok((self.pos_max - self.pos_min) as f32),
+            Units::Counts => Ok(1.0),
+            Units::Mm => self.counts_per_mm.ok_or_else(|| OssmError::invalid("mm need the counts_per_mm calibration, see set_counts_per_mm")),
+        }
+    }
+
+    // Counts are absolute motor positions, the other units start at the homed minimum
+    fn offset(&self, units: Units) -> f32 {
+        if units == Units::Counts { self.pos_min as f32 } else { 0.0 }
+    }
+
+    // A distance along the stroke, e.g. a relative adjustment
+    pub fn length_to_fraction(&self, value: f32, units: Units) -> Result<f32> {
+        let span = (self.pos_max - self.pos_min) as f32;
+        if span <= 0.0 {
+            return Err(OssmError::invalid("The motor is not homed yet"));
+        }
+        Ok(value * self.counts_per_unit(units)? / span)
+    }
+
+    pub fn length_from_fraction(&self, fraction: f32, units: Units) -> Result<f32> {
+        let span = (self.pos_max - self.pos_min) as f32;
+        Ok(fraction * span / self.counts_per_unit(units)?)
+    }
+
+    pub fn position_to_fraction(&self, value: f32, units: Units) -> Result<f32> {
+        self.length_to_fraction(value - self.offset(units), units)
+    }
+
+    pub fn position_from_fraction(&self, fraction: f32, units: Units) -> Result<f32> {
+        Ok(self.length_from_fraction(fraction, units)? + self.offset(units))
+    }
+}